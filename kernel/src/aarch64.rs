@@ -1,10 +1,6 @@
 use core::fmt;
 
-pub fn flush_user_tlb() {
-    flush_tlb_asid(1);
-    flush_tlb_asid(0);
-    //flush_tlb_all();
-}
+use pi::common::CACHE_LINE;
 
 pub fn flush_tlb_all() {
     unsafe {
@@ -29,6 +25,25 @@ pub fn flush_tlb_asid(asid: u16) {
     }
 }
 
+/// Makes the instructions written to the `len` bytes at `addr` visible to
+/// the instruction fetches of every core: cleans the data cache lines to the
+/// point of unification and invalidates the instruction caches.
+pub fn sync_icache(addr: usize, len: usize) {
+    let mut line = addr & !(CACHE_LINE - 1);
+    while line < addr + len {
+        unsafe { asm!("dc cvau, $0" :: "r"(line) :: "volatile"); }
+        line += CACHE_LINE;
+    }
+    unsafe {
+        asm!("
+            dsb     ish
+            ic      ialluis
+            dsb     ish
+            isb
+            " :::: "volatile");
+    }
+}
+
 #[inline(always)]
 pub fn far_el1() -> u64 {
     let r: u64;
@@ -198,6 +213,14 @@ impl MMFR {
         (self.raw[0] >> 24) & 0b1111 == 0b0000
     }
 
+    /// The number of bits of an ASID: 8 or 16.
+    pub fn asid_bits(&self) -> u32 {
+        match (self.raw[0] >> 4) & 0b1111 {
+            0b0010 => 16,
+            _ => 8,
+        }
+    }
+
     pub fn physical_address_range(&self) -> PhysicalAddressRange {
         match self.raw[0] & 0b1111 {
             0b0000 => PhysicalAddressRange::R32,
@@ -8,13 +8,33 @@ use allocator::linked_list::LinkedList;
 const BIN_COUNT: usize = 32;
 const END_ALIGN: usize = 8;
 
+/// Usage statistics of the allocator.
+#[derive(Default, Debug, Clone, Copy)]
+pub struct Stats {
+    /// Size of the heap in bytes.
+    pub heap_size: usize,
+    /// Bytes of the heap carved out so far, whether in use or kept in bins.
+    pub heap_used: usize,
+    /// Bytes currently handed out, rounded up to the size of their bins.
+    pub in_use: usize,
+    /// Number of successful allocations.
+    pub allocs: usize,
+    /// Number of deallocations.
+    pub deallocs: usize,
+    /// Number of allocations that failed.
+    pub failures: usize,
+}
+
 /// A simple allocator that allocates based on size classes.
 #[derive(Debug)]
 pub struct Allocator {
     bin: [LinkedList; BIN_COUNT],
 
+    start: usize,
     current: usize,
     end: usize,
+
+    stats: Stats,
 }
 
 impl Allocator {
@@ -23,8 +43,19 @@ impl Allocator {
     pub fn new(start: usize, end: usize) -> Self {
         Self {
             bin: [LinkedList::new(); BIN_COUNT],
+            start,
             current: start,
             end,
+            stats: Stats::default(),
+        }
+    }
+
+    /// Returns the usage statistics of this allocator.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            heap_size: self.end - self.start,
+            heap_used: self.current - self.start,
+            ..self.stats
         }
     }
 
@@ -40,6 +71,10 @@ impl Allocator {
     }
 }
 
+fn bin_size(bin: usize) -> usize {
+    1 << (bin + 3)
+}
+
 fn bin_from_layout(layout: Layout) -> usize {
     align_up(layout.size(), layout.align())
         .next_power_of_two()
@@ -70,6 +105,7 @@ unsafe impl<'a> Alloc for Allocator {
     unsafe fn alloc(&mut self, layout: Layout) -> Result<NonNull<Opaque>, AllocErr> {
         let bin = bin_from_layout(layout);
         if bin >= BIN_COUNT {
+            self.stats.failures += 1;
             return Err(AllocErr);
         }
 
@@ -77,10 +113,13 @@ unsafe impl<'a> Alloc for Allocator {
             align_up(addr as usize, layout.align())
         } else {
             let start = align_up(self.current, layout.align());
-            let end = align_up(start + layout.size(), END_ALIGN);
+            // carve out the whole bin, the block is reused for any layout of
+            // this bin after it is deallocated
+            let end = align_up(start + bin_size(bin), END_ALIGN);
 
             if end >= self.end {
                 ::console::kprintln!("alloc {:x}-{:x} @end: {:x}", start, end, self.end);
+                self.stats.failures += 1;
                 return Err(AllocErr);
             }
 
@@ -90,6 +129,9 @@ unsafe impl<'a> Alloc for Allocator {
             start
         };
 
+        self.stats.allocs += 1;
+        self.stats.in_use += bin_size(bin);
+
         Ok(NonNull::new_unchecked(addr as *mut u8).as_opaque())
     }
 
@@ -109,5 +151,8 @@ unsafe impl<'a> Alloc for Allocator {
     unsafe fn dealloc(&mut self, ptr: NonNull<Opaque>, layout: Layout) {
         let bin = bin_from_layout(layout);
         self.bin[bin].push(ptr.as_ptr() as *mut usize);
+
+        self.stats.deallocs += 1;
+        self.stats.in_use -= bin_size(bin);
     }
 }
@@ -14,6 +14,7 @@ use core::ptr::NonNull;
 use core::mem;
 
 pub use core::alloc::AllocErr;
+pub use self::imp::Stats;
 
 /// Thread-safe (locking) wrapper around a particular memory allocator.
 #[derive(Debug)]
@@ -39,6 +40,11 @@ impl Allocator {
         *self.0.lock().unwrap() = Some(heap);
     }
 
+    /// Returns the usage statistics of the allocator.
+    pub fn stats(&self) -> Stats {
+        self.0.lock().unwrap().as_ref().expect("allocator uninitialized").stats()
+    }
+
     /*
     pub fn remap(&self, mask: usize) {
         self.0.lock().unwrap().as_mut().expect("allocator uninitialized").remap(mask)
@@ -223,6 +223,20 @@ mod allocator {
         }
     });
 
+    test_allocators!(@bin, bin_reuse_largest, 4096, |(_, _, mut a)| {
+        // a block freed by a small layout is reused for the largest layout of
+        // its bin: it must not overlap the block carved after it
+        let small = a.alloc(layout!(17, 1)).expect("allocation");
+        let next = a.alloc(layout!(16, 8)).expect("allocation");
+        scribble(next, 16);
+        a.dealloc(small, layout!(17, 1));
+
+        let large = a.alloc(layout!(32, 1)).expect("allocation");
+        unsafe { ::std::ptr::write_bytes(large.cast().as_ptr() as *mut u8, 0x00, 32); }
+        let next = unsafe { ::std::slice::from_raw_parts(next.cast().as_ptr() as *const u8, 16) };
+        assert!(next.iter().all(|&b| b == 0xAF), "reused block overlaps the next one");
+    });
+
     test_allocators!(@bin, bin_dealloc_2, 8192, |(_, _, mut a)| {
         let layouts = [
             layout!(3072, 16),
@@ -1,18 +1,31 @@
 //use std::io;
-use core::fmt;
+use core::{fmt, mem};
 
 use pi::uart::MiniUart;
 use sys::Mutex;
+use process::WaitQueue;
+
+/// The number of received bytes the console buffers.
+const INPUT_SIZE: usize = 256;
+
+/// The byte received for Ctrl-C.
+const CTRL_C: u8 = 0x03;
 
 /// A global singleton allowing read/write access to the console.
 pub struct Console {
-    inner: Option<MiniUart>
+    inner: Option<MiniUart>,
+    /// Bytes received from the UART device, not read yet.
+    input: [u8; INPUT_SIZE],
+    head: usize,
+    len: usize,
+    /// Set when Ctrl-C was received.
+    interrupted: bool,
 }
 
 impl Console {
     /// Creates a new instance of `Console`.
     const fn new() -> Self {
-        Self { inner: None }
+        Self { inner: None, input: [0; INPUT_SIZE], head: 0, len: 0, interrupted: false }
     }
 
     /// Initializes the console if it's not already initialized.
@@ -33,8 +46,45 @@ impl Console {
         }
     }
 
+    /// Enables the UART receive interrupt. It stays raised until `receive`
+    /// takes the received bytes.
+    pub fn enable_interrupts(&mut self) {
+        self.inner().enable_rx_interrupt()
+    }
+
+    /// Moves the bytes received by the UART device to the input buffer,
+    /// dropping them once it is full. Returns `true` if any byte was received.
+    /// Ctrl-C is not buffered, but reported by `take_interrupt`.
+    pub fn receive(&mut self) -> bool {
+        let mut received = false;
+        while self.inner().has_byte() {
+            let byte = self.inner().read_byte();
+            if byte == CTRL_C {
+                self.interrupted = true;
+                continue;
+            }
+            if self.len < INPUT_SIZE {
+                self.input[(self.head + self.len) % INPUT_SIZE] = byte;
+                self.len += 1;
+            }
+            received = true;
+        }
+        received
+    }
+
+    /// Returns `true` if Ctrl-C was received since the last call.
+    pub fn take_interrupt(&mut self) -> bool {
+        mem::replace(&mut self.interrupted, false)
+    }
+
     /// Reads a byte from the UART device, blocking until a byte is available.
     pub fn read_byte(&mut self) -> u8 {
+        if self.len > 0 {
+            let byte = self.input[self.head];
+            self.head = (self.head + 1) % INPUT_SIZE;
+            self.len -= 1;
+            return byte;
+        }
         self.inner().read_byte()
     }
 
@@ -44,7 +94,7 @@ impl Console {
     }
 
     pub fn has_byte(&mut self) -> bool {
-        self.inner().has_byte()
+        self.len > 0 || self.inner().has_byte()
     }
 
     pub fn write_buf(&mut self, buf: &[u8]) {
@@ -52,7 +102,15 @@ impl Console {
     }
 
     pub fn read_buf(&mut self, buf: &mut [u8]) -> Option<usize> {
-        self.inner().read_buf(buf)
+        let mut count = 0;
+        for b in buf.iter_mut() {
+            if count > 0 && !self.has_byte() {
+                break;
+            }
+            *b = self.read_byte();
+            count += 1;
+        }
+        Some(count)
     }
 }
 
@@ -83,6 +141,9 @@ impl fmt::Write for Console {
 /// Global `Console` singleton.
 pub static CONSOLE: Mutex<Console> = Mutex::new(Console::new());
 
+/// Woken when the console receives input.
+pub static INPUT: WaitQueue = WaitQueue::new();
+
 /// Internal function called by the `kprint[ln]!` macros.
 #[doc(hidden)]
 
@@ -1,18 +1,46 @@
-#![allow(unused_imports)]
-use core::slice::from_raw_parts;
+use core::cmp::min;
 use core::mem::size_of;
-use alloc::Vec;
-use alloc::string::ToString;
-use vfat::traits::File;
+use core::ptr;
+use core::slice::from_raw_parts_mut;
+use alloc::vec::Vec;
 use sys::io;
-use sys::VecExt;
+use pi::rng::Rng;
+use aarch64;
 
-use vm::{self, VirtualAddr};
+use allocator::util::{align_up, align_down};
+use process::{Process, STACK_TOP};
+use vm::{self, Memory, VirtualAddr, Prot, PAGESZ};
 
 const SIZEOF_IDENT: usize = 16;
 
+// Values for e_ident
+pub const ELFCLASS64: u8 = 2;
+pub const ELFDATA2LSB: u8 = 1;
+pub const EV_CURRENT: u8 = 1;
+
+// Values for e_type and e_machine
+pub const ET_EXEC: u16 = 2;
+pub const EM_AARCH64: u16 = 183;
+
 // Values for Proghdr type
 pub const PROG_LOAD: u32 = 1;
+pub const PROG_PHDR: u32 = 6;
+
+// Auxiliary vector types
+pub const AT_NULL: u64 = 0;
+pub const AT_PHDR: u64 = 3;
+pub const AT_PHENT: u64 = 4;
+pub const AT_PHNUM: u64 = 5;
+pub const AT_PAGESZ: u64 = 6;
+pub const AT_ENTRY: u64 = 9;
+pub const AT_RANDOM: u64 = 25;
+
+/// The maximum size of the arguments, environment and auxiliary vector of a
+/// program.
+pub const ARG_MAX: usize = 16 * PAGESZ;
+
+/// The number of random bytes `AT_RANDOM` points to.
+const RANDOM_SIZE: usize = 16;
 
 bitflags! {
     pub struct ProgramFlags: u32 {
@@ -44,13 +72,25 @@ pub struct Header {
 impl Header {
     pub const SIZEOF: usize = size_of::<Self>();
 
-    pub fn from_bytes(bytes: &[u8; Self::SIZEOF]) -> &Self {
-        unsafe { &*(bytes.as_ptr() as *const Self) }
+    /// Reads a header from `r`.
+    pub fn read<R: io::Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        let mut bytes = [0u8; Self::SIZEOF];
+        r.read_exact(&mut bytes)?;
+        Ok(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const Self) })
     }
 
+    /// Returns `true` if this is the header of a 64-bit little endian ELF
+    /// file.
     pub fn check_magic(&self) -> bool {
-        //unimplemented!("elf::Header::check_magic")
-        true
+        &self.e_ident[..4] == b"\x7FELF" &&
+        self.e_ident[4] == ELFCLASS64 &&
+        self.e_ident[5] == ELFDATA2LSB &&
+        self.e_ident[6] == EV_CURRENT
+    }
+
+    /// Returns `true` if the file is an executable for this machine.
+    pub fn is_executable(&self) -> bool {
+        self.e_type == ET_EXEC && self.e_machine == EM_AARCH64
     }
 }
 
@@ -70,19 +110,12 @@ pub struct ProgramHeader {
 impl ProgramHeader {
     pub const SIZEOF: usize = size_of::<Self>();
 
-    pub fn from_bytes(bytes: &[u8; Self::SIZEOF]) -> &Self {
-        unsafe { &*(bytes.as_ptr() as *const Self) }
-    }
-
-    /*
-    pub fn from_bytes(bytes: &[u8]) -> &[Self] {
-        let len = bytes.len() & Self::SIZEOF;
-        let ptr = bytes.as_ptr() as *const Self;
-        unsafe {
-            from_raw_parts(ptr, len)
-        }
+    /// Reads a program header from `r`.
+    pub fn read<R: io::Read + ?Sized>(r: &mut R) -> io::Result<Self> {
+        let mut bytes = [0u8; Self::SIZEOF];
+        r.read_exact(&mut bytes)?;
+        Ok(unsafe { ptr::read_unaligned(bytes.as_ptr() as *const Self) })
     }
-    */
 
     pub fn is_loadable(&self) -> bool {
         self.p_type == PROG_LOAD
@@ -102,81 +135,176 @@ impl ProgramHeader {
         self.flags().contains(ProgramFlags::X)
     }
 
-    pub fn check_vaddr(&self, align: usize) -> bool {
-        (self.p_vaddr % align as u64) != 0 &&
-        self.p_vaddr + self.p_memsz < self.p_vaddr
+    /// Returns the access rights of the segment. Segments are always
+    /// readable.
+    pub fn prot(&self) -> Prot {
+        let mut prot = Prot::READ;
+        if self.is_write() {
+            prot |= Prot::WRITE;
+        }
+        if self.is_executable() {
+            prot |= Prot::EXEC;
+        }
+        prot
     }
 
-    /*
-    pub fn area(&self) -> vm::Area {
-        let start = self.p_vaddr;
-        let end = self.p_vaddr + self.p_memsz;
-        vm::Area {
-            start: VirtualAddr::from(start as *mut u8),
-            end: VirtualAddr::from(end as *mut u8),
-            readable: self.is_read(),
-            writable: self.is_write(),
-            executable: self.is_executable(),
+    /// Returns the area the segment occupies in memory, or `None` if the
+    /// segment does not fit into user space.
+    pub fn area(&self) -> Option<vm::Area> {
+        let end = self.p_vaddr.checked_add(self.p_memsz)?;
+        if self.p_filesz > self.p_memsz || end > vm::LOWER_SPACE_MASK as u64 {
+            return None;
         }
+        Some(vm::Area::new(self.p_vaddr as usize, end as usize).prot(self.prot()))
     }
-    */
+}
 
-    /*
-    pub fn file_range(&self) -> (usize, usize) {
-    }
-    pub fn vm_range(&self) -> (usize, usize) {
-    }
-    */
+fn invalid(msg: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
 }
 
-/*
+fn out_of_memory() -> io::Error {
+    io::Error::new(io::ErrorKind::Other, "out of memory")
+}
 
-pub fn spawn<F: File>(file: &mut F, aux: &[u8]) -> io::Result<()> {
-    //start addr: 0x80000
+/// Loads the executable `file` into the address space of a new process and
+/// returns the process, ready to start at the entry point of the program
+/// with the arguments `args` and the environment `env` on its stack.
+///
+/// The program starts with `sp` pointing to `argc`, followed by the `argv`
+/// and `envp` arrays, each terminated by a null pointer, and the auxiliary
+/// vector, terminated by `AT_NULL`. For programs without a runtime, `x0`,
+/// `x1` and `x2` hold `argc`, `argv` and `envp` as well.
+pub fn spawn<F>(file: &mut F, args: &[&str], env: &[&str]) -> io::Result<Process>
+    where F: io::Read + io::Seek + ?Sized
+{
+    let header = Header::read(file)?;
+    if !header.check_magic() {
+        return Err(invalid("spawn: bad magic"));
+    }
+    if !header.is_executable() || header.e_phentsize as usize != ProgramHeader::SIZEOF {
+        return Err(invalid("spawn: not an executable"));
+    }
 
-    let mut header = [0u8; Header::SIZEOF];
-    file.read_exact(&mut header)?;
-    let header = Header::from_bytes(&header);
+    let mut phdrs: Vec<ProgramHeader> = Vec::new();
+    phdrs.try_reserve(header.e_phnum as usize).map_err(|_| out_of_memory())?;
+    file.seek(io::SeekFrom::Start(header.e_phoff))?;
+    for _ in 0..header.e_phnum {
+        phdrs.push(ProgramHeader::read(file)?);
+    }
 
+    let mut process = Process::new().ok_or_else(out_of_memory)?;
 
-    if !header.check_magic() {
-        return Err(io::Error::new(io::ErrorKind::InvalidData, "spawn: bad magic"))
+    for ph in phdrs.iter().filter(|ph| ph.is_loadable()) {
+        let area = ph.area().ok_or_else(|| invalid("spawn: bad segment"))?;
+        process.mm().area(area).ok_or_else(|| invalid("spawn: overlapping segment"))?;
+        load(&mut process.mm(), file, ph)?;
     }
 
-    let _ = file.seek(io::SeekFrom::Start(header.e_phoff))?;
-    let mut data: Vec<u8> = vec![0; ProgramHeader::SIZEOF * header.e_phnum as usize];
-    file.read_exact(&mut data)?;
+    let entry = VirtualAddr::from(header.e_entry as *mut u8);
+    let executable = process.mm().find_area(entry)
+        .map_or(false, |area| area.protection().contains(Prot::EXEC));
+    if !executable || header.e_entry % 4 != 0 {
+        return Err(invalid("spawn: bad entry point"));
+    }
+    process.trap_frame.elr = header.e_entry;
 
-    let phdrs: Vec<ProgramHeader> = unsafe { data.cast() };
+    let phdr = phdrs.iter()
+        .find(|ph| ph.p_type == PROG_PHDR)
+        .map(|ph| ph.p_vaddr)
+        .or_else(|| phdrs.iter()
+            .filter(|ph| ph.is_loadable())
+            .find(|ph| ph.p_offset <= header.e_phoff && header.e_phoff - ph.p_offset < ph.p_filesz)
+            .map(|ph| ph.p_vaddr + (header.e_phoff - ph.p_offset)))
+        .unwrap_or(0);
 
-    let vm = vm::Memory::with_capacity(phdrs.len());
+    let auxv = [
+        (AT_PHDR, phdr),
+        (AT_PHENT, ProgramHeader::SIZEOF as u64),
+        (AT_PHNUM, header.e_phnum as u64),
+        (AT_PAGESZ, PAGESZ as u64),
+        (AT_ENTRY, header.e_entry),
+    ];
+    let sp = initial_stack(&mut process.mm(), STACK_TOP, args, env, &auxv)?;
 
-    for ph in phdrs {
-        if !ph.is_loadable() {
-            continue;
-        }
-        if ph.check_vaddr(PAGESZ) && ph.p_memsz < ph.p_filesz {
-            return Err(io::Error::new(io::ErrorKind::InvalidData, "spawn: fail mem attributes"))
-        }
+    process.trap_frame.sp = sp as u64;
+    process.trap_frame.x0 = args.len() as u64;
+    process.trap_frame.x1 = (sp + 8) as u64;
+    process.trap_frame.x2 = (sp + 8 * (args.len() + 2)) as u64;
 
-        /*
-        vm.add(vm::Area {
-            start: VirtualAddr::from(ph.p_vaddr as *mut u8),
-            end: VirtualAddr::from((ph.p_vaddr + ph.p_memsz) as *mut u8),
-            readable: ph.is_readable(),
-            writable: ph.is_writable(),
-            executable: ph.is_executable(),
-        });
-        */
+    Ok(process)
+}
 
+/// Writes the initial stack of a program below `top` and returns the stack
+/// pointer. `AT_RANDOM` is appended to `auxv`.
+fn initial_stack(mm: &mut Memory, top: usize, args: &[&str], env: &[&str], auxv: &[(u64, u64)]) -> io::Result<usize> {
+    let strings = args.iter().chain(env).map(|s| s.len() + 1).sum::<usize>() + RANDOM_SIZE;
+    let words = 1 + (args.len() + 1) + (env.len() + 1) + 2 * (auxv.len() + 2);
 
-        /*
-        if((sz = allocuvm(pgdir, sz, ph.vaddr + ph.memsz)) == 0) goto bad;
-        if(ph.vaddr % PGSIZE != 0) goto bad;
-        if(loaduvm(pgdir, (char*)ph.vaddr, ip, ph.off, ph.filesz) < 0)
-        */
+    let size = align_up(strings, 16) + align_up(words * 8, 16);
+    if size > ARG_MAX {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "spawn: argument list too long"));
     }
+    let sp = top - size;
+    let strings_start = top - align_up(strings, 16);
+
+    let mut image: Vec<u64> = Vec::new();
+    image.try_reserve(size / 8).map_err(|_| out_of_memory())?;
+    image.resize(size / 8, 0);
 
+    let mut word = 0;
+    let mut cursor = strings_start;
+    image[word] = args.len() as u64;
+    word += 1;
+    for list in [args, env].iter() {
+        for s in list.iter() {
+            image[word] = cursor as u64;
+            word += 1;
+            cursor += s.len() + 1;
+        }
+        // null terminated
+        word += 1;
+    }
+    let random = [(AT_RANDOM, cursor as u64)];
+    for &(key, value) in auxv.iter().chain(random.iter()) {
+        image[word] = key;
+        image[word + 1] = value;
+        word += 2;
+    }
+    // the last pair is AT_NULL
+    debug_assert_eq!(AT_NULL, image[word]);
+
+    let bytes = unsafe { from_raw_parts_mut(image.as_mut_ptr() as *mut u8, size) };
+    let mut offset = strings_start - sp;
+    for s in args.iter().chain(env) {
+        bytes[offset..offset + s.len()].copy_from_slice(s.as_bytes());
+        offset += s.len() + 1;
+    }
+    Rng::new().fill(&mut bytes[offset..offset + RANDOM_SIZE]);
+
+    mm.copy_to(sp, bytes).ok_or_else(out_of_memory)?;
+    Ok(sp)
+}
+
+/// Backs the segment `ph` with zeroed pages and copies its contents from
+/// `file`. The rest of the segment stays zero-filled.
+fn load<F: io::Read + io::Seek + ?Sized>(mm: &mut Memory, file: &mut F, ph: &ProgramHeader) -> io::Result<()> {
+    let start = ph.p_vaddr as usize;
+    let end = start + ph.p_filesz as usize;
+    mm.populate(start, ph.p_memsz as usize).ok_or_else(out_of_memory)?;
+
+    file.seek(io::SeekFrom::Start(ph.p_offset))?;
+
+    let mut addr = start;
+    while addr < end {
+        let len = min(end, align_down(addr, PAGESZ) + PAGESZ) - addr;
+        let p = mm.translate(VirtualAddr::from(addr as *mut u8)).expect("populated page");
+        let buf = unsafe { from_raw_parts_mut(vm::p2v(p).as_mut_ptr(), len) };
+        file.read_exact(buf)?;
+        if ph.is_executable() {
+            aarch64::sync_icache(buf.as_ptr() as usize, len);
+        }
+        addr += len;
+    }
     Ok(())
 }
-*/
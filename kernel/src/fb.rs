@@ -28,6 +28,10 @@ pub struct FrameBuffer {
     next: bool,
 }
 
+// The buffer is memory reserved by the GPU for the lifetime of the system,
+// not borrowed from any thread.
+unsafe impl Send for FrameBuffer {}
+
 impl FrameBuffer {
     pub fn new(width: u32, height: u32, depth: u32) -> Option<Self> {
         let info = FrameBufferInfo {
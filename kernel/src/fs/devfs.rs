@@ -0,0 +1,627 @@
+use core::cmp::min;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use sys::io;
+use sys::sync::Mutex;
+use pi::gpio::{Gpio, Function, Pud};
+use pi::common::clean_invalidate_dcache;
+use pi::mbox::{Mailbox, Tag};
+use pi::rng::Rng;
+use vfat::MasterBootRecord;
+use vfat::traits::BlockDevice;
+use vfat::vfat::Shared;
+
+use console::{self, CONSOLE};
+use fb::FrameBuffer;
+use fs::sd::{SharedSd, BLOCK_SIZE};
+use fs::sysfs::Listing;
+use fs::vfs::{self, Node, Stat, Kind, DirEntry};
+use process::WaitQueue;
+
+/// Control requests understood by the devices in `/dev`, passed to
+/// `vfs::File::ioctl`.
+pub mod ioctl {
+    /// Serial devices: returns `1` if a byte can be read without blocking.
+    pub const SERIAL_AVAILABLE: u64 = 0x0100;
+
+    /// GPIO pins: returns the selected function, as `pi::gpio::Function`.
+    pub const GPIO_GET_FUNCTION: u64 = 0x0200;
+    /// GPIO pins: selects the function `arg`, as `pi::gpio::Function`.
+    pub const GPIO_SET_FUNCTION: u64 = 0x0201;
+    /// GPIO pins: sets the pull-up/down state `arg`, as `pi::gpio::Pud`.
+    pub const GPIO_SET_PULL: u64 = 0x0202;
+
+    /// Frame buffers: returns the width in pixels.
+    pub const FB_WIDTH: u64 = 0x0300;
+    /// Frame buffers: returns the height in pixels.
+    pub const FB_HEIGHT: u64 = 0x0301;
+    /// Frame buffers: returns the number of bytes per row.
+    pub const FB_PITCH: u64 = 0x0302;
+    /// Frame buffers: fills the screen with the 32-bit color `arg`.
+    pub const FB_FILL: u64 = 0x0303;
+
+    /// Block devices: returns the size of a sector in bytes.
+    pub const BLK_SECTOR_SIZE: u64 = 0x0400;
+    /// Block devices: returns the number of sectors.
+    pub const BLK_SECTORS: u64 = 0x0401;
+    /// Block devices: returns the first sector on the disk, `0` for disks.
+    pub const BLK_START: u64 = 0x0402;
+}
+
+/// Character devices in the root of `/dev`.
+const CHAR_DEVICES: &[(&str, Device)] = &[
+    ("console", Device::Console),
+    ("uart0", Device::Uart),
+    ("fb0", Device::Fb),
+    ("null", Device::Null),
+    ("zero", Device::Zero),
+    ("random", Device::Random),
+];
+
+/// The number of GPIO pins.
+const GPIO_PINS: u8 = 54;
+
+/// The size of the frame buffer allocated when no display reports its size.
+const FB_DEFAULT_MODE: (u32, u32) = (640, 480);
+
+/// The kind of a character device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Device {
+    Console,
+    Uart,
+    Gpio(u8),
+    Fb,
+    Null,
+    Zero,
+    Random,
+}
+
+/// A block device: the whole SD card or one of its partitions.
+#[derive(Debug)]
+struct Disk {
+    name: String,
+    start: u64,
+    sectors: u64,
+}
+
+/// A virtual file system exposing devices, usually mounted at `/dev`.
+///
+/// ```text
+/// /dev/console     the serial console; translates line endings
+/// /dev/uart0       the mini UART, byte for byte
+/// /dev/gpio/<n>    GPIO pin n; reads and writes b'0' or b'1'
+/// /dev/fb0         the frame buffer, allocated when first opened
+/// /dev/null        discards writes, reads nothing
+/// /dev/zero        discards writes, reads zeroes
+/// /dev/random      reads from the hardware random number generator
+/// /dev/mmcblk0     the SD card
+/// /dev/mmcblk0p<n> partition n of the SD card, from the MBR
+/// ```
+///
+/// Serial devices never block: a read with no input pending fails with an
+/// error of kind `WouldBlock`. Character devices other than `fb0` are streams
+/// and ignore seeks. GPIO pins used by the kernel (the UART and the SD card)
+/// are not listed.
+///
+/// Block devices bypass the sector cache of file systems mounted from the
+/// same card, so writing to a mounted partition corrupts it.
+pub struct DevFs {
+    sd: SharedSd,
+    disks: Vec<Disk>,
+    fb: Mutex<Option<Shared<FrameBuffer>>>,
+}
+
+/// A parsed path in `/dev`.
+#[derive(Clone, Copy)]
+enum Path {
+    Root,
+    GpioDir,
+    Char(Device),
+    Disk(usize),
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such device")
+}
+
+fn not_supported() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "operation not supported by /dev")
+}
+
+fn bad_request() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, "inappropriate ioctl for device")
+}
+
+/// Returns `true` if `pin` is driven by a kernel driver and must not be
+/// reconfigured from user space.
+fn reserved(pin: u8) -> bool {
+    // 14-15: mini UART, 47-53: SD card
+    pin == 14 || pin == 15 || pin >= 47
+}
+
+impl DevFs {
+    /// Creates the device file system, listing `sd` and the partitions found
+    /// in its master boot record.
+    pub fn new(sd: SharedSd) -> DevFs {
+        let mut disks = vec![Disk { name: String::from("mmcblk0"), start: 0, sectors: sd.blocks() }];
+        if let Ok(mbr) = MasterBootRecord::from(&mut sd.clone()) {
+            for (i, entry) in mbr.table.iter().enumerate() {
+                if entry.ptype != 0 && entry.total_sectors != 0 {
+                    disks.push(Disk {
+                        name: format!("mmcblk0p{}", i + 1),
+                        start: entry.relative_sector as u64,
+                        sectors: entry.total_sectors as u64,
+                    });
+                }
+            }
+        }
+        DevFs { sd, disks, fb: Mutex::new(None) }
+    }
+
+    fn parse(&self, path: &str) -> io::Result<Path> {
+        let mut components = path.split('/').filter(|c| !c.is_empty());
+        let path = match (components.next(), components.next()) {
+            (None, _) => Path::Root,
+            (Some("gpio"), None) => Path::GpioDir,
+            (Some("gpio"), Some(pin)) => {
+                let pin: u8 = pin.parse().map_err(|_| not_found())?;
+                if pin >= GPIO_PINS || reserved(pin) {
+                    return Err(not_found());
+                }
+                Path::Char(Device::Gpio(pin))
+            }
+            (Some(name), None) => {
+                if let Some(&(_, device)) = CHAR_DEVICES.iter().find(|&&(n, _)| n == name) {
+                    Path::Char(device)
+                } else {
+                    let index = self.disks.iter().position(|d| d.name == name).ok_or_else(not_found)?;
+                    Path::Disk(index)
+                }
+            }
+            _ => return Err(not_found()),
+        };
+        if components.next().is_some() {
+            return Err(not_found());
+        }
+        Ok(path)
+    }
+
+    /// Returns the frame buffer, allocating it with the size of the attached
+    /// display on first use.
+    fn framebuffer(&self) -> io::Result<Shared<FrameBuffer>> {
+        let mut fb = self.fb.lock().unwrap();
+        if fb.is_none() {
+            let (width, height) = match Mailbox::new()
+                .tag_message(&[Tag::GET_PHYSICAL_WIDTH_HEIGHT as u32, 8, 8, 0, 0])
+            {
+                Some(buf) if buf[5] != 0 && buf[6] != 0 => (buf[5], buf[6]),
+                _ => FB_DEFAULT_MODE,
+            };
+            let buffer = FrameBuffer::new(width, height, 32)
+                .ok_or(io::Error::new(io::ErrorKind::Other, "failed to allocate frame buffer"))?;
+            *fb = Some(Shared::new(buffer));
+        }
+        let shared = fb.as_ref().unwrap().clone();
+        Ok(shared)
+    }
+
+    fn list(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let entries = match *path {
+            Path::Root => {
+                let mut entries: Vec<DirEntry> = CHAR_DEVICES.iter()
+                    .map(|&(name, _)| DirEntry {
+                        name: String::from(name),
+                        stat: Stat::synthetic(Kind::CharDevice, 0),
+                    })
+                    .collect();
+                entries.push(DirEntry { name: String::from("gpio"), stat: Stat::synthetic(Kind::Dir, 0) });
+                for disk in self.disks.iter() {
+                    entries.push(DirEntry {
+                        name: disk.name.clone(),
+                        stat: Stat::synthetic(Kind::BlockDevice, disk.sectors * BLOCK_SIZE as u64),
+                    });
+                }
+                entries
+            }
+            Path::GpioDir => (0..GPIO_PINS)
+                .filter(|&pin| !reserved(pin))
+                .map(|pin| DirEntry {
+                    name: format!("{}", pin),
+                    stat: Stat::synthetic(Kind::CharDevice, 0),
+                })
+                .collect(),
+            _ => return Err(io::Error::new(io::ErrorKind::Other, "not a directory")),
+        };
+        Ok(entries)
+    }
+}
+
+impl vfs::FileSystem for DevFs {
+    fn open(&self, path: &str) -> io::Result<Node> {
+        let path = self.parse(path)?;
+        match path {
+            Path::Char(Device::Fb) => {
+                let fb = self.framebuffer()?;
+                Ok(Node::File(Box::new(FbFile { fb, position: 0 })))
+            }
+            Path::Char(device) => Ok(Node::File(Box::new(CharFile(device)))),
+            Path::Disk(index) => {
+                let disk = &self.disks[index];
+                Ok(Node::File(Box::new(BlockFile {
+                    sd: self.sd.clone(),
+                    start: disk.start,
+                    sectors: disk.sectors,
+                    position: 0,
+                })))
+            }
+            _ => Ok(Node::Dir(Box::new(Listing(self.list(&path)?)))),
+        }
+    }
+
+    fn stat(&self, path: &str) -> io::Result<Stat> {
+        match self.parse(path)? {
+            Path::Root | Path::GpioDir => Ok(Stat::synthetic(Kind::Dir, 0)),
+            Path::Char(_) => Ok(Stat::synthetic(Kind::CharDevice, 0)),
+            Path::Disk(index) => {
+                let size = self.disks[index].sectors * BLOCK_SIZE as u64;
+                Ok(Stat::synthetic(Kind::BlockDevice, size))
+            }
+        }
+    }
+
+    fn create_file(&self, _path: &str) -> io::Result<Box<vfs::File>> {
+        Err(not_supported())
+    }
+
+    fn create_dir(&self, _path: &str, _parents: bool) -> io::Result<()> {
+        Err(not_supported())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> io::Result<()> {
+        Err(not_supported())
+    }
+
+    fn remove(&self, _path: &str, _children: bool) -> io::Result<()> {
+        Err(not_supported())
+    }
+}
+
+/// An opened character device other than the frame buffer.
+pub struct CharFile(Device);
+
+impl io::Read for CharFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0 {
+            Device::Console | Device::Uart => {
+                let mut console = CONSOLE.lock().unwrap();
+                if !buf.is_empty() && !console.has_byte() {
+                    return Err(io::Error::new(io::ErrorKind::WouldBlock, "no input pending"));
+                }
+                let mut n = 0;
+                while n < buf.len() && console.has_byte() {
+                    buf[n] = match console.read_byte() {
+                        b'\r' if self.0 == Device::Console => b'\n',
+                        byte => byte,
+                    };
+                    n += 1;
+                }
+                Ok(n)
+            }
+            Device::Gpio(pin) => {
+                if buf.is_empty() {
+                    return Ok(0);
+                }
+                buf[0] = if Gpio::new(pin).level() { b'1' } else { b'0' };
+                Ok(1)
+            }
+            Device::Null => Ok(0),
+            Device::Zero => {
+                for byte in buf.iter_mut() {
+                    *byte = 0;
+                }
+                Ok(buf.len())
+            }
+            Device::Random => {
+                Rng::new().fill(buf);
+                Ok(buf.len())
+            }
+            Device::Fb => unreachable!("frame buffer opened as character device"),
+        }
+    }
+}
+
+impl io::Write for CharFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.0 {
+            Device::Console => {
+                let mut console = CONSOLE.lock().unwrap();
+                for &byte in buf {
+                    if byte == b'\n' {
+                        console.write_byte(b'\r');
+                    }
+                    console.write_byte(byte);
+                }
+            }
+            Device::Uart => CONSOLE.lock().unwrap().write_buf(buf),
+            Device::Gpio(pin) => {
+                let gpio = Gpio::new(pin);
+                if gpio.function() != Function::Output {
+                    return Err(io::Error::new(io::ErrorKind::PermissionDenied, "pin is not an output"));
+                }
+                let mut gpio = gpio.into_output();
+                for &byte in buf {
+                    match byte {
+                        b'0' => gpio.clear(),
+                        b'1' => gpio.set(),
+                        b'\n' => (),
+                        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "expected '0' or '1'")),
+                    }
+                }
+            }
+            Device::Null | Device::Zero | Device::Random => (),
+            Device::Fb => unreachable!("frame buffer opened as character device"),
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for CharFile {
+    fn seek(&mut self, _pos: io::SeekFrom) -> io::Result<u64> {
+        Ok(0)
+    }
+}
+
+impl vfs::File for CharFile {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        0
+    }
+
+    fn ioctl(&mut self, request: u64, arg: u64) -> io::Result<u64> {
+        match (self.0, request) {
+            (Device::Console, ioctl::SERIAL_AVAILABLE) | (Device::Uart, ioctl::SERIAL_AVAILABLE) => {
+                Ok(CONSOLE.lock().unwrap().has_byte() as u64)
+            }
+            (Device::Gpio(pin), ioctl::GPIO_GET_FUNCTION) => Ok(Gpio::new(pin).function() as u64),
+            (Device::Gpio(pin), ioctl::GPIO_SET_FUNCTION) => {
+                let function = match arg {
+                    0b000 => Function::Input,
+                    0b001 => Function::Output,
+                    0b100 => Function::Alt0,
+                    0b101 => Function::Alt1,
+                    0b110 => Function::Alt2,
+                    0b111 => Function::Alt3,
+                    0b011 => Function::Alt4,
+                    0b010 => Function::Alt5,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid function")),
+                };
+                Gpio::new(pin).into_alt(function);
+                Ok(0)
+            }
+            (Device::Gpio(pin), ioctl::GPIO_SET_PULL) => {
+                let pud = match arg {
+                    0b00 => Pud::Off,
+                    0b01 => Pud::Down,
+                    0b10 => Pud::Up,
+                    _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid pull state")),
+                };
+                Gpio::new(pin).set_pud(pud);
+                Ok(0)
+            }
+            _ => Err(bad_request()),
+        }
+    }
+
+    fn read_queue(&self) -> Option<&'static WaitQueue> {
+        match self.0 {
+            Device::Console | Device::Uart => Some(&console::INPUT),
+            _ => None,
+        }
+    }
+}
+
+/// An opened frame buffer: its pixels as a seekable array of bytes.
+pub struct FbFile {
+    fb: Shared<FrameBuffer>,
+    position: u64,
+}
+
+impl FbFile {
+    /// Returns the address and length of the pixel memory.
+    fn memory(&self) -> (usize, usize) {
+        let fb = self.fb.borrow();
+        (fb.addr(), fb.pitch() as usize * fb.height() as usize)
+    }
+}
+
+impl io::Read for FbFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let (addr, len) = self.memory();
+        let start = min(self.position, len as u64) as usize;
+        let n = min(buf.len(), len - start);
+        for (i, byte) in buf[..n].iter_mut().enumerate() {
+            *byte = unsafe { ((addr + start + i) as *const u8).read_volatile() };
+        }
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+impl io::Write for FbFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let (addr, len) = self.memory();
+        let start = min(self.position, len as u64) as usize;
+        let n = min(buf.len(), len - start);
+        if n == 0 && !buf.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::Other, "no space left on device"));
+        }
+        for (i, &byte) in buf[..n].iter().enumerate() {
+            unsafe { ((addr + start + i) as *mut u8).write_volatile(byte) };
+        }
+        // the GPU scans the pixels out of memory, behind the cache
+        clean_invalidate_dcache(addr + start, n);
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for FbFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let size = vfs::File::size(self);
+        self.position = seek(self.position, size, pos)?;
+        Ok(self.position)
+    }
+}
+
+impl vfs::File for FbFile {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.memory().1 as u64
+    }
+
+    fn ioctl(&mut self, request: u64, arg: u64) -> io::Result<u64> {
+        let fb = self.fb.borrow();
+        match request {
+            ioctl::FB_WIDTH => Ok(fb.width() as u64),
+            ioctl::FB_HEIGHT => Ok(fb.height() as u64),
+            ioctl::FB_PITCH => Ok(fb.pitch() as u64),
+            ioctl::FB_FILL => {
+                fb.fill_rgba(arg as u32);
+                Ok(0)
+            }
+            _ => Err(bad_request()),
+        }
+    }
+}
+
+/// An opened block device: a range of sectors of the SD card as a seekable
+/// array of bytes.
+///
+/// Accesses need not be aligned to sectors; partially covered sectors are
+/// read, modified and written back.
+pub struct BlockFile {
+    sd: SharedSd,
+    start: u64,
+    sectors: u64,
+    position: u64,
+}
+
+impl BlockFile {
+    /// Splits the current position into a sector number relative to the
+    /// device, an offset into that sector and the number of bytes left in
+    /// it, or returns `None` at the end of the device.
+    fn locate(&self) -> Option<(u64, usize, usize)> {
+        let sector = self.position / BLOCK_SIZE as u64;
+        if sector >= self.sectors {
+            return None;
+        }
+        let offset = (self.position % BLOCK_SIZE as u64) as usize;
+        Some((sector, offset, BLOCK_SIZE - offset))
+    }
+}
+
+impl io::Read for BlockFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut sector = [0u8; BLOCK_SIZE];
+        let mut n = 0;
+        while n < buf.len() {
+            let (index, offset, left) = match self.locate() {
+                Some(location) => location,
+                None => break,
+            };
+            let len = min(left, buf.len() - n);
+            self.sd.read_sector(self.start + index, &mut sector)?;
+            buf[n..n + len].copy_from_slice(&sector[offset..offset + len]);
+            n += len;
+            self.position += len as u64;
+        }
+        Ok(n)
+    }
+}
+
+impl io::Write for BlockFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut sector = [0u8; BLOCK_SIZE];
+        let mut n = 0;
+        while n < buf.len() {
+            let (index, offset, left) = match self.locate() {
+                Some(location) => location,
+                None if n == 0 => {
+                    return Err(io::Error::new(io::ErrorKind::Other, "no space left on device"))
+                }
+                None => break,
+            };
+            let len = min(left, buf.len() - n);
+            if len < BLOCK_SIZE {
+                self.sd.read_sector(self.start + index, &mut sector)?;
+            }
+            sector[offset..offset + len].copy_from_slice(&buf[n..n + len]);
+            self.sd.write_sector(self.start + index, &sector)?;
+            n += len;
+            self.position += len as u64;
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for BlockFile {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let size = vfs::File::size(self);
+        self.position = seek(self.position, size, pos)?;
+        Ok(self.position)
+    }
+}
+
+impl vfs::File for BlockFile {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.sectors * BLOCK_SIZE as u64
+    }
+
+    fn ioctl(&mut self, request: u64, _arg: u64) -> io::Result<u64> {
+        match request {
+            ioctl::BLK_SECTOR_SIZE => Ok(BLOCK_SIZE as u64),
+            ioctl::BLK_SECTORS => Ok(self.sectors),
+            ioctl::BLK_START => Ok(self.start),
+            _ => Err(bad_request()),
+        }
+    }
+}
+
+/// Returns the position reached by seeking to `pos` from `position` in a
+/// device of `size` bytes.
+fn seek(position: u64, size: u64, pos: io::SeekFrom) -> io::Result<u64> {
+    let (base, offset) = match pos {
+        io::SeekFrom::Start(pos) => (pos as i64, 0),
+        io::SeekFrom::End(offset) => (size as i64, offset),
+        io::SeekFrom::Current(offset) => (position as i64, offset),
+    };
+    match base.checked_add(offset) {
+        Some(pos) if pos >= 0 => Ok(pos as u64),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start of device")),
+    }
+}
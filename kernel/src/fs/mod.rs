@@ -1,16 +1,46 @@
+#[macro_use]
+pub mod vfs;
 pub mod sd;
+pub mod sysfs;
+pub mod devfs;
+pub mod pipe;
 
-use core::ops::Deref;
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
 
-use vfat::traits;
-use vfat::vfat::{self, Shared, VFat};
+use vfat::vfat::{Shared, VFat};
+use vfat::tmpfs::TmpFs;
 
 use sys::io;
-use sys::path::Path;
 use sys::sync::Mutex;
-use self::sd::Sd;
+use self::sd::{Sd, SharedSd};
+use self::vfs::{Node, Stat, MountDir};
 
-pub struct FileSystem(pub Mutex<Option<Shared<VFat>>>);
+filesystem!(Shared<VFat>);
+filesystem!(TmpFs);
+
+/// The maximum number of bytes held by the tmpfs at `/var`.
+const VAR_LIMIT: usize = 16 * 1024 * 1024;
+
+/// The clock of in-memory file systems: seconds since boot, as there is no
+/// real time clock.
+fn clock() -> u64 {
+    ::pi::timer::current_time() / 1_000_000
+}
+
+/// A file system mounted at `path`.
+struct Mount {
+    path: String,
+    fs: Box<vfs::FileSystem>,
+}
+
+/// The kernel's file system namespace: a mount table keyed by path prefix.
+///
+/// Every path is resolved against the mount with the longest matching prefix
+/// and the remainder of the path is handed to that file system. Directories
+/// containing mount points list them in addition to their own entries.
+pub struct FileSystem(Mutex<Option<Vec<Mount>>>);
 
 impl FileSystem {
     /// Returns an uninitialized `FileSystem`.
@@ -21,55 +51,207 @@ impl FileSystem {
         FileSystem(Mutex::new(None))
     }
 
-    /// Initializes the file system.
+    /// Initializes the file system, mounts the SD card at `/`, the kernel
+    /// state at `/sys`, the devices at `/dev` and an empty tmpfs at `/var`.
     ///
     /// # Panics
     ///
     /// Panics if the underlying disk or file sytem failed to initialize.
     pub fn initialize(&self) {
-        let sd = Sd::new().unwrap();
-        let vfat = VFat::from(sd).unwrap();
-        *self.0.lock().unwrap() = Some(vfat);
+        let sd = SharedSd::new(Sd::new().expect("failed to initialize sd card"));
+        let vfat = VFat::from(sd.clone()).unwrap();
+        *self.0.lock().unwrap() = Some(Vec::new());
+        self.mount("/", Box::new(vfat)).expect("mount /");
+        self.mount("/sys", Box::new(sysfs::SysFs)).expect("mount /sys");
+        self.mount("/dev", Box::new(devfs::DevFs::new(sd))).expect("mount /dev");
+        self.mount("/var", Box::new(TmpFs::new(VAR_LIMIT, clock))).expect("mount /var");
+    }
+
+    /// Mounts `fs` at the absolute path `path`.
+    ///
+    /// The mount point does not need to exist in the parent file system.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `AlreadyExists` if a file system is already
+    /// mounted at `path`.
+    pub fn mount(&self, path: &str, fs: Box<vfs::FileSystem>) -> io::Result<()> {
+        let path = vfs::normalize(path)?;
+        let mut guard = self.0.lock().unwrap();
+        let mounts = guard.as_mut().expect("uninitialized");
+        if mounts.iter().any(|m| m.path == path) {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "mount point busy"));
+        }
+        mounts.push(Mount { path, fs });
+        // Longest prefix first, so the first match during lookup is the best.
+        mounts.sort_by(|a, b| b.path.len().cmp(&a.path.len()));
+        Ok(())
+    }
+
+    /// Unmounts and returns the file system mounted at `path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `NotFound` if nothing is mounted at `path` and
+    /// of kind `Other` if other file systems are mounted below `path`.
+    pub fn unmount(&self, path: &str) -> io::Result<Box<vfs::FileSystem>> {
+        let path = vfs::normalize(path)?;
+        let mut guard = self.0.lock().unwrap();
+        let mounts = guard.as_mut().expect("uninitialized");
+        if !children(mounts, &path).is_empty() {
+            return Err(io::Error::new(io::ErrorKind::Other, "mount point busy"));
+        }
+        match mounts.iter().position(|m| m.path == path) {
+            Some(index) => Ok(mounts.remove(index).fs),
+            None => Err(io::Error::new(io::ErrorKind::NotFound, "not mounted")),
+        }
+    }
+
+    /// Opens the node at the absolute path `path`.
+    pub fn open(&self, path: &str) -> io::Result<Node> {
+        let path = vfs::normalize(path)?;
+        let guard = self.0.lock().unwrap();
+        let mounts = guard.as_ref().expect("uninitialized");
+
+        let children = children(mounts, &path);
+        let (mount, rest) = resolve(mounts, &path)?;
+        let result = mount.fs.open(rest);
+        if children.is_empty() {
+            return result;
+        }
+        match result {
+            Ok(Node::Dir(dir)) => Ok(Node::Dir(Box::new(MountDir { inner: Some(dir), mounts: children }))),
+            Ok(node) => Ok(node),
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                Ok(Node::Dir(Box::new(MountDir { inner: None, mounts: children })))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Opens the file at the absolute path `path`.
+    pub fn open_file(&self, path: &str) -> io::Result<Box<vfs::File>> {
+        self.open(path)?
+            .into_file()
+            .ok_or(io::Error::new(io::ErrorKind::Other, "not a regular file"))
     }
-}
 
-impl<'a> traits::FileSystem for &'a FileSystem {
-    type File = vfat::File;
-    type Dir = vfat::Dir;
-    type Entry = vfat::Entry;
+    /// Opens the directory at the absolute path `path`.
+    pub fn open_dir(&self, path: &str) -> io::Result<Box<vfs::Dir>> {
+        self.open(path)?
+            .into_dir()
+            .ok_or(io::Error::new(io::ErrorKind::Other, "not a directory"))
+    }
+
+    /// Returns the metadata of the node at the absolute path `path`.
+    pub fn stat(&self, path: &str) -> io::Result<Stat> {
+        let path = vfs::normalize(path)?;
+        let guard = self.0.lock().unwrap();
+        let mounts = guard.as_ref().expect("uninitialized");
 
-    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
-        match self.0.lock().unwrap().deref() {
-            &Some(ref vfat) => vfat.open(path),
-            &None => panic!("uninitialized"),
+        let (mount, rest) = resolve(mounts, &path)?;
+        match mount.fs.stat(rest) {
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound && !children(mounts, &path).is_empty() => {
+                Ok(Stat::synthetic(vfs::Kind::Dir, 0))
+            }
+            result => result,
         }
     }
 
-    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
-        match self.0.lock().unwrap().deref() {
-            &Some(ref vfat) => vfat.create_file(path),
-            &None => panic!("uninitialized"),
+    /// Creates a new file at the absolute path `path`, opens it, and returns
+    /// it.
+    pub fn create_file(&self, path: &str) -> io::Result<Box<vfs::File>> {
+        let path = vfs::normalize(path)?;
+        let guard = self.0.lock().unwrap();
+        let mounts = guard.as_ref().expect("uninitialized");
+
+        let (mount, rest) = resolve(mounts, &path)?;
+        mount.fs.create_file(rest)
+    }
+
+    /// Creates a new directory at the absolute path `path`. If `parents` is
+    /// `true`, also creates all missing parent directories.
+    pub fn create_dir(&self, path: &str, parents: bool) -> io::Result<()> {
+        let path = vfs::normalize(path)?;
+        let guard = self.0.lock().unwrap();
+        let mounts = guard.as_ref().expect("uninitialized");
+
+        let (mount, rest) = resolve(mounts, &path)?;
+        mount.fs.create_dir(rest, parents)
+    }
+
+    /// Renames the node at `from` to `to`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Other` if `from` and `to` are on different
+    /// file systems or `from` is a mount point.
+    pub fn rename(&self, from: &str, to: &str) -> io::Result<()> {
+        let from = vfs::normalize(from)?;
+        let to = vfs::normalize(to)?;
+        let guard = self.0.lock().unwrap();
+        let mounts = guard.as_ref().expect("uninitialized");
+
+        if busy(mounts, &from) {
+            return Err(io::Error::new(io::ErrorKind::Other, "mount point busy"));
+        }
+        let (mount, from) = resolve(mounts, &from)?;
+        let (target, to) = resolve(mounts, &to)?;
+        if mount.path != target.path {
+            return Err(io::Error::new(io::ErrorKind::Other, "cross-device rename"));
         }
+        mount.fs.rename(from, to)
     }
 
-    fn create_dir<P: AsRef<Path>>(self, path: P, parents: bool) -> io::Result<Self::Dir> {
-        match self.0.lock().unwrap().deref() {
-            &Some(ref vfat) => vfat.create_dir(path, parents),
-            &None => panic!("uninitialized"),
+    /// Removes the node at `path`, recursively if `children` is `true`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Other` if `path` is or contains a mount
+    /// point.
+    pub fn remove(&self, path: &str, children: bool) -> io::Result<()> {
+        let path = vfs::normalize(path)?;
+        let guard = self.0.lock().unwrap();
+        let mounts = guard.as_ref().expect("uninitialized");
+
+        if busy(mounts, &path) {
+            return Err(io::Error::new(io::ErrorKind::Other, "mount point busy"));
         }
+        let (mount, rest) = resolve(mounts, &path)?;
+        mount.fs.remove(rest, children)
     }
+}
 
-    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
-        match self.0.lock().unwrap().deref() {
-            &Some(ref vfat) => vfat.rename(from, to),
-            &None => panic!("uninitialized"),
+/// Finds the mount responsible for the normalized `path` and returns it
+/// together with the path relative to that mount.
+fn resolve<'a, 'b>(mounts: &'a [Mount], path: &'b str) -> io::Result<(&'a Mount, &'b str)> {
+    for mount in mounts {
+        if let Some(rest) = vfs::strip_mount(&mount.path, path) {
+            return Ok((mount, rest));
         }
     }
+    Err(io::Error::new(io::ErrorKind::NotFound, "no file system mounted"))
+}
 
-    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
-        match self.0.lock().unwrap().deref() {
-            &Some(ref vfat) => vfat.remove(path, children),
-            &None => panic!("uninitialized"),
+/// Returns the names of the entries directly below the normalized `path` that
+/// lead to a mount point.
+fn children(mounts: &[Mount], path: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for mount in mounts {
+        if mount.path == path {
+            continue;
+        }
+        if let Some(rest) = vfs::strip_mount(path, &mount.path) {
+            let name = rest.split('/').find(|s| !s.is_empty()).unwrap_or("");
+            if !name.is_empty() && !names.iter().any(|n| n == name) {
+                names.push(String::from(name));
+            }
         }
     }
+    names
+}
+
+/// Returns `true` if the normalized `path` is or contains a mount point.
+fn busy(mounts: &[Mount], path: &str) -> bool {
+    mounts.iter().any(|m| vfs::strip_mount(path, &m.path).is_some())
 }
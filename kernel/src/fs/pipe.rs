@@ -0,0 +1,167 @@
+use core::cmp::min;
+
+use alloc::arc::Arc;
+
+use sys::io;
+use sys::Mutex;
+use process::{Id, WaitQueue};
+
+/// The number of bytes a pipe holds until its reader catches up.
+pub const PIPE_SIZE: usize = 4096;
+
+/// The bytes written to a pipe and not read yet, in a ring buffer.
+struct Buffer {
+    data: [u8; PIPE_SIZE],
+    head: usize,
+    len: usize,
+    /// Whether the read end is still open.
+    reader: bool,
+    /// Whether the write end is still open.
+    writer: bool,
+}
+
+/// A bounded channel of bytes from a `Writer` to a `Reader`. Reads of an
+/// empty pipe and writes to a full one fail with `WouldBlock`: the caller
+/// waits with `wait_readable` or `wait_writable` and tries again.
+pub struct Pipe {
+    buffer: Mutex<Buffer>,
+    /// Woken when data arrives or the write end is closed.
+    readable: WaitQueue,
+    /// Woken when room is made or the read end is closed.
+    writable: WaitQueue,
+}
+
+/// Returns the two ends of a new pipe.
+pub fn pipe() -> (Reader, Writer) {
+    let pipe = Arc::new(Pipe {
+        buffer: Mutex::new(Buffer { data: [0; PIPE_SIZE], head: 0, len: 0, reader: true, writer: true }),
+        readable: WaitQueue::new(),
+        writable: WaitQueue::new(),
+    });
+    (Reader(pipe.clone()), Writer(pipe))
+}
+
+impl Pipe {
+    /// Queues the process `id` until the pipe is readable. Returns `false`
+    /// without queueing it if the pipe is readable already. The check and
+    /// the queueing are atomic with respect to writes.
+    pub fn wait_readable(&self, id: Id) -> bool {
+        let buffer = self.buffer.lock().unwrap();
+        let wait = buffer.len == 0 && buffer.writer;
+        if wait {
+            self.readable.enqueue(id);
+        }
+        wait
+    }
+
+    /// Queues the process `id` until the pipe is writable. Returns `false`
+    /// without queueing it if the pipe is writable already.
+    pub fn wait_writable(&self, id: Id) -> bool {
+        let buffer = self.buffer.lock().unwrap();
+        let wait = buffer.len == PIPE_SIZE && buffer.reader;
+        if wait {
+            self.writable.enqueue(id);
+        }
+        wait
+    }
+}
+
+/// The read end of a pipe.
+pub struct Reader(Arc<Pipe>);
+
+impl Reader {
+    /// Returns the pipe this end reads from.
+    pub fn pipe(&self) -> Arc<Pipe> {
+        self.0.clone()
+    }
+}
+
+impl io::Read for Reader {
+    /// Reads the bytes available, up to the length of `buf`. Returns `0`
+    /// once the pipe is empty and the write end is closed.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `WouldBlock` if the pipe is empty but the write end is
+    /// open.
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = {
+            let mut buffer = self.0.buffer.lock().unwrap();
+            if buf.is_empty() || (buffer.len == 0 && !buffer.writer) {
+                return Ok(0);
+            }
+            if buffer.len == 0 {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "pipe is empty"));
+            }
+            let n = min(buf.len(), buffer.len);
+            for byte in buf[..n].iter_mut() {
+                *byte = buffer.data[buffer.head];
+                buffer.head = (buffer.head + 1) % PIPE_SIZE;
+            }
+            buffer.len -= n;
+            n
+        };
+        self.0.writable.wake_all();
+        Ok(n)
+    }
+}
+
+impl Drop for Reader {
+    fn drop(&mut self) {
+        self.0.buffer.lock().unwrap().reader = false;
+        self.0.writable.wake_all();
+    }
+}
+
+/// The write end of a pipe.
+pub struct Writer(Arc<Pipe>);
+
+impl Writer {
+    /// Returns the pipe this end writes to.
+    pub fn pipe(&self) -> Arc<Pipe> {
+        self.0.clone()
+    }
+}
+
+impl io::Write for Writer {
+    /// Writes as many bytes of `buf` as the pipe has room for.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `BrokenPipe` if the read end is closed, and with
+    /// `WouldBlock` if the pipe is full.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = {
+            let mut buffer = self.0.buffer.lock().unwrap();
+            if !buffer.reader {
+                return Err(io::Error::new(io::ErrorKind::BrokenPipe, "read end closed"));
+            }
+            if buf.is_empty() {
+                return Ok(0);
+            }
+            if buffer.len == PIPE_SIZE {
+                return Err(io::Error::new(io::ErrorKind::WouldBlock, "pipe is full"));
+            }
+            let n = min(buf.len(), PIPE_SIZE - buffer.len);
+            for &byte in &buf[..n] {
+                let tail = (buffer.head + buffer.len) % PIPE_SIZE;
+                buffer.data[tail] = byte;
+                buffer.len += 1;
+            }
+            n
+        };
+        self.0.readable.wake_all();
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for Writer {
+    fn drop(&mut self) {
+        self.0.buffer.lock().unwrap().writer = false;
+        self.0.readable.wake_all();
+    }
+}
@@ -1,59 +1,673 @@
+use core::fmt;
+
 use sys::io;
+use sys::volatile::prelude::*;
+use sys::volatile::{Volatile, ReadVolatile, Reserved};
 use vfat::traits::BlockDevice;
+use vfat::vfat::Shared;
 
-use pi::common::spin_sleep_us;
+use pi::common::{IO_BASE, spin_sleep_us};
+use pi::gpio::{Gpio, Function, Pud, Event};
 
-extern "C" {
-    /// A global representing the last SD controller error that occured.
-    static sd_err: i64;
+/// The base address of the EMMC controller registers.
+pub const EMMC_BASE: usize = 0x300000;
 
-    /// Initializes the SD card controller.
-    ///
-    /// Returns 0 if initialization is successful. If initialization fails,
-    /// returns -1 if a timeout occured, or -2 if an error sending commands to
-    /// the SD controller occured.
-    fn sd_init() -> i32;
-
-    /// Reads sector `n` (512 bytes) from the SD card and writes it to `buffer`.
-    /// It is undefined behavior if `buffer` does not point to at least 512
-    /// bytes of memory.
-    ///
-    /// On success, returns the number of bytes read: a positive number.
-    ///
-    /// On error, returns 0. The true error code is stored in the `sd_err`
-    /// global. `sd_err` will be set to -1 if a timeout occured or -2 if an
-    /// error sending commands to the SD controller occured. Other error codes
-    /// are also possible but defined only as being less than zero.
-    fn sd_readsector(n: i32, buffer: *mut u8) -> i32;
-}
+/// The size of a single block in bytes.
+///
+/// SDHC and SDXC cards always use 512-byte blocks; SDSC cards are switched to
+/// 512-byte blocks during initialization.
+pub const BLOCK_SIZE: usize = 512;
+
+/// The maximum number of blocks a single multi-block transfer may carry
+/// (`BLKSIZECNT.BLKCNT` is 16 bits wide).
+const MAX_BLOCKS: usize = 0xFFFF;
 
-// for use by `libsd`.
-#[no_mangle]
-pub extern "C" fn wait_micros(n: u32) {
-    spin_sleep_us(n)
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    ARG2: Volatile<u32>,                // 0x00 ACMD23 argument
+    BLKSIZECNT: Volatile<u32>,          // 0x04 block size and count
+    ARG1: Volatile<u32>,                // 0x08 argument
+    CMDTM: Volatile<u32>,               // 0x0C command and transfer mode
+    RESP: [ReadVolatile<u32>; 4],       // 0x10-0x1C response bits 127:0
+    DATA: Volatile<u32>,                // 0x20 data
+    STATUS: ReadVolatile<u32>,          // 0x24 status
+    CONTROL0: Volatile<u32>,            // 0x28 host configuration bits
+    CONTROL1: Volatile<u32>,            // 0x2C host configuration bits
+    INTERRUPT: Volatile<u32>,           // 0x30 interrupt flags
+    IRPT_MASK: Volatile<u32>,           // 0x34 interrupt flag enable
+    IRPT_EN: Volatile<u32>,             // 0x38 interrupt generation enable
+    CONTROL2: Volatile<u32>,            // 0x3C host configuration bits
+    __r0: [Reserved<u32>; 4],           // 0x40-0x4C
+    FORCE_IRPT: Volatile<u32>,          // 0x50 force interrupt event
+    __r1: [Reserved<u32>; 7],           // 0x54-0x6C
+    BOOT_TIMEOUT: Volatile<u32>,        // 0x70 timeout in boot mode
+    DBG_SEL: Volatile<u32>,             // 0x74 debug bus configuration
+    __r2: [Reserved<u32>; 2],           // 0x78-0x7C
+    EXRDFIFO_CFG: Volatile<u32>,        // 0x80 extension FIFO configuration
+    EXRDFIFO_EN: Volatile<u32>,         // 0x84 extension FIFO enable
+    TUNE_STEP: Volatile<u32>,           // 0x88 delay per card clock tuning step
+    TUNE_STEPS_STD: Volatile<u32>,      // 0x8C card clock tuning steps for SDR
+    TUNE_STEPS_DDR: Volatile<u32>,      // 0x90 card clock tuning steps for DDR
+    __r3: [Reserved<u32>; 23],          // 0x94-0xEC
+    SPI_INT_SPT: Volatile<u32>,         // 0xF0 SPI interrupt support
+    __r4: [Reserved<u32>; 2],           // 0xF4-0xF8
+    SLOTISR_VER: ReadVolatile<u32>,     // 0xFC slot interrupt status and version
 }
 
-#[derive(Debug)]
+// command flags
+const CMD_NEED_APP: u32 =        0x8000_0000;
+const CMD_RSPNS_48: u32 =        0x0002_0000;
+const CMD_ERRORS_MASK: u32 =     0xFFF9_C004;
+const CMD_RCA_MASK: u32 =        0xFFFF_0000;
+
+// commands
+const CMD_GO_IDLE: u32 =         0x0000_0000; // CMD0
+const CMD_ALL_SEND_CID: u32 =    0x0201_0000; // CMD2
+const CMD_SEND_REL_ADDR: u32 =   0x0302_0000; // CMD3
+const CMD_CARD_SELECT: u32 =     0x0703_0000; // CMD7
+const CMD_SEND_IF_COND: u32 =    0x0802_0000; // CMD8
+const CMD_SEND_CSD: u32 =        0x0901_0000; // CMD9
+const CMD_STOP_TRANS: u32 =      0x0C03_0000; // CMD12
+const CMD_SET_BLOCKLEN: u32 =    0x1002_0000; // CMD16
+const CMD_READ_SINGLE: u32 =     0x1122_0010; // CMD17
+const CMD_READ_MULTI: u32 =      0x1222_0032; // CMD18
+const CMD_SET_BLOCKCNT: u32 =    0x1702_0000; // CMD23
+const CMD_WRITE_SINGLE: u32 =    0x1822_0000; // CMD24
+const CMD_WRITE_MULTI: u32 =     0x1922_0022; // CMD25
+const CMD_APP_CMD: u32 =         0x3700_0000; // CMD55
+const CMD_SET_BUS_WIDTH: u32 =   0x0602_0000 | CMD_NEED_APP; // ACMD6
+const CMD_SEND_OP_COND: u32 =    0x2902_0000 | CMD_NEED_APP; // ACMD41
+const CMD_SEND_SCR: u32 =        0x3322_0010 | CMD_NEED_APP; // ACMD51
+
+// STATUS register settings
+const SR_READ_AVAILABLE: u32 =   0x0000_0800;
+const SR_DAT_INHIBIT: u32 =      0x0000_0002;
+const SR_CMD_INHIBIT: u32 =      0x0000_0001;
+const SR_APP_CMD: u32 =          0x0000_0020;
+
+// INTERRUPT register settings
+const INT_DATA_TIMEOUT: u32 =    0x0010_0000;
+const INT_CMD_TIMEOUT: u32 =     0x0001_0000;
+const INT_READ_RDY: u32 =        0x0000_0020;
+const INT_WRITE_RDY: u32 =       0x0000_0010;
+const INT_DATA_DONE: u32 =       0x0000_0002;
+const INT_CMD_DONE: u32 =        0x0000_0001;
+
+const INT_ERROR_MASK: u32 =      0x017E_8000;
+
+// CONTROL register settings
+const C0_HCTL_DWITDH: u32 =      0x0000_0002;
+
+const C1_SRST_HC: u32 =          0x0100_0000;
+const C1_TOUNIT_MAX: u32 =       0x000E_0000;
+const C1_CLK_EN: u32 =           0x0000_0004;
+const C1_CLK_STABLE: u32 =       0x0000_0002;
+const C1_CLK_INTLEN: u32 =       0x0000_0001;
+
+// SLOTISR_VER values
+const HOST_SPEC_NUM: u32 =       0x00FF_0000;
+const HOST_SPEC_NUM_SHIFT: u32 = 16;
+const HOST_SPEC_V2: u32 =        1;
+
+// SCR flags
+const SCR_SD_BUS_WIDTH_4: u32 =  0x0000_0400;
+const SCR_SUPP_SET_BLKCNT: u32 = 0x0200_0000;
+
+const ACMD41_VOLTAGE: u32 =      0x00FF_8000;
+const ACMD41_CMD_COMPLETE: u32 = 0x8000_0000;
+const ACMD41_CMD_CCS: u32 =      0x4000_0000;
+const ACMD41_ARG_HC: u32 =       0x51FF_8000;
+
+/// The base clock of the EMMC controller in Hz.
+const BASE_CLOCK: u32 = 41_666_666;
+
+/// An error reported by the SD card or the EMMC controller.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Error {
+    /// Timed out waiting for the controller or the card.
     Timeout,
-    Sending,
-    Other(i32),
+    /// The controller is still busy with a previous command or transfer.
+    Busy,
+    /// The controller did not come out of reset.
+    Reset,
+    /// The SD clock did not stabilize after a frequency change.
+    Clock,
+    /// The card does not support the host's voltage range.
+    Voltage,
+    /// The card did not accept an application specific command (`CMD55`).
+    AppCommand,
+    /// The card reported an error; holds the error bits of the card status.
+    Card(u32),
+    /// The controller raised an error interrupt; holds the `INTERRUPT` bits.
+    Interrupt(u32),
+    /// The request is malformed: the buffer is not a whole number of blocks or
+    /// the block address is out of range.
+    InvalidInput,
+}
+
+impl From<Error> for io::Error {
+    fn from(err: Error) -> io::Error {
+        match err {
+            Error::Timeout => io::Error::new(io::ErrorKind::TimedOut, "sd card timeout"),
+            Error::InvalidInput => io::Error::new(io::ErrorKind::InvalidInput, "sd card invalid request"),
+            err => io::Error::new(io::ErrorKind::Other, format!("sd card: {:?}", err)),
+        }
+    }
+}
+
+/// The capacity class of an SD card.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    /// Standard capacity (up to 2GB); addressed in bytes.
+    Sdsc,
+    /// High capacity (up to 32GB); addressed in blocks.
+    Sdhc,
+    /// Extended capacity (up to 2TB); addressed in blocks.
+    Sdxc,
 }
 
 /// A handle to an SD card controller.
-#[derive(Debug)]
-pub struct Sd;
+pub struct Sd {
+    registers: &'static mut Registers,
+    kind: Kind,
+    rca: u32,
+    scr: [u32; 2],
+    blocks: u64,
+    host_version: u32,
+}
 
 impl Sd {
-    /// Initializes the SD card controller and returns a handle to it.
+    /// Initializes the SD card controller and the inserted card and returns a
+    /// handle to it.
     pub fn new() -> Result<Self, Error> {
-        match unsafe { sd_init() } {
-            0 => Ok(Sd),
-            -1 => Err(Error::Timeout),
-            -2 => Err(Error::Sending),
-            err @ _ => Err(Error::Other(err)),
+        unsafe { Self::new_from(IO_BASE + EMMC_BASE) }
+    }
+
+    /// Initializes the SD card controller at `base` and the inserted card and
+    /// returns a handle to it.
+    pub unsafe fn new_from(base: usize) -> Result<Self, Error> {
+        setup_gpio();
+
+        let registers = &mut *(base as *mut Registers);
+        let host_version = (registers.SLOTISR_VER.read() & HOST_SPEC_NUM) >> HOST_SPEC_NUM_SHIFT;
+
+        let mut sd = Self {
+            registers,
+            kind: Kind::Sdsc,
+            rca: 0,
+            scr: [0; 2],
+            blocks: 0,
+            host_version,
+        };
+        sd.reset()?;
+        sd.identify()?;
+        Ok(sd)
+    }
+
+    /// The capacity class of the card.
+    pub fn kind(&self) -> Kind {
+        self.kind
+    }
+
+    /// The number of 512-byte blocks on the card.
+    pub fn blocks(&self) -> u64 {
+        self.blocks
+    }
+
+    /// Reads `buf.len() / BLOCK_SIZE` consecutive blocks starting at block
+    /// `lba` into `buf`. On success, the number of bytes read is returned.
+    ///
+    /// Transfers of more than one block are issued as `READ_MULTIPLE_BLOCK`
+    /// (`CMD18`) on block addressed cards.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidInput` if `buf` is empty, is not a multiple of
+    /// `BLOCK_SIZE` or the range is beyond the end of the card.
+    pub fn read_blocks(&mut self, lba: u64, buf: &mut [u8]) -> Result<usize, Error> {
+        self.check_range(lba, buf.len())?;
+        let mut done = 0;
+        for chunk in buf.chunks_mut(MAX_BLOCKS * BLOCK_SIZE) {
+            let lba = lba + (done / BLOCK_SIZE) as u64;
+            self.transfer_read(lba, chunk)?;
+            done += chunk.len();
+        }
+        Ok(done)
+    }
+
+    /// Writes `buf.len() / BLOCK_SIZE` consecutive blocks starting at block
+    /// `lba` from `buf`. On success, the number of bytes written is returned.
+    ///
+    /// Transfers of more than one block are issued as
+    /// `WRITE_MULTIPLE_BLOCK` (`CMD25`) on block addressed cards.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::InvalidInput` if `buf` is empty, is not a multiple of
+    /// `BLOCK_SIZE` or the range is beyond the end of the card.
+    pub fn write_blocks(&mut self, lba: u64, buf: &[u8]) -> Result<usize, Error> {
+        self.check_range(lba, buf.len())?;
+        let mut done = 0;
+        for chunk in buf.chunks(MAX_BLOCKS * BLOCK_SIZE) {
+            let lba = lba + (done / BLOCK_SIZE) as u64;
+            self.transfer_write(lba, chunk)?;
+            done += chunk.len();
+        }
+        Ok(done)
+    }
+
+    fn check_range(&self, lba: u64, len: usize) -> Result<(), Error> {
+        let count = (len / BLOCK_SIZE) as u64;
+        if len == 0 || len % BLOCK_SIZE != 0 {
+            return Err(Error::InvalidInput);
+        }
+        match lba.checked_add(count) {
+            Some(end) if end <= self.blocks && end <= ::core::u32::MAX as u64 => Ok(()),
+            _ => Err(Error::InvalidInput),
+        }
+    }
+
+    /// Returns the argument addressing block `lba` for a data command.
+    fn address(&self, lba: u64) -> u32 {
+        match self.kind {
+            Kind::Sdsc => (lba * BLOCK_SIZE as u64) as u32,
+            Kind::Sdhc | Kind::Sdxc => lba as u32,
+        }
+    }
+
+    fn transfer_read(&mut self, lba: u64, buf: &mut [u8]) -> Result<(), Error> {
+        let num = buf.len() / BLOCK_SIZE;
+        self.status(SR_DAT_INHIBIT)?;
+
+        if self.kind == Kind::Sdsc {
+            // byte addressed cards are read one block at a time
+            for (i, block) in buf.chunks_mut(BLOCK_SIZE).enumerate() {
+                self.registers.BLKSIZECNT.write((1 << 16) | BLOCK_SIZE as u32);
+                let addr = self.address(lba + i as u64);
+                self.command(CMD_READ_SINGLE, addr)?;
+                self.interrupt(INT_READ_RDY)?;
+                self.read_data(block);
+            }
+            return Ok(());
+        }
+
+        let set_count = num > 1 && self.scr[0] & SCR_SUPP_SET_BLKCNT != 0;
+        if set_count {
+            self.command(CMD_SET_BLOCKCNT, num as u32)?;
+        }
+        self.registers.BLKSIZECNT.write(((num as u32) << 16) | BLOCK_SIZE as u32);
+        let addr = self.address(lba);
+        self.command(if num == 1 { CMD_READ_SINGLE } else { CMD_READ_MULTI }, addr)?;
+        for block in buf.chunks_mut(BLOCK_SIZE) {
+            self.interrupt(INT_READ_RDY)?;
+            self.read_data(block);
+        }
+        if num > 1 && !set_count {
+            self.command(CMD_STOP_TRANS, 0)?;
+        }
+        Ok(())
+    }
+
+    fn transfer_write(&mut self, lba: u64, buf: &[u8]) -> Result<(), Error> {
+        let num = buf.len() / BLOCK_SIZE;
+        self.status(SR_DAT_INHIBIT)?;
+
+        if self.kind == Kind::Sdsc {
+            // byte addressed cards are written one block at a time
+            for (i, block) in buf.chunks(BLOCK_SIZE).enumerate() {
+                self.registers.BLKSIZECNT.write((1 << 16) | BLOCK_SIZE as u32);
+                let addr = self.address(lba + i as u64);
+                self.command(CMD_WRITE_SINGLE, addr)?;
+                self.interrupt(INT_WRITE_RDY)?;
+                self.write_data(block);
+                self.interrupt(INT_DATA_DONE)?;
+            }
+            return Ok(());
+        }
+
+        let set_count = num > 1 && self.scr[0] & SCR_SUPP_SET_BLKCNT != 0;
+        if set_count {
+            self.command(CMD_SET_BLOCKCNT, num as u32)?;
+        }
+        self.registers.BLKSIZECNT.write(((num as u32) << 16) | BLOCK_SIZE as u32);
+        let addr = self.address(lba);
+        self.command(if num == 1 { CMD_WRITE_SINGLE } else { CMD_WRITE_MULTI }, addr)?;
+        for block in buf.chunks(BLOCK_SIZE) {
+            self.interrupt(INT_WRITE_RDY)?;
+            self.write_data(block);
+        }
+        self.interrupt(INT_DATA_DONE)?;
+        if num > 1 && !set_count {
+            self.command(CMD_STOP_TRANS, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Drains one block from the data FIFO into `block`.
+    fn read_data(&mut self, block: &mut [u8]) {
+        for word in block.chunks_mut(4) {
+            let data = self.registers.DATA.read();
+            word[0] = data as u8;
+            word[1] = (data >> 8) as u8;
+            word[2] = (data >> 16) as u8;
+            word[3] = (data >> 24) as u8;
+        }
+    }
+
+    /// Fills the data FIFO with one block from `block`.
+    fn write_data(&mut self, block: &[u8]) {
+        for word in block.chunks(4) {
+            let data = (word[0] as u32)
+                | (word[1] as u32) << 8
+                | (word[2] as u32) << 16
+                | (word[3] as u32) << 24;
+            self.registers.DATA.write(data);
+        }
+    }
+
+    /// Waits until none of the `mask` bits are set in the `STATUS` register.
+    fn status(&self, mask: u32) -> Result<(), Error> {
+        let mut cnt = 500_000;
+        while self.registers.STATUS.read() & mask != 0
+            && self.registers.INTERRUPT.read() & INT_ERROR_MASK == 0
+            && cnt != 0
+        {
+            spin_sleep_us(1);
+            cnt -= 1;
+        }
+        let flags = self.registers.INTERRUPT.read();
+        if flags & INT_ERROR_MASK != 0 {
+            Err(Error::Interrupt(flags))
+        } else if cnt == 0 {
+            Err(Error::Busy)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Waits for any of the `mask` interrupts and acknowledges them.
+    fn interrupt(&mut self, mask: u32) -> Result<(), Error> {
+        let m = mask | INT_ERROR_MASK;
+        let mut cnt = 1_000_000;
+        while self.registers.INTERRUPT.read() & m == 0 && cnt != 0 {
+            spin_sleep_us(1);
+            cnt -= 1;
+        }
+        let flags = self.registers.INTERRUPT.read();
+        if cnt == 0 || flags & (INT_CMD_TIMEOUT | INT_DATA_TIMEOUT) != 0 {
+            self.registers.INTERRUPT.write(flags);
+            Err(Error::Timeout)
+        } else if flags & INT_ERROR_MASK != 0 {
+            self.registers.INTERRUPT.write(flags);
+            Err(Error::Interrupt(flags))
+        } else {
+            self.registers.INTERRUPT.write(mask);
+            Ok(())
+        }
+    }
+
+    /// Sends the command `cmd` with argument `arg` and returns the decoded
+    /// response. Application specific commands are prefixed with `CMD55`.
+    fn command(&mut self, cmd: u32, arg: u32) -> Result<u32, Error> {
+        if cmd & CMD_NEED_APP != 0 {
+            let rca = self.rca;
+            let app = if rca != 0 { CMD_APP_CMD | CMD_RSPNS_48 } else { CMD_APP_CMD };
+            let status = self.command(app, rca)?;
+            if rca != 0 && status == 0 {
+                return Err(Error::AppCommand);
+            }
+        }
+        let cmd = cmd & !CMD_NEED_APP;
+
+        self.status(SR_CMD_INHIBIT)?;
+
+        let flags = self.registers.INTERRUPT.read();
+        self.registers.INTERRUPT.write(flags);
+        self.registers.ARG1.write(arg);
+        self.registers.CMDTM.write(cmd);
+
+        if cmd == CMD_SEND_OP_COND & !CMD_NEED_APP {
+            spin_sleep_us(1000);
+        } else if cmd == CMD_SEND_IF_COND || cmd == CMD_APP_CMD {
+            spin_sleep_us(100);
+        }
+
+        self.interrupt(INT_CMD_DONE)?;
+
+        let resp = self.registers.RESP[0].read();
+        match cmd {
+            CMD_GO_IDLE | CMD_APP_CMD => Ok(0),
+            CMD_ALL_SEND_CID | CMD_SEND_CSD => Ok(resp),
+            CMD_SEND_IF_COND => if resp == arg { Ok(resp) } else { Err(Error::Voltage) },
+            CMD_SEND_REL_ADDR => {
+                let err = ((resp & 0x1FFF)
+                    | ((resp & 0x2000) << 6)
+                    | ((resp & 0x4000) << 8)
+                    | ((resp & 0x8000) << 8)) & CMD_ERRORS_MASK;
+                if err != 0 {
+                    Err(Error::Card(err))
+                } else {
+                    Ok(resp & CMD_RCA_MASK)
+                }
+            }
+            cmd if cmd == CMD_APP_CMD | CMD_RSPNS_48 => Ok(resp & SR_APP_CMD),
+            cmd if cmd == CMD_SEND_OP_COND & !CMD_NEED_APP => Ok(resp),
+            _ => match resp & CMD_ERRORS_MASK {
+                0 => Ok(resp),
+                err => Err(Error::Card(err)),
+            },
         }
     }
+
+    /// Sets the SD clock to the frequency `freq` in Hz.
+    fn set_clock(&mut self, freq: u32) -> Result<(), Error> {
+        let mut cnt = 100_000;
+        while self.registers.STATUS.read() & (SR_CMD_INHIBIT | SR_DAT_INHIBIT) != 0 && cnt != 0 {
+            spin_sleep_us(1);
+            cnt -= 1;
+        }
+        if cnt == 0 {
+            return Err(Error::Busy);
+        }
+
+        self.registers.CONTROL1.and_mask(!C1_CLK_EN);
+        spin_sleep_us(10);
+
+        let c = BASE_CLOCK / freq;
+        let mut x = c - 1;
+        let mut s: u32 = 32;
+        if x == 0 {
+            s = 0;
+        } else {
+            if x & 0xFFFF_0000 == 0 { x <<= 16; s -= 16; }
+            if x & 0xFF00_0000 == 0 { x <<= 8; s -= 8; }
+            if x & 0xF000_0000 == 0 { x <<= 4; s -= 4; }
+            if x & 0xC000_0000 == 0 { x <<= 2; s -= 2; }
+            if x & 0x8000_0000 == 0 { s -= 1; }
+            if s > 0 { s -= 1; }
+            if s > 7 { s = 7; }
+        }
+
+        let mut d = if self.host_version > HOST_SPEC_V2 { c } else { 1 << s };
+        if d <= 2 {
+            d = 2;
+        }
+
+        let h = if self.host_version > HOST_SPEC_V2 { (d & 0x300) >> 2 } else { 0 };
+        let d = ((d & 0x0FF) << 8) | h;
+
+        let control = self.registers.CONTROL1.read();
+        self.registers.CONTROL1.write((control & 0xFFFF_003F) | d);
+        spin_sleep_us(10);
+        self.registers.CONTROL1.or_mask(C1_CLK_EN);
+        spin_sleep_us(10);
+
+        let mut cnt = 10_000;
+        while self.registers.CONTROL1.read() & C1_CLK_STABLE == 0 && cnt != 0 {
+            spin_sleep_us(10);
+            cnt -= 1;
+        }
+        if cnt == 0 {
+            Err(Error::Clock)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Resets the host controller and sets up the identification clock.
+    fn reset(&mut self) -> Result<(), Error> {
+        self.registers.CONTROL0.write(0);
+        self.registers.CONTROL1.or_mask(C1_SRST_HC);
+
+        let mut cnt = 10_000;
+        while self.registers.CONTROL1.read() & C1_SRST_HC != 0 && cnt != 0 {
+            spin_sleep_us(10);
+            cnt -= 1;
+        }
+        if cnt == 0 {
+            return Err(Error::Reset);
+        }
+
+        self.registers.CONTROL1.or_mask(C1_CLK_INTLEN | C1_TOUNIT_MAX);
+        spin_sleep_us(10);
+
+        self.set_clock(400_000)?;
+
+        self.registers.IRPT_EN.write(0xFFFF_FFFF);
+        self.registers.IRPT_MASK.write(0xFFFF_FFFF);
+        Ok(())
+    }
+
+    /// Runs the card identification sequence and brings the card into the
+    /// transfer state.
+    fn identify(&mut self) -> Result<(), Error> {
+        self.rca = 0;
+        self.scr = [0; 2];
+
+        self.command(CMD_GO_IDLE, 0)?;
+        self.command(CMD_SEND_IF_COND, 0x0000_01AA)?;
+
+        let mut ocr = 0;
+        for _ in 0..6 {
+            spin_sleep_us(400);
+            match self.command(CMD_SEND_OP_COND, ACMD41_ARG_HC) {
+                Ok(r) => ocr = r,
+                Err(Error::Timeout) => continue,
+                Err(err) => return Err(err),
+            }
+            if ocr & ACMD41_CMD_COMPLETE != 0 {
+                break;
+            }
+        }
+        if ocr & ACMD41_CMD_COMPLETE == 0 {
+            return Err(Error::Timeout);
+        }
+        if ocr & ACMD41_VOLTAGE == 0 {
+            return Err(Error::Voltage);
+        }
+        let ccs = ocr & ACMD41_CMD_CCS != 0;
+
+        self.command(CMD_ALL_SEND_CID, 0)?;
+        self.rca = self.command(CMD_SEND_REL_ADDR, 0)?;
+
+        let rca = self.rca;
+        self.command(CMD_SEND_CSD, rca)?;
+        self.blocks = self.capacity();
+        self.kind = if !ccs {
+            Kind::Sdsc
+        } else if self.blocks > (32 << 30) / BLOCK_SIZE as u64 {
+            Kind::Sdxc
+        } else {
+            Kind::Sdhc
+        };
+
+        self.set_clock(25_000_000)?;
+        self.command(CMD_CARD_SELECT, rca)?;
+
+        self.status(SR_DAT_INHIBIT)?;
+        self.registers.BLKSIZECNT.write((1 << 16) | 8);
+        self.command(CMD_SEND_SCR, 0)?;
+        self.interrupt(INT_READ_RDY)?;
+
+        let mut r = 0;
+        let mut cnt = 100_000;
+        while r < 2 && cnt != 0 {
+            if self.registers.STATUS.read() & SR_READ_AVAILABLE != 0 {
+                self.scr[r] = self.registers.DATA.read();
+                r += 1;
+            } else {
+                spin_sleep_us(1);
+                cnt -= 1;
+            }
+        }
+        if r != 2 {
+            return Err(Error::Timeout);
+        }
+
+        if self.scr[0] & SCR_SD_BUS_WIDTH_4 != 0 {
+            self.command(CMD_SET_BUS_WIDTH, rca | 2)?;
+            self.registers.CONTROL0.or_mask(C0_HCTL_DWITDH);
+        }
+
+        if self.kind == Kind::Sdsc {
+            self.command(CMD_SET_BLOCKLEN, BLOCK_SIZE as u32)?;
+        }
+
+        Ok(())
+    }
+
+    /// Decodes the card capacity in blocks from the `CSD` register left in the
+    /// response registers by `SEND_CSD`.
+    ///
+    /// The controller strips the CRC, so `CSD[127:8]` lands in `RESP[119:0]`.
+    fn capacity(&self) -> u64 {
+        let resp = [
+            self.registers.RESP[0].read(),
+            self.registers.RESP[1].read(),
+            self.registers.RESP[2].read(),
+            self.registers.RESP[3].read(),
+        ];
+        match (resp[3] >> 22) & 0b11 {
+            // CSD version 1.0: capacity = (C_SIZE + 1) * 2^(C_SIZE_MULT + 2) * 2^READ_BL_LEN
+            0 => {
+                let read_bl_len = (resp[2] >> 8) & 0xF;
+                let c_size = ((resp[1] >> 22) & 0x3FF) | ((resp[2] & 0b11) << 10);
+                let c_size_mult = (resp[1] >> 7) & 0b111;
+                let bytes = (c_size as u64 + 1) << (c_size_mult + 2 + read_bl_len);
+                bytes / BLOCK_SIZE as u64
+            }
+            // CSD version 2.0: capacity = (C_SIZE + 1) * 512KiB
+            _ => {
+                let c_size = (resp[1] >> 8) & 0x3F_FFFF;
+                (c_size as u64 + 1) * 1024
+            }
+        }
+    }
+}
+
+/// Routes the SD card pins (47-53) to the EMMC controller.
+fn setup_gpio() {
+    // GPIO_CD
+    let mut cd = Gpio::new(47).into_input();
+    cd.set_pud(Pud::Up);
+    cd.set_event_detection(Event::HighLevel);
+
+    // GPIO_CLK, GPIO_CMD, GPIO_DAT0-3
+    for &pin in &[48, 49, 50, 51, 52, 53] {
+        let mut pin = Gpio::new(pin).into_alt(Function::Alt3);
+        pin.pull(Pud::Up);
+    }
+}
+
+impl fmt::Debug for Sd {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Sd")
+            .field("kind", &self.kind)
+            .field("rca", &self.rca)
+            .field("blocks", &self.blocks)
+            .finish()
+    }
 }
 
 impl BlockDevice for Sd {
@@ -63,29 +677,56 @@ impl BlockDevice for Sd {
     /// # Errors
     ///
     /// An I/O error of kind `InvalidInput` is returned if `buf.len() < 512` or
-    /// `n > 2^31 - 1` (the maximum value for an `i32`).
+    /// `n` is beyond the end of the card.
     ///
     /// An error of kind `TimedOut` is returned if a timeout occurs while
     /// reading from the SD card.
     ///
     /// An error of kind `Other` is returned for all other errors.
     fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
-        if buf.len() < 512 || buf.len() > ::core::i32::MAX as usize {
-            Err(io::Error::new(io::ErrorKind::InvalidInput, "buf.len() out of bound"))
-        } else {
-            let n = unsafe { sd_readsector(n as i32, buf.as_mut_ptr()) };
-            let errno = unsafe { sd_err };
-            if n > 0 {
-                Ok(n as usize)
-            } else if errno == -1 {
-                Err(io::Error::new(io::ErrorKind::TimedOut, "sd card timeout"))
-            } else {
-                Err(io::Error::new(io::ErrorKind::Other, format!("sd_err: {}", errno)))
-            }
+        if buf.len() < BLOCK_SIZE {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "buf.len() out of bound"));
+        }
+        Ok(self.read_blocks(n, &mut buf[..BLOCK_SIZE])?)
+    }
+
+    /// Overwrites sector `n` with the first 512 bytes of `buf`. On success,
+    /// the number of bytes written is returned.
+    ///
+    /// # Errors
+    ///
+    /// An I/O error of kind `UnexpectedEof` is returned if `buf.len() < 512`.
+    /// Other errors are reported as for `read_sector`.
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        if buf.len() < BLOCK_SIZE {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "buf.len() out of bound"));
         }
+        Ok(self.write_blocks(n, &buf[..BLOCK_SIZE])?)
+    }
+}
+
+/// A handle to the SD card that can be cloned, so the file system mounted
+/// from the card and the block devices in `/dev` can share it.
+#[derive(Debug, Clone)]
+pub struct SharedSd(Shared<Sd>);
+
+impl SharedSd {
+    pub fn new(sd: Sd) -> Self {
+        SharedSd(Shared::new(sd))
+    }
+
+    /// Returns the capacity of the card in blocks.
+    pub fn blocks(&self) -> u64 {
+        self.0.borrow().blocks()
+    }
+}
+
+impl BlockDevice for SharedSd {
+    fn read_sector(&mut self, n: u64, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.borrow_mut().read_sector(n, buf)
     }
 
-    fn write_sector(&mut self, _n: u64, _buf: &[u8]) -> io::Result<usize> {
-        unimplemented!("SD card and file system are read only")
+    fn write_sector(&mut self, n: u64, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write_sector(n, buf)
     }
 }
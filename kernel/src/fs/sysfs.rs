@@ -0,0 +1,330 @@
+use core::fmt::Write;
+use core::cmp::min;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use sys::io;
+use pi::atags::{Atags, Atag};
+use pi::mbox::{Mailbox, Tag};
+use pi::timer::current_time;
+
+use fs::vfs::{self, Node, Stat, Kind, DirEntry};
+use process::{Id, Process, State};
+use vm::Prot;
+
+/// Files in the root of `/sys`.
+const FILES: &[&str] = &["board", "cmdline", "meminfo", "memory", "uptime"];
+
+/// Files in the directory of every process, `/sys/proc/<id>`.
+const PROCESS_FILES: &[&str] = &["id", "maps", "state"];
+
+/// A virtual file system exposing kernel state, usually mounted at `/sys`.
+///
+/// Nothing is stored: the content of a file is generated when it is opened,
+/// so every open observes a fresh snapshot.
+///
+/// ```text
+/// /sys/board          board model, revision, serial and memory split
+/// /sys/cmdline        kernel command line from the ATAGs
+/// /sys/meminfo        kernel heap statistics
+/// /sys/memory         memory regions from the ATAGs
+/// /sys/uptime         seconds since boot
+/// /sys/proc/<id>/id
+/// /sys/proc/<id>/maps memory areas of the process
+/// /sys/proc/<id>/state
+/// ```
+pub struct SysFs;
+
+/// A parsed path in `/sys`.
+#[derive(Clone, Copy)]
+enum Path<'a> {
+    Root,
+    File(&'a str),
+    Proc,
+    Process(Id),
+    ProcessFile(Id, &'a str),
+}
+
+impl<'a> Path<'a> {
+    fn parse(path: &'a str) -> io::Result<Self> {
+        let mut components = path.split('/').filter(|c| !c.is_empty());
+        let path = match (components.next(), components.next(), components.next()) {
+            (None, _, _) => Path::Root,
+            (Some("proc"), None, _) => Path::Proc,
+            (Some("proc"), Some(id), file) => {
+                let id = id.parse().ok().and_then(Id::new).ok_or_else(not_found)?;
+                match file {
+                    None => Path::Process(id),
+                    Some(name) if PROCESS_FILES.contains(&name) => Path::ProcessFile(id, name),
+                    Some(_) => return Err(not_found()),
+                }
+            }
+            (Some(name), None, _) if FILES.contains(&name) => Path::File(name),
+            _ => return Err(not_found()),
+        };
+        if components.next().is_some() {
+            return Err(not_found());
+        }
+        Ok(path)
+    }
+}
+
+fn not_found() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "no such entry in /sys")
+}
+
+fn read_only() -> io::Error {
+    io::Error::new(io::ErrorKind::PermissionDenied, "/sys is read only")
+}
+
+impl SysFs {
+    /// Returns the content of the file at `path`.
+    fn generate(&self, path: &Path) -> io::Result<String> {
+        match *path {
+            Path::File(name) => Ok(generate(name)),
+            Path::ProcessFile(id, name) => ::SCHEDULER
+                .with_process(id, |process| generate_process(process, name))
+                .ok_or_else(not_found),
+            _ => Err(io::Error::new(io::ErrorKind::Other, "is a directory")),
+        }
+    }
+
+    /// Returns the entries of the directory at `path`.
+    fn list(&self, path: &Path) -> io::Result<Vec<DirEntry>> {
+        let names: Vec<String> = match *path {
+            Path::Root => {
+                let mut names: Vec<String> = FILES.iter().map(|&n| String::from(n)).collect();
+                names.push(String::from("proc"));
+                names
+            }
+            Path::Proc => ::SCHEDULER.ids().iter().map(|id| format!("{}", id.as_u64())).collect(),
+            Path::Process(id) => {
+                if !::SCHEDULER.ids().contains(&id) {
+                    return Err(not_found());
+                }
+                PROCESS_FILES.iter().map(|&n| String::from(n)).collect()
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::Other, "not a directory")),
+        };
+
+        let kind = match *path {
+            Path::Root | Path::Proc => Kind::Dir,
+            _ => Kind::File,
+        };
+        Ok(names.into_iter()
+            .map(|name| {
+                let kind = if name == "proc" { Kind::Dir } else { kind };
+                DirEntry { name, stat: Stat::synthetic(kind, 0) }
+            })
+            .collect())
+    }
+}
+
+impl vfs::FileSystem for SysFs {
+    fn open(&self, path: &str) -> io::Result<Node> {
+        let path = Path::parse(path)?;
+        match path {
+            Path::File(_) | Path::ProcessFile(_, _) => {
+                let data = self.generate(&path)?.into_bytes();
+                Ok(Node::File(Box::new(Buffer::new(data))))
+            }
+            _ => Ok(Node::Dir(Box::new(Listing(self.list(&path)?)))),
+        }
+    }
+
+    fn stat(&self, path: &str) -> io::Result<Stat> {
+        let path = Path::parse(path)?;
+        match path {
+            Path::File(_) | Path::ProcessFile(_, _) => {
+                let size = self.generate(&path)?.len() as u64;
+                Ok(Stat { read_only: true, ..Stat::synthetic(Kind::File, size) })
+            }
+            Path::Process(id) if !::SCHEDULER.ids().contains(&id) => Err(not_found()),
+            _ => Ok(Stat::synthetic(Kind::Dir, 0)),
+        }
+    }
+
+    fn create_file(&self, _path: &str) -> io::Result<Box<vfs::File>> {
+        Err(read_only())
+    }
+
+    fn create_dir(&self, _path: &str, _parents: bool) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn rename(&self, _from: &str, _to: &str) -> io::Result<()> {
+        Err(read_only())
+    }
+
+    fn remove(&self, _path: &str, _children: bool) -> io::Result<()> {
+        Err(read_only())
+    }
+}
+
+fn generate(name: &str) -> String {
+    let mut s = String::new();
+    match name {
+        "board" => board(&mut s),
+        "cmdline" => {
+            for cmd in Atags::get().filter_map(|atag| atag.cmd()) {
+                s.push_str(cmd);
+            }
+            s.push('\n');
+        }
+        "meminfo" => {
+            let stats = ::ALLOCATOR.stats();
+            let _ = writeln!(s, "heap_size: {}", stats.heap_size);
+            let _ = writeln!(s, "heap_used: {}", stats.heap_used);
+            let _ = writeln!(s, "in_use:    {}", stats.in_use);
+            let _ = writeln!(s, "allocs:    {}", stats.allocs);
+            let _ = writeln!(s, "deallocs:  {}", stats.deallocs);
+            let _ = writeln!(s, "failures:  {}", stats.failures);
+        }
+        "memory" => {
+            for atag in Atags::get() {
+                if let Atag::Mem(mem) = atag {
+                    let _ = writeln!(s, "{:#010x}-{:#010x} {}",
+                        mem.start, mem.start + mem.size, mem.size);
+                }
+            }
+        }
+        "uptime" => {
+            let us = current_time();
+            let _ = writeln!(s, "{}.{:06}", us / 1_000_000, us % 1_000_000);
+        }
+        _ => unreachable!("unknown /sys file"),
+    }
+    s
+}
+
+fn generate_process(process: &mut Process, name: &str) -> String {
+    let mut s = String::new();
+    match name {
+        "id" => {
+            let id = process.id().map(|id| id.as_u64()).unwrap_or(0);
+            let _ = writeln!(s, "{}", id);
+        }
+        "state" => {
+            let _ = match process.state {
+                State::Ready => writeln!(s, "ready"),
+                State::Running => writeln!(s, "running"),
+                State::Waiting(_) => writeln!(s, "waiting"),
+                State::Exit(code) => writeln!(s, "exit {}", code),
+            };
+        }
+        "maps" => {
+            for area in process.mm().areas() {
+                let prot = area.protection();
+                let _ = write!(s, "{:016x}-{:016x} {}{}{}",
+                    area.start.as_usize(), area.end.as_usize(),
+                    if prot.contains(Prot::READ) { 'r' } else { '-' },
+                    if prot.contains(Prot::WRITE) { 'w' } else { '-' },
+                    if prot.contains(Prot::EXEC) { 'x' } else { '-' });
+                let _ = match area.physical {
+                    Some(p) => writeln!(s, " {:016x}", p.as_usize()),
+                    None => writeln!(s, " anon"),
+                };
+            }
+        }
+        _ => unreachable!("unknown /sys/proc file"),
+    }
+    s
+}
+
+/// Queries a single property tag with up to two words of response.
+fn property(tag: Tag) -> Option<[u32; 2]> {
+    Mailbox::new()
+        .tag_message(&[tag as u32, 8, 8, 0, 0])
+        .map(|buf| [buf[5], buf[6]])
+}
+
+fn board(s: &mut String) {
+    if let Some([model, _]) = property(Tag::GET_BOARD_MODEL) {
+        let _ = writeln!(s, "model:    {:#x}", model);
+    }
+    if let Some([revision, _]) = property(Tag::GET_BOARD_REVISION) {
+        let _ = writeln!(s, "revision: {:#x}", revision);
+    }
+    if let Some([lo, hi]) = property(Tag::GET_BOARD_SERIAL) {
+        let _ = writeln!(s, "serial:   {:08x}{:08x}", hi, lo);
+    }
+    if let Some([version, _]) = property(Tag::GET_FIRMWARE_VERSION) {
+        let _ = writeln!(s, "firmware: {:#x}", version);
+    }
+    if let Some([base, size]) = property(Tag::GET_ARM_MEMORY) {
+        let _ = writeln!(s, "arm_mem:  {:#010x} {}", base, size);
+    }
+    if let Some([base, size]) = property(Tag::GET_VC_MEMORY) {
+        let _ = writeln!(s, "vc_mem:   {:#010x} {}", base, size);
+    }
+}
+
+/// A read-only file backed by a generated buffer.
+pub struct Buffer {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl Buffer {
+    pub fn new(data: Vec<u8>) -> Self {
+        Buffer { data, position: 0 }
+    }
+}
+
+impl io::Read for Buffer {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let start = min(self.position, self.data.len());
+        let n = min(buf.len(), self.data.len() - start);
+        buf[..n].copy_from_slice(&self.data[start..start + n]);
+        self.position += n;
+        Ok(n)
+    }
+}
+
+impl io::Write for Buffer {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(read_only())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl io::Seek for Buffer {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            io::SeekFrom::Start(pos) => (pos as i64, 0),
+            io::SeekFrom::End(offset) => (self.data.len() as i64, offset),
+            io::SeekFrom::Current(offset) => (self.position as i64, offset),
+        };
+        match base.checked_add(offset) {
+            Some(pos) if pos >= 0 => {
+                self.position = pos as usize;
+                Ok(pos as u64)
+            }
+            _ => Err(io::Error::new(io::ErrorKind::InvalidInput, "seek before start of file")),
+        }
+    }
+}
+
+impl vfs::File for Buffer {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+}
+
+/// A directory whose entries are fixed when it is opened.
+pub struct Listing(pub Vec<DirEntry>);
+
+impl vfs::Dir for Listing {
+    fn entries(&self) -> io::Result<Vec<DirEntry>> {
+        Ok(self.0.clone())
+    }
+}
@@ -0,0 +1,343 @@
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use sys::io;
+use vfat::traits;
+
+use process::WaitQueue;
+
+/// The kind of a node in the file system.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    File,
+    Dir,
+    /// A device accessed as a stream of bytes.
+    CharDevice,
+    /// A device accessed in fixed-size blocks.
+    BlockDevice,
+}
+
+/// A point in time, independent of the representation used by any particular
+/// file system.
+#[repr(C)]
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Timestamp {
+    pub year: u16,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl Timestamp {
+    /// Converts any `traits::Timestamp` into a `Timestamp`.
+    pub fn from_timestamp<T: traits::Timestamp>(ts: T) -> Self {
+        Timestamp {
+            year: ts.year() as u16,
+            month: ts.month(),
+            day: ts.day(),
+            hour: ts.hour(),
+            minute: ts.minute(),
+            second: ts.second(),
+        }
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize { self.year as usize }
+    fn month(&self) -> u8 { self.month }
+    fn day(&self) -> u8 { self.day }
+    fn hour(&self) -> u8 { self.hour }
+    fn minute(&self) -> u8 { self.minute }
+    fn second(&self) -> u8 { self.second }
+}
+
+/// Metadata of a node as reported by `stat`.
+///
+/// The layout is fixed, as the `stat` and `readdir` system calls copy it to
+/// user space.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct Stat {
+    pub kind: Kind,
+    pub size: u64,
+    pub read_only: bool,
+    pub hidden: bool,
+    pub created: Timestamp,
+    pub accessed: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl Stat {
+    /// Builds a `Stat` from a node's kind, size and `traits::Metadata`.
+    pub fn new<M: traits::Metadata>(kind: Kind, size: u64, meta: &M) -> Self {
+        Stat {
+            kind,
+            size,
+            read_only: meta.read_only(),
+            hidden: meta.hidden(),
+            created: Timestamp::from_timestamp(meta.created()),
+            accessed: Timestamp::from_timestamp(meta.accessed()),
+            modified: Timestamp::from_timestamp(meta.modified()),
+        }
+    }
+
+    /// Returns the `Stat` of a directory that does not exist on any backing
+    /// store, such as a mount point or a directory of a virtual file system.
+    pub fn synthetic(kind: Kind, size: u64) -> Self {
+        Stat {
+            kind,
+            size,
+            read_only: kind == Kind::Dir,
+            hidden: false,
+            created: Timestamp::default(),
+            accessed: Timestamp::default(),
+            modified: Timestamp::default(),
+        }
+    }
+
+    /// Returns the `Stat` of any `traits::Entry`.
+    pub fn of<E: traits::Entry>(entry: &E) -> Self {
+        match entry.as_file() {
+            Some(file) => Stat::new(Kind::File, traits::File::size(file), entry.metadata()),
+            None => Stat::new(Kind::Dir, 0, entry.metadata()),
+        }
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == Kind::Dir
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.kind == Kind::File
+    }
+}
+
+/// A single entry of a directory listing.
+#[derive(Debug, Clone)]
+pub struct DirEntry {
+    pub name: String,
+    pub stat: Stat,
+}
+
+/// Object safe counterpart of `traits::File`.
+pub trait File: io::Read + io::Write + io::Seek + Send {
+    /// Writes any buffered data to the backing store.
+    fn sync(&mut self) -> io::Result<()>;
+
+    /// Returns the size of the file in bytes.
+    fn size(&self) -> u64;
+
+    /// Performs the device specific control operation identified by a request
+    /// number, passing it one argument, and returns its result. The requests
+    /// understood by devices are listed in `fs::devfs::ioctl`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `InvalidInput` if the file does not support
+    /// the request, which is always the case for regular files.
+    fn ioctl(&mut self, _request: u64, _arg: u64) -> io::Result<u64> {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "inappropriate ioctl for device"))
+    }
+
+    /// Returns the queue woken when data arrives, for files whose reads fail
+    /// with `WouldBlock` while there is none.
+    fn read_queue(&self) -> Option<&'static WaitQueue> {
+        None
+    }
+}
+
+impl<T: traits::File + Send> File for T {
+    fn sync(&mut self) -> io::Result<()> {
+        traits::File::sync(self)
+    }
+
+    fn size(&self) -> u64 {
+        traits::File::size(self)
+    }
+}
+
+/// Object safe counterpart of `traits::Dir`.
+pub trait Dir: Send {
+    /// Returns the entries of this directory.
+    fn entries(&self) -> io::Result<Vec<DirEntry>>;
+}
+
+impl<T: traits::Dir + Send> Dir for T {
+    fn entries(&self) -> io::Result<Vec<DirEntry>> {
+        Ok(traits::Dir::entries(self)?
+            .map(|e| DirEntry {
+                name: String::from(traits::Entry::name(&e)),
+                stat: Stat::of(&e),
+            })
+            .collect())
+    }
+}
+
+/// An opened node: either a file or a directory.
+pub enum Node {
+    File(Box<File>),
+    Dir(Box<Dir>),
+}
+
+impl Node {
+    /// Wraps any `traits::Entry` into a `Node`.
+    pub fn from_entry<E>(entry: E) -> Self
+        where E: traits::Entry, E::File: Send + 'static, E::Dir: Send + 'static
+    {
+        if entry.is_file() {
+            Node::File(Box::new(entry.into_file().unwrap()))
+        } else {
+            Node::Dir(Box::new(entry.into_dir().unwrap()))
+        }
+    }
+
+    /// If `self` is a file, returns `Some` of the file. Otherwise returns
+    /// `None`.
+    pub fn into_file(self) -> Option<Box<File>> {
+        match self {
+            Node::File(file) => Some(file),
+            Node::Dir(_) => None,
+        }
+    }
+
+    /// If `self` is a directory, returns `Some` of the directory. Otherwise
+    /// returns `None`.
+    pub fn into_dir(self) -> Option<Box<Dir>> {
+        match self {
+            Node::File(_) => None,
+            Node::Dir(dir) => Some(dir),
+        }
+    }
+}
+
+/// Object safe file system interface used by the mount table.
+///
+/// All paths are absolute and relative to the root of the file system, not
+/// to the root of the namespace it is mounted in.
+pub trait FileSystem: Send {
+    /// Opens the node at `path`.
+    fn open(&self, path: &str) -> io::Result<Node>;
+
+    /// Returns the metadata of the node at `path`.
+    fn stat(&self, path: &str) -> io::Result<Stat>;
+
+    /// Creates a new file at `path`, opens it, and returns it.
+    fn create_file(&self, path: &str) -> io::Result<Box<File>>;
+
+    /// Creates a new directory at `path`, creating all missing parents if
+    /// `parents` is `true`.
+    fn create_dir(&self, path: &str, parents: bool) -> io::Result<()>;
+
+    /// Renames the node at `from` to `to`.
+    fn rename(&self, from: &str, to: &str) -> io::Result<()>;
+
+    /// Removes the node at `path`, recursively if `children` is `true`.
+    fn remove(&self, path: &str, children: bool) -> io::Result<()>;
+}
+
+/// Implements `vfs::FileSystem` for a type `T` where `&T` implements
+/// `traits::FileSystem`.
+macro_rules! filesystem {
+    ($t:ty) => {
+        impl ::fs::vfs::FileSystem for $t {
+            fn open(&self, path: &str) -> ::sys::io::Result<::fs::vfs::Node> {
+                Ok(::fs::vfs::Node::from_entry(::vfat::traits::FileSystem::open(self, path)?))
+            }
+
+            fn stat(&self, path: &str) -> ::sys::io::Result<::fs::vfs::Stat> {
+                Ok(::fs::vfs::Stat::of(&::vfat::traits::FileSystem::open(self, path)?))
+            }
+
+            fn create_file(&self, path: &str) -> ::sys::io::Result<::alloc::boxed::Box<::fs::vfs::File>> {
+                Ok(::alloc::boxed::Box::new(::vfat::traits::FileSystem::create_file(self, path)?))
+            }
+
+            fn create_dir(&self, path: &str, parents: bool) -> ::sys::io::Result<()> {
+                ::vfat::traits::FileSystem::create_dir(self, path, parents).map(|_| ())
+            }
+
+            fn rename(&self, from: &str, to: &str) -> ::sys::io::Result<()> {
+                ::vfat::traits::FileSystem::rename(self, from, to)
+            }
+
+            fn remove(&self, path: &str, children: bool) -> ::sys::io::Result<()> {
+                ::vfat::traits::FileSystem::remove(self, path, children)
+            }
+        }
+    }
+}
+
+/// Normalizes the absolute path `path`, resolving `.` and `..` components and
+/// removing redundant slashes.
+///
+/// # Errors
+///
+/// Returns an error of kind `InvalidInput` if `path` is not absolute.
+pub fn normalize(path: &str) -> io::Result<String> {
+    if !path.starts_with('/') {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "path must be absolute"));
+    }
+
+    let mut components: Vec<&str> = Vec::new();
+    for c in path.split('/') {
+        match c {
+            "" | "." => (),
+            ".." => { components.pop(); }
+            c => components.push(c),
+        }
+    }
+
+    let mut result = String::new();
+    for c in components {
+        result.push('/');
+        result.push_str(c);
+    }
+    if result.is_empty() {
+        result.push('/');
+    }
+    Ok(result)
+}
+
+/// Returns the path of `path` relative to the mount point `mount`, or `None`
+/// if `path` is not below `mount`. Both paths must be normalized.
+pub fn strip_mount<'a>(mount: &str, path: &'a str) -> Option<&'a str> {
+    if mount == "/" {
+        return Some(path);
+    }
+    if !path.starts_with(mount) {
+        return None;
+    }
+    match &path[mount.len()..] {
+        "" => Some("/"),
+        rest if rest.starts_with('/') => Some(rest),
+        _ => None,
+    }
+}
+
+/// A directory overlaid with the mount points directly below it.
+pub struct MountDir {
+    pub inner: Option<Box<Dir>>,
+    pub mounts: Vec<String>,
+}
+
+impl Dir for MountDir {
+    fn entries(&self) -> io::Result<Vec<DirEntry>> {
+        let mut entries = match self.inner {
+            Some(ref dir) => dir.entries()?,
+            None => Vec::new(),
+        };
+        for name in self.mounts.iter() {
+            if !entries.iter().any(|e| &e.name == name) {
+                entries.push(DirEntry {
+                    name: name.clone(),
+                    stat: Stat::synthetic(Kind::Dir, 0),
+                });
+            }
+        }
+        Ok(entries)
+    }
+}
@@ -40,9 +40,8 @@ extern crate alloc;
 #[cfg(not(test))] pub mod panic;
 #[cfg(not(test))] pub mod fb;
 #[cfg(not(test))] pub mod user;
+#[cfg(not(test))] pub mod smp;
 
-//pub mod sd;
-//pub mod sdn;
 //pub mod gles;
 
 #[cfg(not(test))] pub mod fs;
@@ -67,28 +66,7 @@ pub extern "C" fn kernel_main() -> ! {
     //dbg::print_memory();
 
     vm::initialize();
-
-    unsafe {
-        asm!("
-            bl      0f
-            b       1f
-        0:
-            mov     x1, sp
-            movk    x1, #0xFFFF, LSL #48
-            movk    x1, #0xFF80, LSL #32
-            mov     sp, x1
-
-            mrs     x1, VBAR_EL1
-            movk    x1, #0xFFFF, LSL #48
-            movk    x1, #0xFF80, LSL #32
-            msr     VBAR_EL1, x1
-
-            movk    x30, #0xFFFF, LSL #48
-            movk    x30, #0xFF80, LSL #32
-            ret
-        1:
-        " : : : "x1" : "volatile");
-    }
+    enter_higher_half();
 
     ALLOCATOR.initialize();
     //dbg::test_alloc();
@@ -106,14 +84,56 @@ pub extern "C" fn kernel_main() -> ! {
     pi::timer::spin_sleep_ms(200);
 
     use pi::interrupt::{Controller, Interrupt};
-    use pi::timer::tick_in;
 
-    Controller::new().enable(Interrupt::Timer1);
-    tick_in(process::TICK);
+    smp::initialize_core();
+    pi::local::tick_in(process::TICK);
+
+    console::CONSOLE.lock().unwrap().enable_interrupts();
+    Controller::new().enable(Interrupt::Aux);
 
     SCHEDULER.start()
 }
 
+/// The entry of the secondary cores, released by `smp::start_secondaries`
+/// once the scheduler runs on core 0.
+#[cfg(not(test))]
+#[no_mangle]
+pub extern "C" fn kernel_secondary(core: usize) -> ! {
+    vm::initialize_core();
+    enter_higher_half();
+
+    kprintln!("---- CPU{}: ----", core);
+    smp::initialize_core();
+    SCHEDULER.start_core()
+}
+
+/// Moves the stack, the exception vectors and the return address of the
+/// caller to the higher half, where the kernel is mapped for every process.
+#[cfg(not(test))]
+#[inline(always)]
+fn enter_higher_half() {
+    unsafe {
+        asm!("
+            bl      0f
+            b       1f
+        0:
+            mov     x1, sp
+            movk    x1, #0xFFFF, LSL #48
+            movk    x1, #0xFF80, LSL #32
+            mov     sp, x1
+
+            mrs     x1, VBAR_EL1
+            movk    x1, #0xFFFF, LSL #48
+            movk    x1, #0xFF80, LSL #32
+            msr     VBAR_EL1, x1
+
+            movk    x30, #0xFFFF, LSL #48
+            movk    x30, #0xFF80, LSL #32
+            ret
+        1:
+        " : : : "x1", "x30" : "volatile");
+    }
+}
 
 #[cfg(not(test))]
 mod dbg {
@@ -175,12 +195,10 @@ mod dbg {
 
     pub fn test_fs() {
         kprint!("test fs: ls /");
-        use vfat::traits::*;
-
-        match ::FILE_SYSTEM.open_dir("").and_then(|e| e.entries()) {
+        match ::FILE_SYSTEM.open_dir("/").and_then(|e| e.entries()) {
             Ok(entries) => {
                 for e in entries {
-                    kprintln!("   /{}", e.name());
+                    kprintln!("   /{}", e.name);
                 }
             }
             Err(err) => kprintln!("ls: {:?}", err),
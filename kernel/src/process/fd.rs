@@ -0,0 +1,165 @@
+use core::cmp::min;
+use core::fmt;
+
+use alloc::boxed::Box;
+use alloc::arc::Arc;
+use alloc::vec::Vec;
+
+use sys::io;
+use vfat::vfat::Shared;
+
+use fs::vfs::{self, DirEntry};
+use fs::pipe;
+use process::ipc;
+use vm::SharedMemory;
+
+/// The maximum number of files a process may have open at once.
+pub const MAX_FILES: usize = 64;
+
+/// An opened node, shared by every descriptor referring to it.
+pub enum OpenFile {
+    File(Box<vfs::File>),
+    /// A directory listing, read one entry at a time.
+    Dir {
+        entries: Vec<DirEntry>,
+        position: usize,
+    },
+    /// The read end of a pipe.
+    PipeReader(pipe::Reader),
+    /// The write end of a pipe.
+    PipeWriter(pipe::Writer),
+    /// The server end of an IPC port.
+    PortServer(ipc::Server),
+    /// A client end of an IPC port.
+    PortClient(ipc::Client),
+    /// A shared memory object, mapped with `mmap`.
+    SharedMemory(Arc<SharedMemory>),
+}
+
+impl OpenFile {
+    /// Wraps an opened node, reading the entries of directories.
+    pub fn new(node: vfs::Node) -> io::Result<Self> {
+        Ok(match node {
+            vfs::Node::File(file) => OpenFile::File(file),
+            vfs::Node::Dir(dir) => OpenFile::Dir { entries: dir.entries()?, position: 0 },
+        })
+    }
+}
+
+/// A handle to an `OpenFile`.
+pub type Handle = Shared<OpenFile>;
+
+/// An entry of a descriptor table.
+#[derive(Clone)]
+struct Descriptor {
+    handle: Handle,
+    close_on_exec: bool,
+}
+
+/// The file descriptor table of a process: maps small integers to open files.
+#[derive(Clone)]
+pub struct FdTable {
+    files: Vec<Option<Descriptor>>,
+    /// The number of descriptors that may be opened, at most `MAX_FILES`.
+    limit: usize,
+}
+
+impl FdTable {
+    /// Returns an empty table.
+    pub fn new() -> Self {
+        FdTable { files: Vec::new(), limit: MAX_FILES }
+    }
+
+    /// Returns a table with `/dev/console` open as descriptors 0, 1 and 2.
+    pub fn stdio() -> io::Result<Self> {
+        let console = Shared::new(OpenFile::new(::FILE_SYSTEM.open("/dev/console")?)?);
+        let mut files = Vec::new();
+        files.try_reserve(3).map_err(|_| io::Error::new(io::ErrorKind::Other, "out of memory"))?;
+        for _ in 0..3 {
+            files.push(Some(Descriptor { handle: console.clone(), close_on_exec: false }));
+        }
+        Ok(FdTable { files, limit: MAX_FILES })
+    }
+
+    /// Limits the descriptors that may be opened to those below `limit`, at
+    /// most `MAX_FILES`. Descriptors open above the limit stay open.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = min(limit, MAX_FILES);
+    }
+
+    /// Stores `handle` in the lowest free descriptor and returns it, or
+    /// returns `None` if the table is full. If `close_on_exec` is set, the
+    /// descriptor is closed when the process executes a new program.
+    pub fn insert(&mut self, handle: Handle, close_on_exec: bool) -> Option<usize> {
+        let descriptor = Descriptor { handle, close_on_exec };
+        let limit = self.limit;
+        if let Some(fd) = self.files.iter().take(limit).position(|f| f.is_none()) {
+            self.files[fd] = Some(descriptor);
+            return Some(fd);
+        }
+        if self.files.len() >= limit || self.files.try_reserve(1).is_err() {
+            return None;
+        }
+        self.files.push(Some(descriptor));
+        Some(self.files.len() - 1)
+    }
+
+    /// Stores `handle` in the descriptor `fd` and returns the handle it
+    /// replaces, if any. Returns `None` if `fd` is beyond the limit of the
+    /// table or there is no memory to extend it.
+    pub fn replace(&mut self, fd: usize, handle: Handle, close_on_exec: bool) -> Option<Option<Handle>> {
+        if fd >= self.limit {
+            return None;
+        }
+        if fd >= self.files.len() {
+            self.files.try_reserve(fd + 1 - self.files.len()).ok()?;
+            while self.files.len() <= fd {
+                self.files.push(None);
+            }
+        }
+        let old = self.files[fd].take();
+        self.files[fd] = Some(Descriptor { handle, close_on_exec });
+        Some(old.map(|f| f.handle))
+    }
+
+    /// Returns the handle behind the descriptor `fd`.
+    pub fn get(&self, fd: usize) -> Option<Handle> {
+        self.files.get(fd).and_then(|f| f.as_ref()).map(|f| f.handle.clone())
+    }
+
+    /// Closes the descriptor `fd` and returns its handle.
+    pub fn remove(&mut self, fd: usize) -> Option<Handle> {
+        let descriptor = self.files.get_mut(fd)?.take();
+        self.trim();
+        descriptor.map(|f| f.handle)
+    }
+
+    /// Closes the descriptors marked close-on-exec and returns their handles.
+    pub fn close_on_exec(&mut self) -> Vec<Handle> {
+        let mut closed = Vec::new();
+        for file in self.files.iter_mut() {
+            if file.as_ref().map_or(false, |f| f.close_on_exec) {
+                closed.extend(file.take().map(|f| f.handle));
+            }
+        }
+        self.trim();
+        closed
+    }
+
+    fn trim(&mut self) {
+        while self.files.last().map_or(false, |f| f.is_none()) {
+            self.files.pop();
+        }
+    }
+}
+
+impl fmt::Debug for FdTable {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let open: Vec<usize> = self.files.iter()
+            .enumerate()
+            .filter(|&(_, file)| file.is_some())
+            .map(|(fd, _)| fd)
+            .collect();
+        write!(f, "FdTable{:?}", open)
+    }
+}
@@ -0,0 +1,62 @@
+use alloc::BTreeMap;
+
+use sys::Mutex;
+use process::{Id, WaitQueue};
+use vm::PhysicalAddr;
+
+/// The processes waiting on a futex, by the physical address of the futex
+/// word: processes mapping the same memory at different addresses wait on
+/// the same futex. Queues are removed once nobody waits on them.
+static FUTEXES: Mutex<Option<BTreeMap<PhysicalAddr, WaitQueue>>> = Mutex::new(None);
+
+/// Queues the process `id` on the futex at `key` if `check` returns `true`,
+/// and returns what `check` returned. As `wake` is excluded meanwhile, a
+/// wakeup following a change of the futex word `check` did not see is not
+/// lost.
+pub fn wait<F: FnOnce() -> bool>(key: PhysicalAddr, id: Id, check: F) -> bool {
+    let mut guard = FUTEXES.lock().unwrap();
+    if !check() {
+        return false;
+    }
+    guard.get_or_insert_with(BTreeMap::new)
+        .entry(key)
+        .or_insert_with(WaitQueue::new)
+        .enqueue(id);
+    true
+}
+
+/// Removes the process `id` from the futex at `key` once it stops waiting,
+/// woken or not, so that later wakeups go to the processes still waiting.
+pub fn cancel(key: PhysicalAddr, id: Id) {
+    let mut guard = FUTEXES.lock().unwrap();
+    if let Some(futexes) = guard.as_mut() {
+        let empty = match futexes.get(&key) {
+            Some(queue) => {
+                queue.remove(id);
+                queue.is_empty()
+            }
+            None => false,
+        };
+        if empty {
+            futexes.remove(&key);
+        }
+    }
+}
+
+/// Wakes up to `n` processes waiting on the futex at `key`, the longest
+/// waiting first, and returns how many were woken.
+pub fn wake(key: PhysicalAddr, n: usize) -> usize {
+    let mut guard = FUTEXES.lock().unwrap();
+    let futexes = match guard.as_mut() {
+        Some(futexes) => futexes,
+        None => return 0,
+    };
+    let (woken, empty) = match futexes.get(&key) {
+        Some(queue) => (queue.wake(n), queue.is_empty()),
+        None => return 0,
+    };
+    if empty {
+        futexes.remove(&key);
+    }
+    woken
+}
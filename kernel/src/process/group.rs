@@ -0,0 +1,137 @@
+use core::fmt;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+use sys::{Mutex, MutexGuard};
+use process::{Id, FdTable, WaitQueue};
+use process::rlimit::{Limits, Limit};
+use vm::Memory;
+
+/// What the threads of a process share: the address space, the open files,
+/// the resource limits and the CPU time, the exit codes of the threads no
+/// thread joined yet and the exit code of the process. A process starts with
+/// a group of its own; `thread_spawn` adds threads to it.
+pub struct Group {
+    mm: Mutex<Box<Memory>>,
+    files: Mutex<FdTable>,
+    limits: Mutex<Limits>,
+    /// The CPU time used by the threads of the process, in microseconds.
+    cpu_time: Mutex<u64>,
+    exited: Mutex<Vec<(Id, u32)>>,
+    /// The exit code of the process, once one of its threads called `exit`.
+    exit_code: Mutex<Option<u32>>,
+    /// Woken when a thread of the group exits.
+    joined: WaitQueue,
+}
+
+impl Group {
+    /// Returns a group of the address space `mm` and the files `files`,
+    /// limited by `limits`, that used no CPU time yet.
+    pub fn new(mm: Box<Memory>, files: FdTable, limits: Limits) -> Self {
+        Self {
+            mm: Mutex::new(mm),
+            files: Mutex::new(files),
+            limits: Mutex::new(limits),
+            cpu_time: Mutex::new(0),
+            exited: Mutex::new(Vec::new()),
+            exit_code: Mutex::new(None),
+            joined: WaitQueue::new(),
+        }
+    }
+
+    /// Locks the address space of the group.
+    pub fn mm(&self) -> MutexGuard<Box<Memory>> {
+        self.mm.lock().unwrap()
+    }
+
+    /// Locks the file descriptor table of the group.
+    pub fn files(&self) -> MutexGuard<FdTable> {
+        self.files.lock().unwrap()
+    }
+
+    /// Returns the resource limits of the process.
+    pub fn limits(&self) -> Limits {
+        *self.limits.lock().unwrap()
+    }
+
+    /// Sets the limit of `resource` to `limit` and enforces it from now on.
+    /// Returns the previous limit, or `None` if there is no such resource.
+    pub fn set_limit(&self, resource: u64, limit: Limit) -> Option<Limit> {
+        // the limits stay locked so that the latest limit is the one enforced
+        let mut limits = self.limits.lock().unwrap();
+        let old = limits.set(resource, limit)?;
+        self.mm().set_page_limit(limits.pages());
+        self.files().set_limit(limits.files());
+        Some(old)
+    }
+
+    /// Takes over the resource limits and the CPU time of `old`, the group of
+    /// the program this one replaces, and enforces the limits.
+    pub fn inherit(&self, old: &Group) {
+        let limits = old.limits();
+        let cpu_time = *old.cpu_time.lock().unwrap();
+        *self.limits.lock().unwrap() = limits;
+        *self.cpu_time.lock().unwrap() = cpu_time;
+        self.mm().set_page_limit(limits.pages());
+        self.files().set_limit(limits.files());
+    }
+
+    /// Charges the process for one of its threads running `ran`
+    /// microseconds. Returns the signal to send to the process if its CPU
+    /// time exceeds its limit.
+    pub fn charge(&self, ran: u64) -> Option<u32> {
+        let mut cpu_time = self.cpu_time.lock().unwrap();
+        *cpu_time += ran;
+        self.limits.lock().unwrap().check_cpu(*cpu_time)
+    }
+
+    /// Records that the thread `id` exited with `code` and wakes the threads
+    /// joining it.
+    pub fn exited(&self, id: Id, code: u32) {
+        self.exited.lock().unwrap().push((id, code));
+        self.joined.wake_all();
+    }
+
+    /// Queues the thread `joiner` until a thread of the group exits.
+    pub fn wait_join(&self, joiner: Id) {
+        self.joined.enqueue(joiner);
+    }
+
+    /// Returns the exit code of the thread `id`, forgetting it, if the thread
+    /// exited.
+    pub fn take_exited(&self, id: Id) -> Option<u32> {
+        let mut exited = self.exited.lock().unwrap();
+        let i = exited.iter().position(|&(thread, _)| thread == id)?;
+        Some(exited.remove(i).1)
+    }
+
+    /// Removes the thread `joiner` from the queue of the joining threads.
+    pub fn cancel_join(&self, joiner: Id) {
+        self.joined.remove(joiner);
+    }
+
+    /// Records that the process exits with `code`, unless another thread
+    /// did first. The main thread reports this code when it exits, whatever
+    /// ended it.
+    pub fn exit(&self, code: u32) {
+        let mut exit_code = self.exit_code.lock().unwrap();
+        if exit_code.is_none() {
+            *exit_code = Some(code);
+        }
+    }
+
+    /// Returns the exit code recorded by `exit`, if a thread called it.
+    pub fn exit_code(&self) -> Option<u32> {
+        *self.exit_code.lock().unwrap()
+    }
+}
+
+impl fmt::Debug for Group {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.mm.try_lock() {
+            Some(mm) => write!(f, "Group({:?})", *mm),
+            None => write!(f, "Group(locked)"),
+        }
+    }
+}
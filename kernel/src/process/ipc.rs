@@ -0,0 +1,308 @@
+use alloc::arc::Arc;
+use alloc::string::String;
+use alloc::vec::Vec;
+use alloc::VecDeque;
+
+use sys::io;
+use sys::Mutex;
+use process::{Process, Id, Handle, WaitQueue};
+use vm::Page;
+
+/// The message carries no capability.
+pub const CAP_NONE: u64 = 0;
+/// The message carries an open file: `value` is its descriptor.
+pub const CAP_FD: u64 = 1;
+/// The message carries a page: `value` is its page aligned address.
+pub const CAP_PAGE: u64 = 2;
+
+/// Where pages received in messages are mapped, on the first free one of
+/// `PAGE_SLOTS` pages.
+const PAGE_BASE: usize = 5 * 0x4000_0000;
+const PAGE_SLOTS: usize = 4096;
+
+/// A message exchanged through a port: four words of data and optionally a
+/// capability, moved from the sender to the receiver.
+///
+/// The layout is fixed, as the port system calls copy it from and to user
+/// space.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Message {
+    pub data: [u64; 4],
+    /// The kind of capability carried: `CAP_NONE`, `CAP_FD` or `CAP_PAGE`.
+    pub cap: u64,
+    /// The descriptor or the address of the capability in the sender, then
+    /// in the receiver.
+    pub value: u64,
+}
+
+/// A capability in transit.
+pub enum Capability {
+    /// An open file, shared by the sender and the receiver.
+    Fd(Handle),
+    /// A page, unmapped from the sender.
+    Page(Page),
+}
+
+/// A message in transit and its sender.
+pub struct Envelope {
+    pub sender: Id,
+    pub message: Message,
+    pub cap: Option<Capability>,
+}
+
+/// Delivers `envelope` to `process`: installs the capability it carries in
+/// the files or the address space of the process and returns the message
+/// as the process sees it. A capability the process has no room for is
+/// dropped, and the message carries none.
+pub fn deliver(process: &mut Process, envelope: Envelope) -> Message {
+    let mut message = envelope.message;
+    let installed = match envelope.cap {
+        None => None,
+        Some(Capability::Fd(handle)) => {
+            process.files().insert(handle, false).map(|fd| (CAP_FD, fd as u64))
+        }
+        Some(Capability::Page(page)) => {
+            process.mm().give_page(page, PAGE_BASE, PAGE_SLOTS).map(|addr| (CAP_PAGE, addr as u64))
+        }
+    };
+    let (cap, value) = installed.unwrap_or((CAP_NONE, 0));
+    message.cap = cap;
+    message.value = value;
+    message
+}
+
+/// The messages of a port.
+struct Queues {
+    /// The calls sent and not received yet.
+    pending: VecDeque<Envelope>,
+    /// The clients whose call was received and not replied to yet.
+    calls: Vec<Id>,
+    /// The replies not picked up by their client yet.
+    replies: Vec<Envelope>,
+    /// Whether the server end is still open.
+    open: bool,
+}
+
+/// A named endpoint: clients call the server of the port, which receives
+/// the calls one at a time and replies to each. Calls are synchronous: the
+/// client waits until the server replies.
+pub struct Port {
+    name: String,
+    queues: Mutex<Queues>,
+    /// Woken when a call is sent.
+    received: WaitQueue,
+    /// Woken when a call is replied to or the port is closed.
+    replied: WaitQueue,
+}
+
+/// The ports that have a server, by name.
+static PORTS: Mutex<Option<Vec<Arc<Port>>>> = Mutex::new(None);
+
+/// Creates the port `name` and returns its server end.
+///
+/// # Errors
+///
+/// Fails with `AlreadyExists` if a port of that name has a server.
+pub fn create(name: &str) -> io::Result<Server> {
+    let mut guard = PORTS.lock().unwrap();
+    let ports = guard.get_or_insert_with(Vec::new);
+    if ports.iter().any(|port| port.name == name) {
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, "port exists"));
+    }
+    let port = Arc::new(Port {
+        name: String::from(name),
+        queues: Mutex::new(Queues {
+            pending: VecDeque::new(),
+            calls: Vec::new(),
+            replies: Vec::new(),
+            open: true,
+        }),
+        received: WaitQueue::new(),
+        replied: WaitQueue::new(),
+    });
+    ports.push(port.clone());
+    Ok(Server(port))
+}
+
+/// Returns a client end of the port `name`.
+///
+/// # Errors
+///
+/// Fails with `NotFound` if no port of that name has a server.
+pub fn connect(name: &str) -> io::Result<Client> {
+    let guard = PORTS.lock().unwrap();
+    guard.as_ref()
+        .and_then(|ports| ports.iter().find(|port| port.name == name))
+        .map(|port| Client(port.clone()))
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such port"))
+}
+
+fn closed() -> io::Error {
+    io::Error::new(io::ErrorKind::BrokenPipe, "port closed")
+}
+
+/// Where the call of a client stands.
+pub enum CallState {
+    /// The client has no call in progress.
+    Idle,
+    /// The call was not replied to yet. The client is queued until it is.
+    Waiting,
+    /// The reply to the call.
+    Replied(Envelope),
+}
+
+impl Port {
+    /// Returns where the call of `client` stands. Checking for the reply and
+    /// queueing the client are atomic with respect to replies.
+    ///
+    /// # Errors
+    ///
+    /// Fails with `BrokenPipe` if the server end is closed and the call was
+    /// not replied to.
+    pub fn call_state(&self, client: Id) -> io::Result<CallState> {
+        let mut queues = self.queues.lock().unwrap();
+        if let Some(i) = queues.replies.iter().position(|reply| reply.sender == client) {
+            return Ok(CallState::Replied(queues.replies.remove(i)));
+        }
+        if !queues.open {
+            return Err(closed());
+        }
+        let waiting = queues.calls.contains(&client)
+            || queues.pending.iter().any(|call| call.sender == client);
+        if waiting {
+            self.replied.enqueue(client);
+            Ok(CallState::Waiting)
+        } else {
+            Ok(CallState::Idle)
+        }
+    }
+
+    /// Sends the call `envelope` to the server and queues its sender until
+    /// the reply.
+    ///
+    /// # Errors
+    ///
+    /// Fails if the server end is closed, handing `envelope` back so that
+    /// the sender gets its capability back.
+    pub fn send(&self, envelope: Envelope) -> Result<(), Envelope> {
+        {
+            let mut queues = self.queues.lock().unwrap();
+            if !queues.open {
+                return Err(envelope);
+            }
+            self.replied.enqueue(envelope.sender);
+            queues.pending.push_back(envelope);
+        }
+        self.received.wake_all();
+        Ok(())
+    }
+
+    /// Takes the next call, remembering that its sender waits for a reply.
+    /// If there is none, queues the process `server` until one is sent and
+    /// returns `None`.
+    pub fn receive(&self, server: Id) -> Option<Envelope> {
+        let mut queues = self.queues.lock().unwrap();
+        match queues.pending.pop_front() {
+            Some(call) => {
+                queues.calls.push(call.sender);
+                Some(call)
+            }
+            None => {
+                self.received.enqueue(server);
+                None
+            }
+        }
+    }
+
+    /// Replies `envelope` to the received call of `client` and wakes the
+    /// client.
+    ///
+    /// # Errors
+    ///
+    /// Fails if no call of `client` waits for a reply, because the client
+    /// exited for instance, handing `envelope` back so that the server gets
+    /// its capability back.
+    pub fn reply(&self, client: Id, envelope: Envelope) -> Result<(), Envelope> {
+        {
+            let mut queues = self.queues.lock().unwrap();
+            let i = match queues.calls.iter().position(|&id| id == client) {
+                Some(i) => i,
+                None => return Err(envelope),
+            };
+            queues.calls.remove(i);
+            queues.replies.push(Envelope { sender: client, ..envelope });
+        }
+        self.replied.wake_all();
+        Ok(())
+    }
+}
+
+/// Forgets the calls of the process `client`, which exited: its calls not
+/// received yet are dropped, replies to its received calls fail and the
+/// replies it did not pick up are released, along with the capabilities they
+/// carry.
+pub fn forget(client: Id) {
+    let ports = match PORTS.lock().unwrap().as_ref() {
+        Some(ports) => ports.clone(),
+        None => return,
+    };
+    let mut forgotten = Vec::new();
+    for port in ports {
+        let mut queues = port.queues.lock().unwrap();
+        queues.calls.retain(|&id| id != client);
+        let pending = queues.pending.split_off(0);
+        for call in pending {
+            if call.sender == client {
+                forgotten.push(call);
+            } else {
+                queues.pending.push_back(call);
+            }
+        }
+        while let Some(i) = queues.replies.iter().position(|reply| reply.sender == client) {
+            forgotten.push(queues.replies.remove(i));
+        }
+    }
+    // the capabilities in transit are released outside of the locks
+    drop(forgotten);
+}
+
+/// The server end of a port. The port is closed and its name released when
+/// it is dropped: the calls not replied to fail.
+pub struct Server(Arc<Port>);
+
+impl Server {
+    pub fn port(&self) -> Arc<Port> {
+        self.0.clone()
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        {
+            let mut guard = PORTS.lock().unwrap();
+            if let Some(ports) = guard.as_mut() {
+                ports.retain(|port| !Arc::ptr_eq(port, &self.0));
+            }
+        }
+        let pending = {
+            let mut queues = self.0.queues.lock().unwrap();
+            queues.open = false;
+            queues.calls.clear();
+            queues.pending.split_off(0)
+        };
+        // the capabilities in transit are released outside of the lock
+        drop(pending);
+        self.0.replied.wake_all();
+    }
+}
+
+/// The client end of a port.
+pub struct Client(Arc<Port>);
+
+impl Client {
+    pub fn port(&self) -> Arc<Port> {
+        self.0.clone()
+    }
+}
+
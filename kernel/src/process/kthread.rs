@@ -0,0 +1,48 @@
+use process::{Process, Id, WaitQueue};
+use SCHEDULER;
+
+/// The function a kernel thread runs, called with the argument given to
+/// `spawn`.
+pub type ThreadFn = extern "C" fn(u64);
+
+/// Starts a kernel thread calling `entry` with `arg` and returns its id, or
+/// returns `None` if the thread could not be created.
+///
+/// Kernel threads are scheduled next to user processes but run at EL1 with
+/// interrupts masked: they are never preempted and must give up the CPU by
+/// calling `yield_now`, `sleep`, `wait` or `exit`. A wakeup arriving between
+/// checking for work and calling `wait`, from an interrupt or another core,
+/// is kept by the scheduler until the thread waits.
+pub fn spawn(entry: ThreadFn, arg: u64) -> Option<Id> {
+    SCHEDULER.add(Process::kernel(entry, arg)?)
+}
+
+/// Gives up the CPU to the other ready processes.
+pub fn yield_now() {
+    unsafe { asm!("svc 0" ::: "x0", "x7" : "volatile"); }
+}
+
+/// Sleeps for `ms` milliseconds.
+pub fn sleep(ms: u32) {
+    unsafe {
+        asm!("mov x0, $0
+              svc 1"
+             :: "r"(ms as u64) : "x0", "x7" : "volatile");
+    }
+}
+
+/// Blocks until `queue` is woken.
+pub fn wait(queue: &'static WaitQueue) {
+    unsafe {
+        asm!("mov x0, $0
+              svc 2"
+             :: "r"(queue as *const WaitQueue) : "x0", "x7" : "volatile");
+    }
+}
+
+/// Ends the calling kernel thread. Threads returning from their function end
+/// here.
+pub extern "C" fn exit() -> ! {
+    unsafe { asm!("svc 3" :::: "volatile"); }
+    unreachable!("kernel thread exited");
+}
@@ -1,9 +1,22 @@
 mod process;
 mod state;
 mod scheduler;
+mod policy;
 mod stack;
+mod fd;
+mod wait_queue;
+mod group;
+pub mod kthread;
+pub mod signal;
+pub mod rlimit;
+pub mod ipc;
+pub mod futex;
 
-pub use self::process::{Process, Id};
-pub use self::state::State;
+pub use self::process::{Process, Id, STACK_BOTTOM, STACK_TOP};
+pub use self::state::{State, Wait, Wakeup, WakeFn};
 pub use self::scheduler::{GlobalScheduler, TICK};
+pub use self::policy::{Policy, RoundRobin, Fair, SchedInfo, NICE_MIN, NICE_MAX};
 pub use self::stack::Stack;
+pub use self::fd::{FdTable, OpenFile, Handle, MAX_FILES};
+pub use self::wait_queue::{WaitQueue, take_woken};
+pub use self::group::Group;
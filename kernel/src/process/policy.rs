@@ -0,0 +1,134 @@
+use core::cmp::max;
+use core::fmt;
+
+use alloc::VecDeque;
+
+use process::{Process, TICK};
+
+/// The nice value of the processes getting the largest share of CPU time.
+pub const NICE_MIN: i8 = -20;
+/// The nice value of the processes getting the smallest share of CPU time.
+pub const NICE_MAX: i8 = 19;
+
+/// The scheduling parameters and the CPU time accounting of a process. The
+/// CPU time a process is limited by is kept by its `Group`.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SchedInfo {
+    /// The priority of the process, from `NICE_MIN` to `NICE_MAX`: the lower
+    /// the value, the larger the share of CPU time the process gets.
+    pub nice: i8,
+    /// The CPU time used by the process scaled by its weight, in
+    /// microseconds. Used by `Fair`.
+    pub vruntime: u64,
+    /// The time the process was last switched in, in microseconds.
+    pub started: u64,
+}
+
+/// A scheduling policy: decides which process runs next and for how long.
+pub trait Policy: fmt::Debug {
+    /// Returns the index of the process in `processes` to run next and the
+    /// length of its time slice in microseconds, or `None` if no process is
+    /// ready. Processes are ready if `Process::is_ready()` returns `true`.
+    fn pick(&mut self, processes: &mut VecDeque<Process>) -> Option<(usize, u32)>;
+
+    /// Charges `process` for running `ran` microseconds.
+    fn charge(&mut self, process: &mut Process, ran: u64);
+
+    /// Prepares `process`, moved from the queue of another core, to be
+    /// scheduled with the processes of this one.
+    fn migrated(&mut self, process: &mut Process);
+}
+
+/// Runs the ready processes one after another for a tick each, ignoring
+/// their priority.
+#[derive(Debug)]
+pub struct RoundRobin;
+
+impl Policy for RoundRobin {
+    fn pick(&mut self, processes: &mut VecDeque<Process>) -> Option<(usize, u32)> {
+        processes.iter_mut().position(|p| p.is_ready()).map(|i| (i, TICK))
+    }
+
+    fn charge(&mut self, _process: &mut Process, _ran: u64) {}
+
+    fn migrated(&mut self, _process: &mut Process) {}
+}
+
+/// The weights of the nice values from `NICE_MIN` to `NICE_MAX`. A process
+/// gets about 10% more CPU time than a process with the next nice value.
+const WEIGHTS: [u64; 40] = [
+    88761, 71755, 56483, 46273, 36291, 29154, 23254, 18705, 14949, 11916,
+     9548,  7620,  6100,  4904,  3906,  3121,  2501,  1991,  1586,  1277,
+     1024,   820,   655,   526,   423,   335,   272,   215,   172,   137,
+      110,    87,    70,    56,    45,    36,    29,    23,    18,    15,
+];
+
+/// The weight of nice value 0.
+const NICE_0_WEIGHT: u64 = 1024;
+
+/// The period, in microseconds, in which every ready process runs once.
+const LATENCY: u64 = 4 * TICK as u64;
+
+/// The shortest time slice, in microseconds.
+const MIN_SLICE: u64 = TICK as u64 / 4;
+
+/// How far, in microseconds of virtual runtime, a process that waited may
+/// fall behind the others. It catches up quickly, but does not monopolize
+/// the CPU.
+const SLEEPER_CREDIT: u64 = LATENCY / 2;
+
+/// Returns the weight of `nice`.
+fn weight(nice: i8) -> u64 {
+    WEIGHTS[(nice - NICE_MIN) as usize]
+}
+
+/// Shares the CPU time between the ready processes in proportion to their
+/// weights: runs the process with the lowest virtual runtime, which advances
+/// slower the higher the weight of the process.
+#[derive(Debug)]
+pub struct Fair {
+    /// The virtual runtime of the last picked process. Processes that were
+    /// not ready are moved up close to it.
+    min_vruntime: u64,
+}
+
+impl Fair {
+    pub fn new() -> Self {
+        Fair { min_vruntime: 0 }
+    }
+}
+
+impl Policy for Fair {
+    fn pick(&mut self, processes: &mut VecDeque<Process>) -> Option<(usize, u32)> {
+        let floor = self.min_vruntime.saturating_sub(SLEEPER_CREDIT);
+        let mut next: Option<(usize, u64)> = None;
+        let mut total = 0;
+        for (i, process) in processes.iter_mut().enumerate() {
+            if !process.is_ready() {
+                continue;
+            }
+            let sched = &mut process.sched;
+            sched.vruntime = max(sched.vruntime, floor);
+            total += weight(sched.nice);
+            if next.map_or(true, |(_, vruntime)| sched.vruntime < vruntime) {
+                next = Some((i, sched.vruntime));
+            }
+        }
+
+        let (i, vruntime) = next?;
+        self.min_vruntime = max(self.min_vruntime, vruntime);
+        let slice = LATENCY * weight(processes[i].sched.nice) / total;
+        Some((i, max(slice, MIN_SLICE) as u32))
+    }
+
+    fn charge(&mut self, process: &mut Process, ran: u64) {
+        process.sched.vruntime += ran * NICE_0_WEIGHT / weight(process.sched.nice);
+    }
+
+    /// The virtual runtime of the process is relative to the processes of
+    /// the core it left: it starts over from the one of the last process
+    /// picked here, neither ahead of nor behind the processes of this core.
+    fn migrated(&mut self, process: &mut Process) {
+        process.sched.vruntime = self.min_vruntime;
+    }
+}
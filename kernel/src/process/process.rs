@@ -1,10 +1,17 @@
-use core::{mem, fmt};
+use core::{mem, fmt, slice};
 use core::num::NonZeroU64;
 use traps::TrapFrame;
-use process::{State, Stack};
+use process::{State, Wakeup, Stack, FdTable, Handle, SchedInfo, Group, WaitQueue};
+use process::kthread::{self, ThreadFn};
+use process::signal::{Signals, SignalFrame, Delivery, Action, SIGSEGV};
+use process::rlimit::Limits;
+use alloc::vec::Vec;
+use alloc::arc::Arc;
 use alloc::boxed::Box;
+use alloc::string::String;
 
-use vm::{self, Memory, Area, Prot};
+use sys::MutexGuard;
+use vm::{self, Memory, Area, Prot, UPPER_SPACE_MASK};
 use pi::common::IO_BASE_RAW;
 
 use allocator::safe_box;
@@ -41,7 +48,9 @@ impl fmt::Debug for Id {
     }
 }
 
-/// A structure that represents the complete state of a process.
+/// A structure that represents the complete state of a process, or of a
+/// thread of one: the threads of a process are scheduled on their own but
+/// share its `Group`.
 #[derive(Debug)]
 pub struct Process {
     /// The saved trap frame of a process.
@@ -50,51 +59,297 @@ pub struct Process {
     //pub stack: Stack,
     /// The scheduling state of the process.
     pub state: State,
-    /// The virtual memory of the process.
-    pub mm: Box<Memory>,
+    /// The priority and the CPU time used by the process.
+    pub sched: SchedInfo,
+    /// The address space and the open files, shared by the threads of the
+    /// process.
+    pub group: Arc<Group>,
+    /// The main thread of the process, if this is another thread of it.
+    pub leader: Option<Id>,
+    /// The stack of a kernel thread. User processes keep their stack in their
+    /// address space.
+    pub stack: Option<Stack>,
+    /// The process that started this process and waits for it to exit.
+    pub parent: Option<Id>,
+    /// The ids and exit codes of the children that exited and were not
+    /// waited for yet: the zombies of the process.
+    pub zombies: Vec<(Id, u32)>,
+    /// Woken when a child of the process exits.
+    pub children: WaitQueue,
+    /// The pending and blocked signals and the signal actions.
+    pub signals: Signals,
 }
 
+/// The lowest address of the stack of a user process.
+pub const STACK_BOTTOM: usize = 4 * 0x4000_0000;
+/// The initial stack pointer of a user process.
+pub const STACK_TOP: usize = STACK_BOTTOM + Stack::SIZE;
+
+/// The saved program status of kernel threads: EL1 using `SP_EL0` as the
+/// stack pointer, with all interrupts masked.
+const KERNEL_SPSR: u64 = 0b1111 << 6 | 0b0100;
+
+/// The bits of the saved program status a user program may change: the
+/// condition flags.
+const USER_SPSR_MASK: u64 = 0xF000_0000;
+
 impl Process {
-    /// Creates a new process with a zeroed `TrapFrame` (the default), a zeroed
-    /// stack of the default size, and a state of `Ready`.
+    /// Creates a new process with a zeroed `TrapFrame` (the default), an
+    /// address space holding only a stack of the default size, and a state of
+    /// `Ready`.
     ///
     /// If enough memory could not be allocated to start the process, returns
     /// `None`. Otherwise returns `Some` of the new `Process`.
     pub fn new() -> Option<Self> {
-        let stack_bottom = 4 * 0x4000_0000;
-        let stack_top = stack_bottom + Stack::SIZE;
-
         let mut trap_frame: Box<TrapFrame> = safe_box(unsafe { mem::zeroed() })?;
 
         // create MMU translation tables
         let mut mm = Memory::new()?;
-
-        let start = vm::kernel_start().as_usize();
-        let data = vm::kernel_data().as_usize();
-        let end = vm::kernel_end().as_usize();
-
-        // different for code and data
-        mm.area_rx(Area::new(start, data).map_to(start.into()))?;
-        mm.area_rw(Area::new(data, end).map_to(data.into()))?;
-        // different attributes for device memory
-        mm.area_dev(Area::new(IO_BASE_RAW, 0x4000_0000).map_to(IO_BASE_RAW.into()))?;
-        // different for stack area
-        mm.area_rw(Area::new(stack_bottom, stack_top).prot(Prot::RW))?;
+        mm.area_rw(Area::new(STACK_BOTTOM, STACK_TOP))?;
 
         let state = State::Ready;
-        trap_frame.sp = stack_top as u64;
+        trap_frame.sp = STACK_TOP as u64;
         trap_frame.set_ttbr(0, mm.ttbr());
-        //mem::forget(mm);
-        Some(Self { trap_frame, state, mm })
+        Some(Self {
+            trap_frame, state,
+            group: Arc::new(Group::new(mm, FdTable::new(), Limits::new())),
+            leader: None,
+            sched: SchedInfo::default(),
+            stack: None,
+            parent: None,
+            zombies: Vec::new(),
+            children: WaitQueue::new(),
+            signals: Signals::new(),
+        })
     }
 
+    /// Creates a process running the kernel function `entry` in user mode.
+    /// The kernel image and the peripherals are mapped into its address space.
     pub fn with_entry(entry: unsafe extern "C" fn () -> !) -> Option<Self> {
-        Self::new().map(|mut p| {
-            p.trap_frame.set_elr(entry);
-            p
+        let mut p = Self::new()?;
+        map_kernel(&mut p.mm())?;
+        p.trap_frame.set_elr(entry);
+        Some(p)
+    }
+
+    /// Creates a kernel thread calling `entry` with `arg` at EL1, on a stack
+    /// of its own and with interrupts masked. The kernel image and the
+    /// peripherals are mapped into its address space. The thread exits when
+    /// `entry` returns.
+    pub fn kernel(entry: ThreadFn, arg: u64) -> Option<Self> {
+        let mut trap_frame: Box<TrapFrame> = safe_box(unsafe { mem::zeroed() })?;
+        let mut mm = Memory::new()?;
+        map_kernel(&mut mm)?;
+        let stack = Stack::new()?;
+
+        trap_frame.elr = (entry as usize | UPPER_SPACE_MASK) as u64;
+        trap_frame.spsr = KERNEL_SPSR;
+        trap_frame.sp = vm::p2v(stack.top()).as_u64();
+        trap_frame.x0 = arg;
+        trap_frame.x30 = (kthread::exit as usize | UPPER_SPACE_MASK) as u64;
+        trap_frame.set_ttbr(0, mm.ttbr());
+        Some(Self {
+            trap_frame,
+            group: Arc::new(Group::new(mm, FdTable::new(), Limits::new())),
+            leader: None,
+            state: State::Ready,
+            sched: SchedInfo::default(),
+            stack: Some(stack),
+            parent: None,
+            zombies: Vec::new(),
+            children: WaitQueue::new(),
+            signals: Signals::new(),
+        })
+    }
+
+    /// Returns a copy of this process that resumes from `tf`. The copy shares
+    /// the open files of this process and a copy-on-write copy of its memory,
+    /// and inherits its priority and resource limits. Only the calling thread
+    /// is copied: the copy is the main thread of a new process, a child of
+    /// this process even if this is another thread of it.
+    pub fn fork(&mut self, tf: &TrapFrame) -> Option<Self> {
+        let mut trap_frame: Box<TrapFrame> = safe_box(*tf)?;
+        let mut mm = self.mm().fork()?;
+        trap_frame.set_ttbr(0, mm.ttbr());
+        let files = self.files().clone();
+        Some(Self {
+            trap_frame,
+            group: Arc::new(Group::new(mm, files, self.group.limits())),
+            leader: None,
+            state: State::Ready,
+            sched: self.sched,
+            stack: None,
+            parent: self.process_id(),
+            zombies: Vec::new(),
+            children: WaitQueue::new(),
+            signals: self.signals.fork(),
+        })
+    }
+
+    /// Returns a thread of this process, running at `tf`, that starts at
+    /// `entry` with the stack pointer `sp` and `arg` in `x0`. The thread
+    /// shares the address space, the open files, the resource limits and the
+    /// CPU time of this process, and inherits its priority and signal
+    /// actions.
+    pub fn thread(&self, tf: &TrapFrame, entry: u64, sp: u64, arg: u64) -> Option<Self> {
+        let mut trap_frame: Box<TrapFrame> = safe_box(*tf)?;
+        trap_frame.elr = entry;
+        trap_frame.sp = sp;
+        trap_frame.x0 = arg;
+        trap_frame.x30 = 0;
+        trap_frame.spsr &= USER_SPSR_MASK;
+        Some(Self {
+            trap_frame,
+            group: self.group.clone(),
+            leader: self.process_id(),
+            state: State::Ready,
+            sched: self.sched,
+            stack: None,
+            parent: None,
+            zombies: Vec::new(),
+            children: WaitQueue::new(),
+            signals: self.signals.fork(),
         })
     }
 
+    /// Locks the address space of this process.
+    pub fn mm(&self) -> MutexGuard<Box<Memory>> {
+        self.group.mm()
+    }
+
+    /// Locks the file descriptor table of this process.
+    pub fn files(&self) -> MutexGuard<FdTable> {
+        self.group.files()
+    }
+
+    /// Returns `true` if this process and `other` are threads of the same
+    /// process.
+    pub fn shares_group(&self, other: &Process) -> bool {
+        Arc::ptr_eq(&self.group, &other.group)
+    }
+
+    /// Replaces the program of this process, a main thread, with `image`, a
+    /// process loaded by `elf::spawn`, and stores its initial state in `tf`.
+    /// The id, the resource limits, the CPU time and the open files of this
+    /// process are kept, except for the descriptors marked close-on-exec:
+    /// their handles are returned.
+    ///
+    /// The process leaves its group for the one of `image`, which is returned
+    /// too: the caller kills the other threads of the old group.
+    pub fn exec(&mut self, image: Process, tf: &mut TrapFrame) -> (Vec<Handle>, Arc<Group>) {
+        let files = self.files().clone();
+        image.group.inherit(&self.group);
+        {
+            let mut mm = image.mm();
+            let pid = tf.pid;
+            *tf = *image.trap_frame;
+            tf.pid = pid;
+            tf.set_ttbr(mm.activate(), mm.ttbr());
+        }
+        // the old group keeps its files until its other threads exited
+        let group = mem::replace(&mut self.group, image.group.clone());
+        *self.files() = files;
+        self.signals.exec();
+        (self.files().close_on_exec(), group)
+    }
+
+    /// Tags the translation table of this process in its trap frame with the
+    /// ASID of its address space, before it runs on the calling core.
+    pub fn activate(&mut self) {
+        let (asid, ttbr) = {
+            let mut mm = self.mm();
+            (mm.activate(), mm.ttbr())
+        };
+        self.trap_frame.set_ttbr(asid, ttbr);
+    }
+
+    /// Sends the signal `sig` to this process, waking it if it is waiting and
+    /// the signal can be delivered. Init only takes the signals it handles or
+    /// ignores. Returns `false` for kernel threads, which take no signals.
+    pub fn signal(&mut self, sig: u32) -> bool {
+        if self.stack.is_some() {
+            return false;
+        }
+        if self.id() == Some(Id::one()) && self.signals.action(sig) == Action::Default {
+            return true;
+        }
+        if self.signals.raise(sig) {
+            self.wake(Wakeup::Event);
+        }
+        true
+    }
+
+    /// Delivers the pending signals of this process before it resumes from
+    /// `tf`. The handler of a signal is called by pushing a `SignalFrame` to
+    /// the user stack and redirecting `tf` to the handler. Returns the signal
+    /// and whether to dump core if a signal terminates the process.
+    pub fn deliver_signals(&mut self, tf: &mut TrapFrame) -> Option<(u32, bool)> {
+        if self.stack.is_some() {
+            return None;
+        }
+        match self.signals.next()? {
+            Delivery::Terminate { sig, core } => Some((sig, core)),
+            Delivery::Handle { sig, handler, restorer, blocked } => {
+                let frame = SignalFrame { tf: *tf, blocked, sig };
+                let size = mem::size_of::<SignalFrame>();
+                let bytes = unsafe {
+                    slice::from_raw_parts(&frame as *const SignalFrame as *const u8, size)
+                };
+                let sp = (tf.sp as usize).wrapping_sub(size) & !0xF;
+                let pushed = {
+                    let mut mm = self.mm();
+                    sp < tf.sp as usize
+                        && mm.check_user(sp, size, Prot::RW).is_some()
+                        && mm.copy_to(sp, bytes).is_some()
+                };
+                if !pushed {
+                    // no room for the frame: the stack overflowed
+                    return Some((SIGSEGV, true));
+                }
+                tf.sp = sp as u64;
+                tf.elr = handler;
+                tf.x30 = restorer;
+                tf.x0 = sig as u64;
+                tf.x1 = sp as u64;
+                None
+            }
+        }
+    }
+
+    /// Returns from a signal handler: restores the state of the interrupted
+    /// program from the `SignalFrame` at the stack pointer of `tf`. Returns
+    /// `None` if there is no valid frame.
+    pub fn sigreturn(&mut self, tf: &mut TrapFrame) -> Option<()> {
+        let size = mem::size_of::<SignalFrame>();
+        let sp = tf.sp as usize;
+        if sp % 16 != 0 {
+            return None;
+        }
+        let mut frame: SignalFrame = unsafe { mem::zeroed() };
+        {
+            let bytes = unsafe {
+                slice::from_raw_parts_mut(&mut frame as *mut SignalFrame as *mut u8, size)
+            };
+            let mut mm = self.mm();
+            mm.check_user(sp, size, Prot::READ)?;
+            mm.copy_from(sp, bytes)?;
+        }
+
+        let (pid, ttbr) = (tf.pid, tf.ttbr);
+        *tf = frame.tf;
+        tf.pid = pid;
+        tf.ttbr = ttbr;
+        tf.spsr &= USER_SPSR_MASK;
+        self.signals.set_blocked(frame.blocked);
+        Some(())
+    }
+
+    /// Returns a description of the state of this process at `tf` for a
+    /// core file: its registers and its memory areas.
+    pub fn core_dump(&self, tf: &TrapFrame) -> String {
+        format!("{:#?}\n{:#?}\n", tf, self.mm().areas())
+    }
+
     pub fn tf_u64(&mut self) -> u64 {
         let p = &*self.trap_frame;
         let tf = p as *const TrapFrame as u64;
@@ -110,28 +365,41 @@ impl Process {
     ///
     ///   * An event being waited for has arrived.
     ///
-    ///     If the process is currently waiting, the corresponding event
-    ///     function is polled to determine if the event being waiting for has
-    ///     occured. If it has, the state is switched to `Ready` and this
-    ///     function returns `true`.
+    ///     If the process is currently waiting, it is ready once it was woken
+    ///     by one of its wait queues or by the scheduler when the deadline of
+    ///     the wait passed. Then its wake function is called, the state is
+    ///     switched to `Ready` and this function returns `true`.
     ///
     /// Returns `false` in all other cases.
     pub fn is_ready(&mut self) -> bool {
         let state = mem::replace(&mut self.state, State::Ready);
         match state {
             State::Ready => true,
-            State::Running => {
-                mem::replace(&mut self.state, State::Running);
+            State::Waiting(mut wait) => match wait.woken {
+                Some(wakeup) => {
+                    if let Some(mut f) = wait.on_wake.take() {
+                        f(self, wakeup);
+                    }
+                    true
+                }
+                None => {
+                    self.state = State::Waiting(wait);
+                    false
+                }
+            },
+            state => {
+                self.state = state;
                 false
             }
-            State::Exit(code) => {
-                mem::replace(&mut self.state, State::Exit(code));
-                false
-            }
-            State::Waiting(mut f) => {
-                let ret = f(self);
-                mem::replace(&mut self.state, State::Waiting(f));
-                ret
+        }
+    }
+
+    /// Wakes this process for `wakeup`, if it is waiting and was not woken
+    /// already.
+    pub fn wake(&mut self, wakeup: Wakeup) {
+        if let State::Waiting(ref mut wait) = self.state {
+            if wait.woken.is_none() {
+                wait.woken = Some(wakeup);
             }
         }
     }
@@ -147,4 +415,24 @@ impl Process {
     pub fn id(&self) -> Option<Id> {
         Id::new(self.trap_frame.pid)
     }
+
+    /// Returns the id of the process this thread belongs to: the id of its
+    /// main thread.
+    pub fn process_id(&self) -> Option<Id> {
+        self.leader.or(self.id())
+    }
+}
+
+/// Maps the kernel image and the peripherals at their physical addresses
+/// into `mm`.
+fn map_kernel(mm: &mut Memory) -> Option<()> {
+    let start = vm::kernel_start().as_usize();
+    let data = vm::kernel_data().as_usize();
+    let end = vm::kernel_end().as_usize();
+
+    // different for code and data
+    mm.area_rx(Area::new(start, data).map_to(start.into()))?;
+    mm.area_rw(Area::new(data, end).map_to(data.into()))?;
+    // different attributes for device memory
+    mm.area_dev(Area::new(IO_BASE_RAW, 0x4000_0000).map_to(IO_BASE_RAW.into()))
 }
@@ -0,0 +1,112 @@
+use core::mem;
+
+use process::MAX_FILES;
+use process::signal::{SIGKILL, SIGXCPU};
+use vm::PAGESZ;
+
+/// The CPU time of a process, in seconds. The process is sent `SIGXCPU` when
+/// it exceeds the soft limit, and every second after that, and `SIGKILL` when
+/// it exceeds the hard limit.
+pub const RLIMIT_CPU: u64 = 0;
+/// The memory of a process backed by pages, in bytes. Faults needing a page
+/// beyond the limit raise `SIGSEGV`.
+pub const RLIMIT_RSS: u64 = 5;
+/// The number of files a process may have open: one more than the highest
+/// descriptor it may open.
+pub const RLIMIT_NOFILE: u64 = 7;
+
+/// A limit that is never reached.
+pub const RLIM_INFINITY: u64 = !0;
+
+/// The limit of a resource.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limit {
+    /// The enforced limit. A process may change it up to `max`.
+    pub cur: u64,
+    /// The highest value of `cur`. A process may only lower it.
+    pub max: u64,
+}
+
+impl Limit {
+    /// Returns a limit of `value` that may be raised up to `value`.
+    pub fn new(value: u64) -> Self {
+        Limit { cur: value, max: value }
+    }
+}
+
+/// The resource limits of a process.
+#[derive(Debug, Clone, Copy)]
+pub struct Limits {
+    cpu: Limit,
+    rss: Limit,
+    nofile: Limit,
+}
+
+impl Limits {
+    /// Returns the limits of a new process: as many open files as a
+    /// descriptor table holds, no limit for the others.
+    pub fn new() -> Self {
+        Limits {
+            cpu: Limit::new(RLIM_INFINITY),
+            rss: Limit::new(RLIM_INFINITY),
+            nofile: Limit::new(MAX_FILES as u64),
+        }
+    }
+
+    /// Returns the limit of `resource`, or `None` if there is no such
+    /// resource.
+    pub fn get(&self, resource: u64) -> Option<Limit> {
+        self.limit(resource).map(|limit| *limit)
+    }
+
+    /// Sets the limit of `resource` to `limit` and returns the previous one,
+    /// or returns `None` if there is no such resource. The limit is not
+    /// checked: see `Limit` for the valid changes.
+    pub fn set(&mut self, resource: u64, limit: Limit) -> Option<Limit> {
+        let old = self.limit_mut(resource)?;
+        Some(mem::replace(old, limit))
+    }
+
+    /// Returns the resident pages permitted by `RLIMIT_RSS`.
+    pub fn pages(&self) -> usize {
+        (self.rss.cur / PAGESZ as u64) as usize
+    }
+
+    /// Returns the open files permitted by `RLIMIT_NOFILE`.
+    pub fn files(&self) -> usize {
+        self.nofile.cur as usize
+    }
+
+    /// Returns the signal to send to a process that used `cpu_time`
+    /// microseconds of CPU time, if it exceeds `RLIMIT_CPU`. The soft limit
+    /// is raised by a second when it is exceeded, so that `SIGXCPU` is sent
+    /// once a second until the hard limit is reached.
+    pub fn check_cpu(&mut self, cpu_time: u64) -> Option<u32> {
+        if cpu_time >= self.cpu.max.saturating_mul(1_000_000) {
+            Some(SIGKILL)
+        } else if cpu_time >= self.cpu.cur.saturating_mul(1_000_000) {
+            self.cpu.cur += 1;
+            Some(SIGXCPU)
+        } else {
+            None
+        }
+    }
+
+    fn limit(&self, resource: u64) -> Option<&Limit> {
+        match resource {
+            RLIMIT_CPU => Some(&self.cpu),
+            RLIMIT_RSS => Some(&self.rss),
+            RLIMIT_NOFILE => Some(&self.nofile),
+            _ => None,
+        }
+    }
+
+    fn limit_mut(&mut self, resource: u64) -> Option<&mut Limit> {
+        match resource {
+            RLIMIT_CPU => Some(&mut self.cpu),
+            RLIMIT_RSS => Some(&mut self.rss),
+            RLIMIT_NOFILE => Some(&mut self.nofile),
+            _ => None,
+        }
+    }
+}
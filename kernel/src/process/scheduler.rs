@@ -1,11 +1,25 @@
+use core::mem;
+
 use alloc::VecDeque;
+use alloc::vec::Vec;
+use alloc::boxed::Box;
+use alloc::arc::Arc;
 
 use sys::Mutex;
-use process::{Process, State, Id};
-use traps::TrapFrame;
+use pi::local::{self, NCORES};
+use pi::timer::current_time;
+use process::{Process, State, Id, FdTable, Group, Wait, WakeFn, Wakeup, WaitQueue, take_woken};
+use process::{Policy, Fair};
+use process::kthread;
+use process::ipc;
+use process::signal::{self, SIGCHLD, SIGKILL, NSIG};
+use console::kprintln;
+use SCHEDULER;
+use smp;
+use traps::{self, TrapFrame};
 use aarch64;
 
-/// The `tick` time.
+/// The `tick` time: the time slice of the round-robin policy.
 pub const TICK: u32 = 10 * 1000;
 
 /// Process scheduler for the entire machine.
@@ -27,151 +41,547 @@ impl GlobalScheduler {
     /// Adds a process to the scheduler's queue and returns that process's ID.
     /// For more details, see the documentation on `Scheduler::add()`.
     pub fn add(&self, process: Process) -> Option<Id> {
-        self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").add(process)
+        let id = self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").add(process);
+        smp::wake_idle();
+        id
     }
 
     /// Performs a context switch using `tf` by setting the state of the current
     /// process to `new_state`, saving `tf` into the current process, and
     /// restoring the next process's trap frame into `tf`. For more details, see
-    /// the documentation on `Scheduler::switch()`.
+    /// the documentation on `Scheduler::switch_out()` and
+    /// `Scheduler::switch_in()`.
+    ///
+    /// This method blocks until there is a process to switch to, conserving
+    /// energy as much as possible in the interim.
     #[must_use]
     pub fn switch(&self, new_state: State, tf: &mut TrapFrame) -> Option<Id> {
-        self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").switch(new_state, tf)
+        self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").switch_out(new_state, tf)?;
+        Some(self.schedule(tf))
     }
 
+    /// Switches the calling core to the next process and returns its ID,
+    /// idling until a process is ready.
+    fn schedule(&self, tf: &mut TrapFrame) -> Id {
+        loop {
+            {
+                let mut guard = self.0.lock().unwrap();
+                let scheduler = guard.as_mut().expect("scheduler uninitialized");
+                // from here on, cores making processes ready wake this one
+                smp::set_idle(true);
+                if let Some(id) = scheduler.switch_in(tf) {
+                    smp::set_idle(false);
+                    return id;
+                }
+
+                // nothing to run: sleep until the next deadline or interrupt
+                match scheduler.sleepers.front() {
+                    Some(&(deadline, _)) => local::tick_at(deadline),
+                    None => local::clear_tick(),
+                }
+            }
+            aarch64::wait_for_interrupt();
+            traps::handle_pending_irqs();
+        }
+    }
+
+    /// Calls `f` with the process running on the calling core.
     pub fn current<F>(&self, f: F)
         where F: FnOnce(&mut Process)
     {
         f(self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").current().unwrap())
     }
 
+    /// Sends `SIGKILL` to the threads of `group`. For more details, see the
+    /// documentation on `Scheduler::kill_group()`.
+    pub fn kill_group(&self, group: &Arc<Group>) {
+        self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").kill_group(group)
+    }
+
+    /// Returns the IDs of all processes known to the scheduler.
+    pub fn ids(&self) -> Vec<Id> {
+        let mut guard = self.0.lock().unwrap();
+        let scheduler = guard.as_mut().expect("scheduler uninitialized");
+        scheduler.processes().filter_map(|p| p.id()).collect()
+    }
+
+    /// Calls `f` with the process `id` and returns its result, or returns
+    /// `None` if there is no such process.
+    pub fn with_process<F, R>(&self, id: Id, f: F) -> Option<R>
+        where F: FnOnce(&mut Process) -> R
+    {
+        let mut guard = self.0.lock().unwrap();
+        let scheduler = guard.as_mut().expect("scheduler uninitialized");
+        scheduler.process(id).map(f)
+    }
+
+    /// Blocks the current process on `queues` until one of them is woken or
+    /// the time `deadline` (in microseconds) passes, then calls `on_wake` with
+    /// the process, and performs a context switch using `tf`.
+    pub fn block(&self, queues: &[&WaitQueue], deadline: Option<u64>, on_wake: Option<WakeFn>, tf: &mut TrapFrame) {
+        let current = self.0.lock().unwrap().as_ref().expect("scheduler uninitialized").cores[smp::core()].current;
+        let id = current.expect("no current process");
+        for queue in queues {
+            queue.enqueue(id);
+        }
+        let wait = Wait { deadline, on_wake, woken: None };
+        self.switch(State::Waiting(wait), tf).expect("block");
+    }
+
+    /// Kills a process to free memory. For more details, see the
+    /// documentation on `Scheduler::oom_kill()`.
+    pub fn oom_kill(&self) -> Option<Id> {
+        self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").oom_kill()
+    }
+
+    /// Reaps an exited child of the current process. For more details, see
+    /// the documentation on `Scheduler::reap()`.
+    pub fn reap(&self, pid: Option<Id>, hang: bool) -> Result<Option<(Id, u32)>, ()> {
+        self.0.lock().unwrap().as_mut().expect("scheduler uninitialized").reap(pid, hang)
+    }
+
     /// Initializes the scheduler and starts executing processes in user space
-    /// using timer interrupt based preemptive scheduling. This method should
-    /// not return under normal conditions.
+    /// using timer interrupt based preemptive scheduling, sharing the CPU
+    /// fairly by priority. The secondary cores are released to join in. This
+    /// method should not return under normal conditions.
     pub fn start(&self) -> ! {
-        *self.0.lock().unwrap() = Some(Scheduler::new());
+        *self.0.lock().unwrap() = Some(Scheduler::new(|| Box::new(Fair::new()) as Box<Policy + Send>));
 
         let tf = {
-            let mut init = Process::with_entry(el0_init).unwrap();
+            let mut init = spawn(el0_init);
+            init.activate();
             let tf = init.tf_u64();
             self.add(init).expect("add proc 'init'");
-            self.add(Process::with_entry(el0_shell).unwrap()).expect("add proc 'shell'");
-            self.add(Process::with_entry(el0_other).unwrap()).expect("add proc 'other'");
+            self.add(spawn(el0_shell)).expect("add proc 'shell'");
+            self.add(spawn(el0_other)).expect("add proc 'other'");
+            kthread::spawn(reaper, 0).expect("add thread 'reaper'");
+            kthread::spawn(traps::input_thread, 0).expect("add thread 'input'");
             tf
         };
 
-        unsafe {
-            asm!("
-            mov     SP, $0
-            bl      context_restore
-            ldr     x0, =_stack_core0_el1
-            mov     SP, x0
+        smp::start_secondaries();
+        enter(tf)
+    }
 
-            mov     x0, xzr
-            mov     x30, xzr
-            eret
-            "
-            :: "r"(tf)
-            :: "volatile");
-        }
-        unreachable!("goto EL0")
+    /// Starts executing processes on a secondary core once `start` runs on
+    /// core 0. This method should not return under normal conditions.
+    pub fn start_core(&self) -> ! {
+        let mut tf: TrapFrame = unsafe { mem::zeroed() };
+        self.schedule(&mut tf);
+        enter(&tf as *const TrapFrame as u64)
+    }
+}
+
+/// Returns from an exception into the trap frame at `tf`, leaving the EL1
+/// stack of the calling core empty.
+fn enter(tf: u64) -> ! {
+    let stack = smp::stack_top(smp::core());
+    // the global translations of the boot tables must not shadow user space
+    aarch64::flush_tlb_all();
+    unsafe {
+        asm!("
+        mov     SP, $0
+        bl      context_restore
+        mov     SP, x0
+
+        mov     x0, xzr
+        mov     x30, xzr
+        eret
+        "
+        :: "r"(tf), "{x0}"(stack)
+        :: "volatile");
+    }
+    unreachable!("goto EL0")
+}
+
+/// Woken when init is handed zombies.
+static ORPHANS: WaitQueue = WaitQueue::new();
+
+/// Releases the zombies of init: it does not wait for its children.
+extern "C" fn reaper(_: u64) {
+    loop {
+        SCHEDULER.with_process(Id::one(), |init| init.zombies.clear());
+        kthread::wait(&ORPHANS);
     }
 }
 
+/// Creates a process starting at `entry` with the console as its standard
+/// input, output and error.
+fn spawn(entry: unsafe extern "C" fn() -> !) -> Process {
+    let process = Process::with_entry(entry).expect("create process");
+    *process.files() = FdTable::stdio().expect("open /dev/console");
+    process
+}
+
+/// The processes of a core.
 #[derive(Debug)]
-pub struct Scheduler {
+struct RunQueue {
     processes: VecDeque<Process>,
+    /// The process running on the core. It stays in the queue.
     current: Option<Id>,
+    /// Decides which process of the queue runs next.
+    policy: Box<Policy + Send>,
+}
+
+impl RunQueue {
+    /// Returns the number of processes in the queue that are ready to run.
+    fn ready(&mut self) -> usize {
+        self.processes.iter_mut().fold(0, |n, p| n + p.is_ready() as usize)
+    }
+}
+
+#[derive(Debug)]
+pub struct Scheduler {
+    /// The run queues of the cores, indexed by core.
+    cores: Vec<RunQueue>,
     last_id: Option<Id>,
+    /// The processes waiting with a deadline, ordered by the deadline.
+    sleepers: VecDeque<(u64, Id)>,
+    /// The processes woken by a wait queue before they got to wait, as
+    /// happens when another core wakes the queue in between: they are woken
+    /// as soon as they wait.
+    early: Vec<Id>,
 }
 
 impl Scheduler {
-    /// Returns a new `Scheduler` with an empty queue.
-    fn new() -> Self {
+    /// Returns a new `Scheduler` with empty queues, scheduling the processes
+    /// of every core by a policy returned by `policy`.
+    fn new(policy: fn() -> Box<Policy + Send>) -> Self {
         Self {
-            processes: VecDeque::new(),
-            current: None,
+            cores: (0..NCORES)
+                .map(|_| RunQueue { processes: VecDeque::new(), current: None, policy: policy() })
+                .collect(),
             last_id: None,
+            sleepers: VecDeque::new(),
+            early: Vec::new(),
         }
     }
 
+    /// Returns the processes of all cores.
+    fn processes<'a>(&'a mut self) -> impl Iterator<Item = &'a mut Process> + 'a {
+        self.cores.iter_mut().flat_map(|queue| queue.processes.iter_mut())
+    }
+
+    /// Returns the process `id`.
+    fn process(&mut self, id: Id) -> Option<&mut Process> {
+        self.processes().find(|p| p.id() == Some(id))
+    }
+
+    /// Returns the process running on the calling core.
     pub fn current(&mut self) -> Option<&mut Process> {
-        let id = self.current;
-        self.processes.iter_mut().find(|p| p.id() == id)
+        let queue = &mut self.cores[smp::core()];
+        let id = queue.current;
+        queue.processes.iter_mut().find(|p| p.id() == id)
     }
 
     /// Adds a process to the scheduler's queue and returns that process's ID if
     /// a new process can be scheduled. The process ID is newly allocated for
     /// the process and saved in its `trap_frame`. If no further processes can
-    /// be scheduled, returns `None`.
+    /// be scheduled, returns `None`. The process is queued on the core with
+    /// the fewest processes.
     ///
-    /// If this is the first process added, it is marked as the current process.
-    /// It is the caller's responsibility to ensure that the first time `switch`
-    /// is called, that process is executing on the CPU.
+    /// If this is the first process added, it is marked as the current process
+    /// of the calling core. It is the caller's responsibility to ensure that
+    /// the first time `switch` is called, that process is executing on the
+    /// CPU.
+    ///
+    /// A thread whose main thread exited or replaced its program meanwhile is
+    /// not added: the other threads of its group were killed already.
     fn add(&mut self, mut process: Process) -> Option<Id> {
-         let id = match self.last_id {
-            Some(id) => id.next()?,
+        if let Some(leader) = process.leader {
+            let group = &process.group;
+            if !self.process(leader).map_or(false, |leader| Arc::ptr_eq(&leader.group, group)) {
+                return None;
+            }
+        }
+        let (id, core) = match self.last_id {
+            Some(id) => {
+                let core = (0..self.cores.len())
+                    .min_by_key(|&core| self.cores[core].processes.len())
+                    .unwrap();
+                (id.next()?, core)
+            }
             None => {
                 let id = Id::one();
+                let core = smp::core();
                 process.state = State::Running;
-                self.current = Some(id);
-                id
+                process.sched.started = current_time();
+                self.cores[core].current = Some(id);
+                (id, core)
             }
         };
 
         process.set_id(Some(id));
-        self.processes.push_back(process);
+        self.cores[core].processes.push_back(process);
 
         self.last_id = Some(id);
         self.last_id
     }
 
-    /// Sets the current process's state to `new_state`, finds the next process
-    /// to switch to, and performs the context switch on `tf` by saving `tf`
-    /// into the current process and restoring the next process's trap frame
-    /// into `tf`. If there is no current process, returns `None`. Otherwise,
-    /// returns `Some` of the process ID that was context switched into `tf`.
-    ///
-    /// This method blocks until there is a process to switch to, conserving
-    /// energy as much as possible in the interim.
-    fn switch(&mut self, new_state: State, tf: &mut TrapFrame) -> Option<Id> {
-        if self.current.is_none() {
-            return None;
-        }
-
-        if let Some(mut process) = self.processes.pop_front() {
-            let is_exit = new_state.is_exit();
-            if process.id() == self.current {
-                *process.trap_frame = *tf;
-                process.state = new_state;
-                self.current = None;
-                if !is_exit {
-                    self.processes.push_back(process);
-                } else {
-                    ::console::kprintln!("exited");
-                }
-            } else {
-                self.processes.push_front(process);
+    /// Releases the exited `process`. Its exit code is kept as a zombie by its
+    /// parent until the parent waits for it, and its children are reparented
+    /// to init. The exit code of a thread is kept by its group until another
+    /// thread joins it; the exit of a main thread kills the other threads of
+    /// the process. A main thread reports the exit code its group recorded,
+    /// if a thread called `exit`, rather than the signal that killed it.
+    fn exited(&mut self, process: Process) {
+        let id = process.id();
+        let code = match process.state {
+            State::Exit(code) => code,
+            _ => unreachable!("process did not exit"),
+        };
+        let code = match process.leader {
+            None => process.group.exit_code().unwrap_or(code),
+            Some(_) => code,
+        };
+
+        self.early.retain(|&early| Some(early) != id);
+        if let Some(id) = id {
+            ipc::forget(id);
+        }
+
+        let group = process.group.clone();
+        match (id, process.leader) {
+            (Some(id), Some(_)) => group.exited(id, code),
+            _ => self.kill_group(&group),
+        }
+
+        let init = if id == Some(Id::one()) { None } else { Some(Id::one()) };
+        let mut zombies = process.zombies;
+        let mut orphans = !zombies.is_empty();
+        for p in self.processes() {
+            if p.parent == id {
+                p.parent = init;
+            }
+            if p.id() == init {
+                p.zombies.append(&mut zombies);
             }
         }
 
-        // TODO: improve WFI works
+        if let (Some(id), Some(parent)) = (id, process.parent) {
+            if let Some(parent) = self.process(parent) {
+                parent.zombies.push((id, code));
+                parent.children.wake_all();
+                parent.signal(SIGCHLD);
+            }
+            orphans |= parent == Id::one();
+        }
 
-        loop {
-            for _ in 0..self.processes.len() {
-                let mut process = self.processes.pop_front().unwrap();
+        if orphans && init.is_some() {
+            ORPHANS.wake_all();
+        }
+    }
+
+    /// Sends `SIGKILL` to the processes of `group`, as its main thread exited
+    /// or replaced its program.
+    fn kill_group(&mut self, group: &Arc<Group>) {
+        for p in self.processes().filter(|p| Arc::ptr_eq(&p.group, group)) {
+            p.signal(SIGKILL);
+        }
+    }
 
-                if !process.is_ready() {
-                    self.processes.push_back(process);
-                    continue;
+    /// Wakes the process `id` for `wakeup` if it is waiting.
+    fn wake(&mut self, id: Id, wakeup: Wakeup) {
+        if let Some(process) = self.process(id) {
+            process.wake(wakeup);
+        }
+    }
+
+    /// Remembers the process `id` to be woken at the time `deadline`.
+    fn sleep(&mut self, id: Id, deadline: u64) {
+        let i = self.sleepers.iter()
+            .position(|&(d, _)| d > deadline)
+            .unwrap_or(self.sleepers.len());
+        self.sleepers.insert(i, (deadline, id));
+    }
+
+    /// Wakes the processes woken by wait queues and the sleepers whose
+    /// deadline passed, and sends the signals broadcast by interrupt
+    /// handlers.
+    fn wake_up(&mut self) {
+        for id in take_woken() {
+            let waiting = match self.process(id) {
+                Some(process) => process.state.is_waiting(),
+                None => continue,
+            };
+            if waiting {
+                self.wake(id, Wakeup::Event);
+            } else if !self.early.contains(&id) {
+                self.early.push(id);
+            }
+        }
+        let broadcast = signal::take_broadcast();
+        for sig in (1..NSIG).filter(|&sig| broadcast & (1 << sig) != 0) {
+            for process in self.processes() {
+                process.signal(sig);
+            }
+        }
+        let now = current_time();
+        while self.sleepers.front().map_or(false, |&(deadline, _)| deadline <= now) {
+            let (deadline, id) = self.sleepers.pop_front().unwrap();
+            // the process may have been woken and waited again since
+            let process = self.process(id);
+            if let Some(process) = process {
+                let expired = match process.state {
+                    State::Waiting(ref wait) => wait.deadline == Some(deadline),
+                    _ => false,
+                };
+                if expired {
+                    process.wake(Wakeup::Timeout);
                 }
+            }
+        }
+    }
+
+    /// Moves a ready process to the queue of `core` from the core with the
+    /// most ready processes, if `core` has no ready process or at least two
+    /// less. Only processes that are not running move.
+    fn balance(&mut self, core: usize) {
+        let mut load = [0; NCORES];
+        for (i, queue) in self.cores.iter_mut().enumerate() {
+            load[i] = queue.ready();
+        }
+        let busiest = (0..NCORES).max_by_key(|&i| load[i]).unwrap();
+        let uneven = load[busiest] > 0 && (load[core] == 0 || load[busiest] >= load[core] + 2);
+        if busiest == core || !uneven {
+            return;
+        }
 
-                *tf = *process.trap_frame;
-                self.current = process.id();
-                self.processes.push_front(process);
-                return self.current;
+        let i = self.cores[busiest].processes.iter_mut().position(|p| p.is_ready()).unwrap();
+        let mut process = self.cores[busiest].processes.remove(i).unwrap();
+        self.cores[core].policy.migrated(&mut process);
+        self.cores[core].processes.push_back(process);
+    }
+
+    /// Sends `SIGKILL` to the user process using the most pages, init aside,
+    /// and returns its id, or returns `None` if there is no process to kill.
+    /// Only main threads are picked: their exit kills the other threads.
+    /// Until the victim exited and released its memory, it is returned again
+    /// rather than killing another process.
+    fn oom_kill(&mut self) -> Option<Id> {
+        if let Some(dying) = self.processes().find(|p| p.signals.is_pending(SIGKILL)) {
+            return dying.id();
+        }
+
+        let victim = self.processes()
+            .filter(|p| p.stack.is_none() && p.leader.is_none() && p.id() != Some(Id::one()))
+            .max_by_key(|p| p.mm().resident())?;
+        kprintln!("--- out of memory: killing {:?} ({} pages)", victim.id(), victim.mm().resident());
+        victim.signal(SIGKILL);
+        victim.id()
+    }
+
+    /// Reaps an exited child of the current process: the child `pid`, or any
+    /// child if `pid` is `None`. Returns the id and exit code of the child, or
+    /// `Ok(None)` if the matching children are still running. Fails if the
+    /// current process has no matching children. The children of a process
+    /// are those of its main thread, which any of its threads may reap.
+    ///
+    /// If `hang` is set and the children are still running, the current
+    /// process is queued until one of its children exits. As children exit
+    /// with the scheduler locked too, none exits unnoticed in between.
+    fn reap(&mut self, pid: Option<Id>, hang: bool) -> Result<Option<(Id, u32)>, ()> {
+        let current = self.cores[smp::core()].current;
+        let parent = self.current().ok_or(())?.process_id();
+        let matches = |id: Id| pid.map_or(true, |pid| pid == id);
+        {
+            let process = parent.and_then(|parent| self.process(parent)).ok_or(())?;
+            if let Some(i) = process.zombies.iter().position(|&(id, _)| matches(id)) {
+                return Ok(Some(process.zombies.remove(i)));
             }
-            aarch64::wait_for_interrupt();
         }
+        let running = self.processes()
+            .any(|p| p.parent == parent && p.id().map_or(false, &matches));
+        if !running {
+            return Err(());
+        }
+        if hang {
+            let process = parent.and_then(|parent| self.process(parent)).ok_or(())?;
+            process.children.enqueue(current.expect("current process"));
+        }
+        Ok(None)
+    }
+
+    /// Sets the state of the process running on the calling core to
+    /// `new_state`, saves `tf` into it and charges it for the CPU time it
+    /// used. Exited processes are released. If the core has no current
+    /// process, returns `None`.
+    fn switch_out(&mut self, new_state: State, tf: &mut TrapFrame) -> Option<()> {
+        let core = smp::core();
+        let id = self.cores[core].current.take()?;
+        let i = self.cores[core].processes.iter()
+            .position(|p| p.id() == Some(id))
+            .expect("current process");
+        let mut process = self.cores[core].processes.remove(i).unwrap();
+
+        let is_exit = new_state.is_exit();
+        let deadline = match new_state {
+            State::Waiting(ref wait) => wait.deadline,
+            _ => None,
+        };
+        let ran = current_time().saturating_sub(process.sched.started);
+        *process.trap_frame = *tf;
+        process.state = new_state;
+        if process.state.is_waiting() {
+            if let Some(i) = self.early.iter().position(|&early| early == id) {
+                self.early.remove(i);
+                process.wake(Wakeup::Event);
+            }
+        }
+        if let Some(sig) = process.group.charge(ran) {
+            // the limit is the one of the process: its main thread is signaled
+            match process.leader.and_then(|leader| self.process(leader)) {
+                Some(leader) => { leader.signal(sig); }
+                None => { process.signal(sig); }
+            }
+        }
+        self.cores[core].policy.charge(&mut process, ran);
+        if let Some(deadline) = deadline {
+            self.sleep(id, deadline);
+        }
+        if !is_exit {
+            self.cores[core].processes.push_back(process);
+        } else {
+            self.exited(process);
+        }
+        Some(())
+    }
+
+    /// Balances the load, lets the policy of the calling core pick the next
+    /// process, and restores its trap frame into `tf`. Returns the ID of the
+    /// process, or `None` if no process is ready to run on the core.
+    fn switch_in(&mut self, tf: &mut TrapFrame) -> Option<Id> {
+        let core = smp::core();
+        self.wake_up();
+        self.balance(core);
+
+        let (i, slice) = {
+            let queue = &mut self.cores[core];
+            queue.policy.pick(&mut queue.processes)?
+        };
+        let now = current_time();
+        let id = {
+            let queue = &mut self.cores[core];
+            let mut process = queue.processes.remove(i).unwrap();
+            process.activate();
+            *tf = *process.trap_frame;
+            process.state = State::Running;
+            process.sched.started = now;
+            queue.current = process.id();
+            queue.processes.push_front(process);
+            queue.current
+        };
+
+        let tick = now + slice as u64;
+        match self.sleepers.front() {
+            Some(&(deadline, _)) if deadline < tick => local::tick_at(deadline),
+            _ => local::tick_at(tick),
+        }
+
+        // idle cores take over the processes left ready
+        if self.cores.iter_mut().any(|queue| queue.ready() > 0) {
+            smp::wake_idle();
+        }
+        id
     }
 }
@@ -0,0 +1,279 @@
+use core::mem;
+
+use sys::Mutex;
+use traps::TrapFrame;
+
+/// The number of signals: signals are numbered from 1 to `NSIG - 1`.
+pub const NSIG: u32 = 32;
+
+pub const SIGHUP: u32 = 1;
+pub const SIGINT: u32 = 2;
+pub const SIGQUIT: u32 = 3;
+pub const SIGILL: u32 = 4;
+pub const SIGTRAP: u32 = 5;
+pub const SIGABRT: u32 = 6;
+pub const SIGBUS: u32 = 7;
+pub const SIGFPE: u32 = 8;
+pub const SIGKILL: u32 = 9;
+pub const SIGUSR1: u32 = 10;
+pub const SIGSEGV: u32 = 11;
+pub const SIGUSR2: u32 = 12;
+pub const SIGPIPE: u32 = 13;
+pub const SIGALRM: u32 = 14;
+pub const SIGTERM: u32 = 15;
+pub const SIGCHLD: u32 = 17;
+pub const SIGXCPU: u32 = 24;
+
+/// The handler of `sigaction` selecting the default action.
+pub const SIG_DFL: u64 = 0;
+/// The handler of `sigaction` ignoring the signal.
+pub const SIG_IGN: u64 = 1;
+
+/// `sigprocmask` adds the given signals to the blocked signals.
+pub const SIG_BLOCK: u64 = 0;
+/// `sigprocmask` removes the given signals from the blocked signals.
+pub const SIG_UNBLOCK: u64 = 1;
+/// `sigprocmask` replaces the blocked signals with the given signals.
+pub const SIG_SETMASK: u64 = 2;
+
+/// Set in the exit code of processes terminated by a signal, next to the
+/// number of the signal.
+pub const KILLED: u32 = 1 << 8;
+/// Set in the exit code of processes terminated by a signal that dumped
+/// core.
+pub const CORE_DUMPED: u32 = 1 << 9;
+
+/// Returns the name of the signal `sig`.
+pub fn name(sig: u32) -> &'static str {
+    match sig {
+        SIGHUP => "SIGHUP",
+        SIGINT => "SIGINT",
+        SIGQUIT => "SIGQUIT",
+        SIGILL => "SIGILL",
+        SIGTRAP => "SIGTRAP",
+        SIGABRT => "SIGABRT",
+        SIGBUS => "SIGBUS",
+        SIGFPE => "SIGFPE",
+        SIGKILL => "SIGKILL",
+        SIGUSR1 => "SIGUSR1",
+        SIGSEGV => "SIGSEGV",
+        SIGUSR2 => "SIGUSR2",
+        SIGPIPE => "SIGPIPE",
+        SIGALRM => "SIGALRM",
+        SIGTERM => "SIGTERM",
+        SIGCHLD => "SIGCHLD",
+        SIGXCPU => "SIGXCPU",
+        _ => "unknown signal",
+    }
+}
+
+/// Returns the bit of `sig` in signal masks.
+fn bit(sig: u32) -> u32 {
+    1 << sig
+}
+
+/// Signals sent to every user process by interrupt handlers. Those may run
+/// while the scheduler is locked, so the scheduler sends the signals from the
+/// next context switch on.
+static BROADCAST: Mutex<u32> = Mutex::new(0);
+
+/// Sends `sig` to every user process from the next context switch on.
+pub fn broadcast(sig: u32) {
+    *BROADCAST.lock().unwrap() |= bit(sig);
+}
+
+/// Returns the mask of the signals broadcast since the last call.
+pub fn take_broadcast() -> u32 {
+    mem::replace(&mut *BROADCAST.lock().unwrap(), 0)
+}
+
+/// What happens when a signal is delivered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    /// The default action of the signal: see `DefaultAction`.
+    Default,
+    /// The signal is discarded.
+    Ignore,
+    /// The user function `handler` is called with the signal number, with
+    /// the signals in `mask` and the signal itself blocked. The handler
+    /// returns to `restorer`, which must call `sigreturn`.
+    Handler { handler: u64, mask: u32, restorer: u64 },
+}
+
+/// The default action of a signal.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefaultAction {
+    /// The process is terminated.
+    Terminate,
+    /// The process is terminated and its state is written to a core file.
+    CoreDump,
+    /// The signal is discarded.
+    Ignore,
+}
+
+impl DefaultAction {
+    /// Returns the default action of `sig`.
+    pub fn of(sig: u32) -> DefaultAction {
+        match sig {
+            SIGQUIT | SIGILL | SIGTRAP | SIGABRT | SIGBUS | SIGFPE | SIGSEGV | SIGXCPU =>
+                DefaultAction::CoreDump,
+            SIGCHLD => DefaultAction::Ignore,
+            _ => DefaultAction::Terminate,
+        }
+    }
+}
+
+/// The signal state of a process: the signals sent to it but not delivered
+/// yet, the signals it blocks, and its actions.
+#[derive(Debug, Clone)]
+pub struct Signals {
+    /// The signals sent to the process, not delivered yet.
+    pending: u32,
+    /// The signals that stay pending until they are unblocked.
+    blocked: u32,
+    actions: [Action; NSIG as usize],
+}
+
+/// The result of taking the next signal to deliver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// The process is terminated by the signal, dumping core if `core` is
+    /// set.
+    Terminate { sig: u32, core: bool },
+    /// The handler of the signal is called.
+    Handle { sig: u32, handler: u64, restorer: u64, blocked: u32 },
+}
+
+impl Signals {
+    /// Returns the state of a new process: no signal pending or blocked,
+    /// the default action for every signal.
+    pub fn new() -> Self {
+        Signals { pending: 0, blocked: 0, actions: [Action::Default; NSIG as usize] }
+    }
+
+    /// Returns the state of a copy of the process: the same blocked signals
+    /// and actions, but no pending signal.
+    pub fn fork(&self) -> Self {
+        Signals { pending: 0, ..self.clone() }
+    }
+
+    /// Resets the handled signals to their default action, as the handlers
+    /// are gone with the program. Ignored signals stay ignored.
+    pub fn exec(&mut self) {
+        for action in self.actions.iter_mut() {
+            if let Action::Handler { .. } = *action {
+                *action = Action::Default;
+            }
+        }
+    }
+
+    /// Returns whether `sig` is a valid signal number.
+    pub fn is_valid(sig: u32) -> bool {
+        sig > 0 && sig < NSIG
+    }
+
+    /// Returns the action of `sig`.
+    pub fn action(&self, sig: u32) -> Action {
+        self.actions[sig as usize]
+    }
+
+    /// Sets the action of `sig` to `action` and returns the previous one.
+    /// Returns `None` if `sig` is invalid or its action cannot be changed.
+    pub fn set_action(&mut self, sig: u32, action: Action) -> Option<Action> {
+        if !Self::is_valid(sig) || sig == SIGKILL {
+            return None;
+        }
+        let old = mem::replace(&mut self.actions[sig as usize], action);
+        if self.is_ignored(sig) {
+            self.pending &= !bit(sig);
+        }
+        Some(old)
+    }
+
+    /// Returns the mask of blocked signals.
+    pub fn blocked(&self) -> u32 {
+        self.blocked
+    }
+
+    /// Sets the mask of blocked signals. `SIGKILL` cannot be blocked.
+    pub fn set_blocked(&mut self, mask: u32) {
+        self.blocked = mask & !bit(SIGKILL) & !1;
+    }
+
+    /// Returns whether `sig` was sent and not delivered yet.
+    pub fn is_pending(&self, sig: u32) -> bool {
+        self.pending & bit(sig) != 0
+    }
+
+    /// Returns whether `sig` is discarded when it is sent.
+    fn is_ignored(&self, sig: u32) -> bool {
+        match self.actions[sig as usize] {
+            Action::Ignore => true,
+            Action::Default => DefaultAction::of(sig) == DefaultAction::Ignore,
+            Action::Handler { .. } => false,
+        }
+    }
+
+    /// Makes `sig` pending unless it is ignored. Returns `true` if the
+    /// signal can be delivered right away: a waiting process should be woken
+    /// to receive it.
+    pub fn raise(&mut self, sig: u32) -> bool {
+        if self.is_ignored(sig) {
+            return false;
+        }
+        self.pending |= bit(sig);
+        self.blocked & bit(sig) == 0
+    }
+
+    /// Makes `sig` pending for an exception of the process itself. As
+    /// returning from the exception raises it again, the signal is
+    /// unblocked, and terminates the process if it is ignored.
+    pub fn force(&mut self, sig: u32) {
+        self.blocked &= !bit(sig);
+        if self.actions[sig as usize] == Action::Ignore {
+            self.actions[sig as usize] = Action::Default;
+        }
+        self.pending |= bit(sig);
+    }
+
+    /// Takes the next pending signal that is not blocked and returns what to
+    /// do with it. Signals whose action is to be ignored are discarded.
+    /// Returns `None` once no signal is left.
+    pub fn next(&mut self) -> Option<Delivery> {
+        loop {
+            let deliverable = self.pending & !self.blocked;
+            if deliverable == 0 {
+                return None;
+            }
+            let sig = deliverable.trailing_zeros();
+            self.pending &= !bit(sig);
+
+            match self.actions[sig as usize] {
+                Action::Ignore => (),
+                Action::Default => match DefaultAction::of(sig) {
+                    DefaultAction::Ignore => (),
+                    DefaultAction::Terminate => return Some(Delivery::Terminate { sig, core: false }),
+                    DefaultAction::CoreDump => return Some(Delivery::Terminate { sig, core: true }),
+                },
+                Action::Handler { handler, mask, restorer } => {
+                    let blocked = self.blocked;
+                    self.set_blocked(blocked | mask | bit(sig));
+                    return Some(Delivery::Handle { sig, handler, restorer, blocked });
+                }
+            }
+        }
+    }
+}
+
+/// The frame pushed to the user stack to call a signal handler: the state of
+/// the interrupted program, restored by `sigreturn`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct SignalFrame {
+    /// The registers of the interrupted program.
+    pub tf: TrapFrame,
+    /// The signals blocked by the interrupted program.
+    pub blocked: u32,
+    /// The signal being handled.
+    pub sig: u32,
+}
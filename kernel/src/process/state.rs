@@ -3,19 +3,37 @@ use alloc::boxed::Box;
 
 use process::Process;
 
-/// Type of a function used to determine if a process is ready to be scheduled
-/// again. The scheduler calls this function when it is the process's turn to
-/// execute. If the function returns `true`, the process is scheduled. If it
-/// returns `false`, the process is not scheduled, and this function will be
-/// called on the next time slice.
-pub type EventPollFn = Box<FnMut(&mut Process) -> bool + Send>;
+/// Type of a function called when a blocked process is woken, with the reason
+/// it was woken. It prepares the process to continue, for example by storing
+/// the result of a system call in its trap frame.
+pub type WakeFn = Box<FnMut(&mut Process, Wakeup) + Send>;
+
+/// The reason a blocked process is woken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wakeup {
+    /// One of the wait queues of the process was woken.
+    Event,
+    /// The deadline of the wait passed.
+    Timeout,
+}
+
+/// How a process is blocked: on the wait queues it enqueued itself on, until
+/// one of them is woken or the deadline passes.
+pub struct Wait {
+    /// The time, in microseconds, at which the wait times out.
+    pub deadline: Option<u64>,
+    /// Called when the process is woken.
+    pub on_wake: Option<WakeFn>,
+    /// Set when the process is woken.
+    pub woken: Option<Wakeup>,
+}
 
 /// The scheduling state of a process.
 pub enum State {
     /// The process is ready to be scheduled.
     Ready,
-    /// The process is waiting on an event to occur before it can be scheduled.
-    Waiting(EventPollFn),
+    /// The process is blocked and skipped by the scheduler until it is woken.
+    Waiting(Wait),
     /// The process is currently running.
     Running,
     /// The process is exiting.
@@ -0,0 +1,84 @@
+use core::cmp::min;
+
+use alloc::vec::Vec;
+
+use sys::Mutex;
+use smp;
+use process::Id;
+
+/// The processes woken since the scheduler last looked. Wait queues may be
+/// woken while the scheduler is locked, so they leave the ids here.
+static WOKEN: Mutex<Option<Vec<Id>>> = Mutex::new(None);
+
+/// Returns the processes woken since the last call.
+pub fn take_woken() -> Vec<Id> {
+    WOKEN.lock().unwrap().take().unwrap_or_else(Vec::new)
+}
+
+/// A queue of processes blocked until an event occurs. Interrupt handlers and
+/// drivers wake the queue when the event occurs.
+///
+/// A process may wait on several queues at once and is woken by the first
+/// of them, so a process may be woken by an older wait it has left already.
+/// Waiters check their condition again when they are woken.
+#[derive(Debug)]
+pub struct WaitQueue(Mutex<Option<Vec<Id>>>);
+
+impl WaitQueue {
+    /// Returns an empty queue.
+    pub const fn new() -> Self {
+        WaitQueue(Mutex::new(None))
+    }
+
+    /// Adds the process `id` to the queue.
+    pub fn enqueue(&self, id: Id) {
+        let mut guard = self.0.lock().unwrap();
+        let waiters = guard.get_or_insert_with(Vec::new);
+        if !waiters.contains(&id) {
+            waiters.push(id);
+        }
+    }
+
+    /// Removes the process `id` from the queue, if it waits on it.
+    pub fn remove(&self, id: Id) {
+        if let Some(waiters) = self.0.lock().unwrap().as_mut() {
+            waiters.retain(|&waiter| waiter != id);
+        }
+    }
+
+    /// Returns `true` if no process waits on the queue.
+    pub fn is_empty(&self) -> bool {
+        self.0.lock().unwrap().as_ref().map_or(true, |waiters| waiters.is_empty())
+    }
+
+    /// Wakes the first `n` processes waiting on the queue, in the order they
+    /// were queued, and returns how many were woken.
+    pub fn wake(&self, n: usize) -> usize {
+        let woken: Vec<Id> = match self.0.lock().unwrap().as_mut() {
+            Some(waiters) => {
+                let n = min(n, waiters.len());
+                waiters.drain(..n).collect()
+            }
+            None => return 0,
+        };
+        if woken.is_empty() {
+            return 0;
+        }
+        let n = woken.len();
+        WOKEN.lock().unwrap().get_or_insert_with(Vec::new).extend(woken);
+        smp::wake_idle();
+        n
+    }
+
+    /// Wakes all processes waiting on the queue. They are scheduled again
+    /// from the next context switch on; idle cores are interrupted to pick
+    /// them up.
+    pub fn wake_all(&self) {
+        let waiters = match self.0.lock().unwrap().take() {
+            Some(waiters) => waiters,
+            None => return,
+        };
+        WOKEN.lock().unwrap().get_or_insert_with(Vec::new).extend(waiters);
+        smp::wake_idle();
+    }
+}
@@ -0,0 +1,87 @@
+use core::ptr;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use pi::common::clean_invalidate_dcache;
+use pi::local::{LocalController, NCORES};
+use vm::{self, PhysicalAddr};
+use aarch64;
+
+/// The physical address of the release addresses of the cores. Secondary
+/// cores wait in `_start` (or in the firmware) until core 0 writes the
+/// address to jump to into their entry.
+const SPIN_TABLE: usize = 0xd8;
+
+/// The size of the EL1 stack of a core: see `layout.ld` and `start.S`.
+const CORE_STACK_SIZE: usize = 0x10_0000;
+
+/// The mailbox cores interrupt each other with.
+const IPI_MAILBOX: usize = 0;
+
+/// The cores idling in the scheduler, one bit per core.
+static IDLE: AtomicUsize = AtomicUsize::new(0);
+
+extern "C" {
+    static _stack_core0_el1: u8;
+    fn _start_secondary();
+}
+
+/// Returns the core currently executing.
+pub fn core() -> usize {
+    unsafe { aarch64::affinity() }
+}
+
+/// Returns the top of the EL1 stack of `core`, in the higher half.
+pub fn stack_top(core: usize) -> usize {
+    let top = unsafe { &_stack_core0_el1 as *const u8 as usize } + core * CORE_STACK_SIZE;
+    vm::p2v(PhysicalAddr::from(top & vm::LOWER_SPACE_MASK)).as_usize()
+}
+
+/// Releases the secondary cores from the spin table. They set up their MMU,
+/// stack and exception vectors and join the scheduler: see
+/// `kernel_secondary`.
+pub fn start_secondaries() {
+    let entry = _start_secondary as usize & vm::LOWER_SPACE_MASK;
+    for core in 1..NCORES {
+        let release = vm::p2v(PhysicalAddr::from(SPIN_TABLE + core * 8)).as_usize();
+        unsafe { ptr::write_volatile(release as *mut usize, entry) };
+        // the core reads its entry with the MMU and the caches off
+        clean_invalidate_dcache(release, 8);
+    }
+    unsafe { asm!("sev" :::: "volatile"); }
+}
+
+/// Enables the interrupts of the calling core: its timer and the IPIs of the
+/// other cores.
+pub fn initialize_core() {
+    let mut local = LocalController::new(core());
+    local.enable_timer();
+    local.enable_mailbox(IPI_MAILBOX);
+}
+
+/// Marks the calling core as idle or busy. Idle cores are woken by
+/// `wake_idle`.
+pub fn set_idle(idle: bool) {
+    let bit = 1 << core();
+    if idle {
+        IDLE.fetch_or(bit, Ordering::SeqCst);
+    } else {
+        IDLE.fetch_and(!bit, Ordering::SeqCst);
+    }
+}
+
+/// Interrupts the idle cores other than the calling one, so that they look
+/// for a process to run again.
+pub fn wake_idle() {
+    let me = core();
+    let idle = IDLE.load(Ordering::SeqCst);
+    let mut local = LocalController::new(me);
+    for core in (0..NCORES).filter(|&core| core != me && idle & (1 << core) != 0) {
+        local.send(core, IPI_MAILBOX, 1);
+    }
+}
+
+/// Acknowledges the IPIs sent to the calling core. Returns `true` if there
+/// were any.
+pub fn take_ipi() -> bool {
+    LocalController::new(core()).take(IPI_MAILBOX) != 0
+}
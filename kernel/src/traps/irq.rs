@@ -1,15 +1,98 @@
-use pi::interrupt::Interrupt;
-use pi::timer::tick_in;
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use pi::interrupt::{Controller, Interrupt};
+use pi::local::{self, LocalController, LocalInterrupt};
 use traps::TrapFrame;
-use process::{State, TICK};
+use process::{State, WaitQueue};
+use process::kthread;
+use process::signal::{self, SIGINT};
+use console::{self, kprintln, CONSOLE};
 use SCHEDULER;
+use smp;
 
-pub fn handle_irq(interrupt: Interrupt, tf: &mut TrapFrame) {
+pub fn handle_irq(interrupt: Interrupt) {
     match interrupt {
-        Interrupt::Timer1 => {
-            tick_in(TICK);
-            SCHEDULER.switch(State::Ready, tf).unwrap();
-        }
+        Interrupt::Aux => defer_input(),
         _ => unimplemented!(),
     }
 }
+
+pub fn handle_local_irq(interrupt: LocalInterrupt, tf: &mut TrapFrame) {
+    match interrupt {
+        // the scheduler sets up the next tick when it switches
+        LocalInterrupt::Timer => { SCHEDULER.switch(State::Ready, tf).unwrap(); }
+        // the core was woken to schedule, but it got a process meanwhile
+        LocalInterrupt::Mailbox0 => { smp::take_ipi(); }
+        other => unexpected(other),
+    }
+}
+
+/// Whether an unexpected local interrupt was reported already.
+static REPORTED: AtomicBool = AtomicBool::new(false);
+
+/// Acknowledges a local interrupt nothing waits for, such as a mailbox other
+/// than the one of the IPIs. Only the first one is reported.
+fn unexpected(interrupt: LocalInterrupt) {
+    let mailbox = match interrupt {
+        LocalInterrupt::Mailbox1 => Some(1),
+        LocalInterrupt::Mailbox2 => Some(2),
+        LocalInterrupt::Mailbox3 => Some(3),
+        _ => None,
+    };
+    if let Some(mailbox) = mailbox {
+        LocalController::new(smp::core()).take(mailbox);
+    }
+    if !REPORTED.swap(true, Ordering::Relaxed) {
+        kprintln!("--- ignoring unexpected interrupt {:?} on core {}", interrupt, smp::core());
+    }
+}
+
+/// Handles the interrupts pending on the calling core while IRQs are masked.
+/// The scheduler calls this when a core wakes from idling, without holding
+/// its lock, so that events arriving meanwhile wake the processes waiting for
+/// them. The GPU interrupts are only routed to core 0.
+pub fn handle_pending_irqs() {
+    let core = smp::core();
+    if LocalController::new(core).is_pending(LocalInterrupt::Timer) {
+        local::clear_tick();
+    }
+    smp::take_ipi();
+    if core == 0 && Controller::new().is_pending(Interrupt::Aux) {
+        defer_input();
+    }
+}
+
+/// Woken when the console received input for `input_thread` to handle.
+static INPUT_PENDING: WaitQueue = WaitQueue::new();
+
+/// The top half of the `Aux` interrupt: masks the interrupt, raised until
+/// the received bytes are taken, and wakes `input_thread` to take them.
+fn defer_input() {
+    Controller::new().disable(Interrupt::Aux);
+    INPUT_PENDING.wake_all();
+}
+
+/// The bottom half of the `Aux` interrupt, run by a kernel thread: handles
+/// the console input outside of the trap, then unmasks the interrupt again.
+pub extern "C" fn input_thread(_: u64) {
+    loop {
+        receive_input();
+        Controller::new().enable(Interrupt::Aux);
+        kthread::wait(&INPUT_PENDING);
+    }
+}
+
+/// Buffers the console input and wakes its readers. Ctrl-C interrupts the
+/// user processes with `SIGINT`.
+fn receive_input() {
+    let (received, interrupted) = {
+        let mut console = CONSOLE.lock().unwrap();
+        (console.receive(), console.take_interrupt())
+    };
+    if received {
+        console::INPUT.wake_all();
+    }
+    if interrupted {
+        signal::broadcast(SIGINT);
+    }
+}
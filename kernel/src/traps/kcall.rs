@@ -0,0 +1,26 @@
+use pi::timer::current_time;
+use process::{State, WaitQueue};
+use traps::TrapFrame;
+use SCHEDULER;
+
+/// Handles the call `num` of a kernel thread: a `svc` taken from EL1. See
+/// `process::kthread` for the calls.
+pub fn handle_kcall(num: u16, tf: &mut TrapFrame) {
+    match num {
+        0 => {
+            SCHEDULER.switch(State::Ready, tf).expect("yield");
+        }
+        1 => {
+            let deadline = current_time() + tf.x0 * 1000;
+            SCHEDULER.block(&[], Some(deadline), None, tf);
+        }
+        2 => {
+            let queue = unsafe { &*(tf.x0 as *const WaitQueue) };
+            SCHEDULER.block(&[queue], None, None, tf);
+        }
+        3 => {
+            SCHEDULER.switch(State::Exit(0), tf).expect("exit");
+        }
+        _ => panic!("unknown kernel call {}\n{:?}", num, tf),
+    }
+}
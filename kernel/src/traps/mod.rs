@@ -2,16 +2,26 @@ mod irq;
 mod trap_frame;
 mod syndrome;
 mod syscall;
+mod kcall;
 
 use pi::interrupt::{Controller, Interrupt};
+use pi::local::{LocalController, LocalInterrupt};
 
 pub use self::trap_frame::TrapFrame;
-pub use self::syscall::Error;
+pub use self::syscall::{Error, OpenFlags, WaitOptions};
+pub use self::irq::{handle_pending_irqs, input_thread};
+
+use sys::io::{self, Write};
 
 use console::kprintln;
+use process::State;
+use process::signal::{self, KILLED, CORE_DUMPED, SIGSEGV, SIGBUS, SIGILL};
+use vm::{VirtualAddr, Prot, FaultError};
 use self::syndrome::{Syndrome, Fault};
-use self::irq::handle_irq;
+use self::irq::{handle_irq, handle_local_irq};
+use smp;
 use self::syscall::handle_syscall;
+use self::kcall::handle_kcall;
 
 #[repr(u16)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
@@ -44,35 +54,68 @@ pub struct Info {
 /// the trap frame for the exception.
 #[no_mangle]
 pub extern fn handle_exception(info: Info, esr: u32, tf: &mut TrapFrame) {
+    handle(info, esr, tf);
+    // the exception returns to the current process, which may have changed
+    deliver_signals(tf);
+}
+
+fn handle(info: Info, esr: u32, tf: &mut TrapFrame) {
     use self::Kind::*;
     use self::Source::*;
     use self::Interrupt::*;
 
     let syndrome = Syndrome::from(esr as u32);
     let ctl = Controller::new();
+    let local = LocalController::new(smp::core());
     match info.source {
+        // kernel threads
+        CurrentSpEl0 => match (info.kind, syndrome) {
+            (Synchronous, Syndrome::Svc(num)) => return handle_kcall(num, tf),
+            _ => (),
+        },
         LowerAArch64 => match info.kind {
             Synchronous => {
-                match syndrome {
-                    Syndrome::Svc(num) => return handle_syscall(num, tf),
-                    Syndrome::Brk(num) => return handle_brk(num, tf),
-                    Syndrome::DataAbort { kind, level } =>
-                        return handle_page_fault(kind, level, tf, ::aarch64::far_el1()),
-                    _ => (),
-                }
+                let far = ::aarch64::far_el1();
+                return match syndrome {
+                    Syndrome::Svc(num) => handle_syscall(num, tf),
+                    Syndrome::Brk(num) => handle_brk(num, tf),
+                    Syndrome::DataAbort { kind, write, .. } => {
+                        let access = if write { Prot::WRITE } else { Prot::READ };
+                        handle_page_fault(kind, access, syndrome, tf, far)
+                    }
+                    Syndrome::InstructionAbort { kind, .. } =>
+                        handle_page_fault(kind, Prot::EXEC, syndrome, tf, far),
+                    Syndrome::PCAlignmentFault | Syndrome::SpAlignmentFault =>
+                        raise(SIGBUS, syndrome, tf, far),
+                    _ => raise(SIGILL, syndrome, tf, far),
+                };
             }
 
-            Irq if ctl.is_pending(Timer1) => return handle_irq(Timer1, tf),
-            Irq if ctl.is_pending(Timer3) => return handle_irq(Timer3, tf),
-            Irq if ctl.is_pending(Usb   ) => return handle_irq(Usb   , tf),
-            Irq if ctl.is_pending(Gpio0 ) => return handle_irq(Gpio0 , tf),
-            Irq if ctl.is_pending(Gpio1 ) => return handle_irq(Gpio1 , tf),
-            Irq if ctl.is_pending(Gpio2 ) => return handle_irq(Gpio2 , tf),
-            Irq if ctl.is_pending(Gpio3 ) => return handle_irq(Gpio3 , tf),
-            Irq if ctl.is_pending(Uart  ) => return handle_irq(Uart  , tf),
+            Irq if local.is_pending(LocalInterrupt::Timer) =>
+                return handle_local_irq(LocalInterrupt::Timer, tf),
+            Irq if local.is_pending(LocalInterrupt::Mailbox0) =>
+                return handle_local_irq(LocalInterrupt::Mailbox0, tf),
+            Irq if local.is_pending(LocalInterrupt::Mailbox1) =>
+                return handle_local_irq(LocalInterrupt::Mailbox1, tf),
+            Irq if local.is_pending(LocalInterrupt::Mailbox2) =>
+                return handle_local_irq(LocalInterrupt::Mailbox2, tf),
+            Irq if local.is_pending(LocalInterrupt::Mailbox3) =>
+                return handle_local_irq(LocalInterrupt::Mailbox3, tf),
+            Irq if ctl.is_pending(Timer3) => return handle_irq(Timer3),
+            Irq if ctl.is_pending(Usb   ) => return handle_irq(Usb   ),
+            Irq if ctl.is_pending(Aux   ) => return handle_irq(Aux   ),
+            Irq if ctl.is_pending(Gpio0 ) => return handle_irq(Gpio0 ),
+            Irq if ctl.is_pending(Gpio1 ) => return handle_irq(Gpio1 ),
+            Irq if ctl.is_pending(Gpio2 ) => return handle_irq(Gpio2 ),
+            Irq if ctl.is_pending(Gpio3 ) => return handle_irq(Gpio3 ),
+            Irq if ctl.is_pending(Uart  ) => return handle_irq(Uart  ),
 
             _ => (),
         },
+        LowerAArch32 => match info.kind {
+            Synchronous => return raise(SIGILL, syndrome, tf, ::aarch64::far_el1()),
+            _ => (),
+        },
         _ => (),
     }
     panic!("IT'S A TRAP: {:?} {:?} {:?} esr: {:08X} far: {:X}\n{:?}",
@@ -84,15 +127,82 @@ fn handle_brk(num: u16, tf: &mut TrapFrame) {
     tf.elr += 4;
 }
 
-fn handle_page_fault(kind: Fault, level: u8, tf: &mut TrapFrame, far: u64) {
-    use self::Fault::*;
+/// Resolves a fault of the current process accessing `far` with `access`:
+/// backs pages that were not touched yet and copies copy-on-write pages.
+/// Raises `SIGSEGV` if the access is outside of the areas of the process, not
+/// permitted, or needs a page beyond its limit. If no memory is left, the
+/// access is retried once the OOM killer freed some.
+fn handle_page_fault(kind: Fault, access: Prot, syndrome: Syndrome, tf: &mut TrapFrame, far: u64) {
+    let mut result = Err(FaultError::Invalid);
     match kind {
-        Translation => {
-            kprintln!("### Translation[{}]: {:X}", level, far);
-            ::SCHEDULER.current(|process| {
-                process.mm.page_fault(far);
-            });
+        Fault::Translation => ::SCHEDULER.current(|process| {
+            result = process.mm().page_fault(far as usize, access);
+        }),
+        Fault::Permission if access == Prot::WRITE => ::SCHEDULER.current(|process| {
+            let addr = VirtualAddr::from(far as usize as *mut u8);
+            result = process.mm().copy_on_write(addr);
+        }),
+        _ => (),
+    }
+    match result {
+        Ok(()) => (),
+        Err(FaultError::NoMemory) => out_of_memory(syndrome, tf, far),
+        Err(_) => raise(SIGSEGV, syndrome, tf, far),
+    }
+}
+
+/// Frees memory for the current process, whose fault could not be resolved
+/// as no memory is left: kills the user process using the most pages. Unless
+/// the victim is the current process, switches away to let the victim exit;
+/// the faulting access is retried when the current process runs again.
+fn out_of_memory(syndrome: Syndrome, tf: &mut TrapFrame, far: u64) {
+    match ::SCHEDULER.oom_kill() {
+        Some(victim) if victim.as_u64() != tf.pid => {
+            ::SCHEDULER.switch(State::Ready, tf).expect("out of memory");
         }
-        _ => panic!("PAGE FALUT: {:?}[{}] far: {:X}\n{:?}", kind, level, far, tf),
+        Some(_) => (),
+        None => raise(SIGSEGV, syndrome, tf, far),
     }
 }
+
+/// Raises the signal `sig` for an exception of the current process.
+fn raise(sig: u32, syndrome: Syndrome, tf: &TrapFrame, far: u64) {
+    kprintln!("--- {} {}: {:?} at {:X} far: {:X}", signal::name(sig), tf.pid, syndrome, tf.elr, far);
+    ::SCHEDULER.current(|process| process.signals.force(sig));
+}
+
+/// Delivers the signals of the current process before the exception returns
+/// to it. If a signal terminates the process, switches to the next process,
+/// which may have signals to deliver as well.
+fn deliver_signals(tf: &mut TrapFrame) {
+    loop {
+        let mut terminated = None;
+        let mut core = None;
+        ::SCHEDULER.current(|process| {
+            terminated = process.deliver_signals(tf);
+            if let Some((_, true)) = terminated {
+                core = Some(process.core_dump(tf));
+            }
+        });
+        let sig = match terminated {
+            Some((sig, _)) => sig,
+            None => return,
+        };
+
+        let mut code = KILLED | sig;
+        if let Some(core) = core {
+            match write_core(tf.pid, &core) {
+                Ok(()) => code |= CORE_DUMPED,
+                Err(err) => kprintln!("--- core {}: {:?}", tf.pid, err),
+            }
+        }
+        kprintln!("--- {} {}: terminated", signal::name(sig), tf.pid);
+        ::SCHEDULER.switch(State::Exit(code), tf).expect("terminate");
+    }
+}
+
+/// Writes the core file of the process `pid`: `/var/core.<pid>`.
+fn write_core(pid: u64, core: &str) -> io::Result<()> {
+    let mut file = ::FILE_SYSTEM.create_file(&format!("/var/core.{}", pid))?;
+    file.write_all(core.as_bytes())
+}
@@ -64,7 +64,9 @@ pub enum Syndrome {
     PCAlignmentFault,
     DataAbort {
         kind: Fault,
-        level: u8
+        level: u8,
+        /// The access was a write.
+        write: bool,
     },
     SpAlignmentFault,
     TrappedFpu,
@@ -109,6 +111,7 @@ impl From<u32> for Syndrome {
             0b100100 | 0b100101 => DataAbort {
                 kind: Fault::from(esr),
                 level: (esr & 0b11) as u8,
+                write: esr & (1 << 6) != 0,
             },
             0b100110 => SpAlignmentFault,
             0b101000 | 0b101100 => TrappedFpu,
@@ -1,13 +1,31 @@
-use alloc::slice::from_raw_parts;
-use alloc::str::from_utf8;
+use core::cmp::min;
+use core::mem::{self, size_of, align_of};
+use core::ptr;
+use alloc::slice::{from_raw_parts, from_raw_parts_mut};
+use alloc::string::String;
 use alloc::boxed::Box;
+use alloc::vec::Vec;
+use alloc::arc::Arc;
+
+use sys::io::{self, Read, Write, Seek};
+use vfat::vfat::Shared;
 
 use traps::TrapFrame;
 use console::kprintln;
-use process::{State, Process};
+use process::{State, Process, Group, OpenFile, Handle, Id, Wakeup, TICK, NICE_MIN, NICE_MAX};
+use process::rlimit::Limit;
+use process::futex;
+use process::ipc::{self, Port, Message, Envelope, Capability, CallState, CAP_NONE, CAP_FD, CAP_PAGE};
+use process::signal::{Action, NSIG, SIG_DFL, SIG_IGN, SIG_BLOCK, SIG_UNBLOCK, SIG_SETMASK, SIGSEGV, SIGPIPE};
 use pi::timer::current_time;
-use console::CONSOLE;
+use console::{self, CONSOLE};
+use fs::vfs::Node;
+use fs::pipe;
+use vm::{Prot, Map, FaultError, Memory, SharedMemory, PhysicalAddr, VirtualAddr, PAGESZ, p2v};
+use vm::shm;
+use elf;
 use SCHEDULER;
+use FILE_SYSTEM;
 
 #[repr(u64)]
 #[derive(Debug)]
@@ -15,6 +33,19 @@ pub enum Error {
     SyscallDoesNotExist = 1,
     Utf8 = 2,
     Io = 3,
+    BadDescriptor = 4,
+    BadAddress = 5,
+    NotFound = 6,
+    PermissionDenied = 7,
+    AlreadyExists = 8,
+    InvalidInput = 9,
+    TooManyFiles = 10,
+    WouldBlock = 11,
+    NoMemory = 12,
+    NotExecutable = 13,
+    NoChildren = 14,
+    BrokenPipe = 15,
+    TimedOut = 16,
 
     Other = 0xFFFF_FFFF,
 }
@@ -26,11 +57,59 @@ impl From<u64> for Error {
             1 => SyscallDoesNotExist,
             2 => Utf8,
             3 => Io,
+            4 => BadDescriptor,
+            5 => BadAddress,
+            6 => NotFound,
+            7 => PermissionDenied,
+            8 => AlreadyExists,
+            9 => InvalidInput,
+            10 => TooManyFiles,
+            11 => WouldBlock,
+            12 => NoMemory,
+            13 => NotExecutable,
+            14 => NoChildren,
+            15 => BrokenPipe,
+            16 => TimedOut,
             _ => Other,
         }
     }
 }
 
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Self {
+        match err.kind() {
+            io::ErrorKind::NotFound => Error::NotFound,
+            io::ErrorKind::PermissionDenied => Error::PermissionDenied,
+            io::ErrorKind::AlreadyExists => Error::AlreadyExists,
+            io::ErrorKind::InvalidInput => Error::InvalidInput,
+            io::ErrorKind::WouldBlock => Error::WouldBlock,
+            io::ErrorKind::BrokenPipe => Error::BrokenPipe,
+            io::ErrorKind::TimedOut => Error::TimedOut,
+            _ => Error::Io,
+        }
+    }
+}
+
+bitflags! {
+    /// Flags of the `open` system call.
+    pub struct OpenFlags: u64 {
+        /// Create the file if it does not exist.
+        const CREATE    = 1 << 0;
+        /// Fail unless the path names a directory.
+        const DIRECTORY = 1 << 1;
+        /// Close the descriptor when the process executes a new program.
+        const CLOSE_ON_EXEC = 1 << 2;
+    }
+}
+
+bitflags! {
+    /// Options of the `wait` system call.
+    pub struct WaitOptions: u64 {
+        /// Return immediately if no child has exited.
+        const NO_HANG = 1 << 0;
+    }
+}
+
 
 // Syscall Convention:
 //
@@ -51,56 +130,1060 @@ impl From<u64> for Error {
 /// parameter: the approximate true elapsed time from when `sleep` was called to
 /// when `sleep` returned.
 pub fn sleep(ms: u32, tf: &mut TrapFrame) {
-    let exit_time = current_time() + 1000 * ms as u64;
-    let f = Box::new(move |p: &mut Process| {
-        let elapsed = current_time().checked_sub(exit_time);
-        if let Some(elapsed) = elapsed {
-            p.trap_frame.x0 = (elapsed / 1000) & 0xFFFF_FFFF;
-            true
-        } else {
-            false
-        }
+    let start = current_time();
+    let f = Box::new(move |p: &mut Process, _: Wakeup| {
+        let elapsed = current_time() - start;
+        p.trap_frame.x0 = (elapsed / 1000) & 0xFFFF_FFFF;
     });
-    SCHEDULER.switch(State::Waiting(f), tf).expect("sleep");
+    SCHEDULER.block(&[], Some(start + 1000 * ms as u64), Some(f), tf);
 }
 
-pub fn print(s: *const u8, len: usize) -> Result<(), Error> {
+pub fn print(s: u64, len: u64) -> Result<(), Error> {
     use core::fmt::Write;
 
-    let s = from_utf8(unsafe { from_raw_parts(s, len) })
-        .map_err(|_| Error::Utf8)?;
-    CONSOLE.lock().unwrap().write_str(s)
+    let s = user_string(s, len)?;
+    CONSOLE.lock().unwrap().write_str(&s)
         .map_err(|_| Error::Io)
 }
 
 pub fn read_byte(tf: &mut TrapFrame) {
-    let f = Box::new(move |p: &mut Process| {
-        let mut c = CONSOLE.lock().unwrap();
-        let has = c.has_byte();
-        if has {
-            p.trap_frame.x0 = c.read_byte() as u64;
+    let byte = {
+        let mut console = CONSOLE.lock().unwrap();
+        if console.has_byte() { Some(console.read_byte()) } else { None }
+    };
+    match byte {
+        Some(byte) => tf.x0 = byte as u64,
+        None => {
+            // re-execute the `svc` once input arrived
+            tf.elr -= 4;
+            SCHEDULER.block(&[&console::INPUT], None, None, tf);
+        }
+    }
+}
+
+/// How many bytes `read` and `write` move between user space and a file at
+/// once.
+const IO_CHUNK: usize = 4 * PAGESZ;
+
+/// Returns the error of a system call failing to access user memory.
+fn fault_error(err: FaultError) -> Error {
+    match err {
+        FaultError::NoMemory => Error::NoMemory,
+        _ => Error::BadAddress,
+    }
+}
+
+/// Returns the group of the current process. User memory is only accessed
+/// with the address space of the group locked: other threads of the process
+/// could unmap it or make it copy-on-write otherwise.
+fn current_group() -> Arc<Group> {
+    let mut group = None;
+    SCHEDULER.current(|process| group = Some(process.group.clone()));
+    group.expect("current process")
+}
+
+/// Checks that the current process may access `len` bytes at `addr` with
+/// `prot`, backing pages it did not touch yet.
+fn check_user(addr: u64, len: u64, prot: Prot) -> Result<(), Error> {
+    let group = current_group();
+    let result = group.mm().fault_in(addr as usize, len as usize, prot);
+    result.map_err(fault_error)
+}
+
+/// Copies the user memory at `addr` into `buf`. The pages are reached
+/// through the kernel mapping of their frames.
+fn read_user(addr: u64, buf: &mut [u8]) -> Result<(), Error> {
+    let group = current_group();
+    let mut mm = group.mm();
+    mm.fault_in(addr as usize, buf.len(), Prot::READ).map_err(fault_error)?;
+    mm.copy_from(addr as usize, buf).ok_or(Error::BadAddress)
+}
+
+/// Copies `buf` to the user memory at `addr`. See `read_user`.
+fn write_user(addr: u64, buf: &[u8]) -> Result<(), Error> {
+    let group = current_group();
+    let mut mm = group.mm();
+    mm.fault_in(addr as usize, buf.len(), Prot::RW).map_err(fault_error)?;
+    mm.copy_to(addr as usize, buf).ok_or(Error::BadAddress)
+}
+
+/// Returns a zeroed buffer of `len` bytes.
+fn buffer(len: usize) -> Result<Vec<u8>, Error> {
+    let mut buf = Vec::new();
+    buf.try_reserve_exact(len).map_err(|_| Error::NoMemory)?;
+    buf.resize(len, 0);
+    Ok(buf)
+}
+
+/// Returns a copy of the `len` bytes of user memory at `addr`.
+fn user_bytes(addr: u64, len: u64) -> Result<Vec<u8>, Error> {
+    check_user(addr, len, Prot::READ)?;
+    let mut buf = buffer(len as usize)?;
+    read_user(addr, &mut buf)?;
+    Ok(buf)
+}
+
+/// Returns a copy of the UTF-8 string of `len` bytes at `addr`.
+fn user_string(addr: u64, len: u64) -> Result<String, Error> {
+    String::from_utf8(user_bytes(addr, len)?).map_err(|_| Error::Utf8)
+}
+
+/// Returns a copy of the `T` at `addr`, which must be aligned for it.
+fn get_user<T: Copy>(addr: u64) -> Result<T, Error> {
+    if addr as usize % align_of::<T>() != 0 {
+        return Err(Error::BadAddress);
+    }
+    let mut value: T = unsafe { mem::zeroed() };
+    let bytes = unsafe { from_raw_parts_mut(&mut value as *mut T as *mut u8, size_of::<T>()) };
+    read_user(addr, bytes)?;
+    Ok(value)
+}
+
+/// Copies `value` to the user memory at `addr`, which must be aligned for
+/// it.
+fn put_user<T: Copy>(addr: u64, value: &T) -> Result<(), Error> {
+    if addr as usize % align_of::<T>() != 0 {
+        return Err(Error::BadAddress);
+    }
+    let bytes = unsafe { from_raw_parts(value as *const T as *const u8, size_of::<T>()) };
+    write_user(addr, bytes)
+}
+
+/// Returns the handle behind the descriptor `fd` of the current process.
+fn handle(fd: u64) -> Result<Handle, Error> {
+    let mut handle = None;
+    SCHEDULER.current(|process| handle = process.files().get(fd as usize));
+    handle.ok_or(Error::BadDescriptor)
+}
+
+/// Opens the file at the absolute path `path` (`len` bytes) and returns its
+/// descriptor. See `OpenFlags` for `flags`.
+pub fn open(path: u64, len: u64, flags: u64) -> Result<u64, Error> {
+    let path = user_string(path, len)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(Error::InvalidInput)?;
+
+    let node = match FILE_SYSTEM.open(&path) {
+        Err(ref err) if err.kind() == io::ErrorKind::NotFound && flags.contains(OpenFlags::CREATE) => {
+            Node::File(FILE_SYSTEM.create_file(&path)?)
+        }
+        result => result?,
+    };
+    if let Node::File(_) = node {
+        if flags.contains(OpenFlags::DIRECTORY) {
+            return Err(Error::InvalidInput);
+        }
+    }
+
+    let handle = Shared::new(OpenFile::new(node)?);
+    let mut fd = None;
+    let close_on_exec = flags.contains(OpenFlags::CLOSE_ON_EXEC);
+    SCHEDULER.current(|process| fd = process.files().insert(handle, close_on_exec));
+    fd.map(|fd| fd as u64).ok_or(Error::TooManyFiles)
+}
+
+/// Closes the descriptor `fd`.
+pub fn close(fd: u64) -> Result<(), Error> {
+    let mut handle = None;
+    SCHEDULER.current(|process| handle = process.files().remove(fd as usize));
+    handle.map(|_| ()).ok_or(Error::BadDescriptor)
+}
+
+/// Creates a pipe and returns the descriptors of its read and write ends in
+/// `x0` and `x1`. The only flag accepted is `OpenFlags::CLOSE_ON_EXEC`,
+/// applying to both descriptors.
+pub fn pipe(flags: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let flags = OpenFlags::from_bits(flags).ok_or(Error::InvalidInput)?;
+    if flags - OpenFlags::CLOSE_ON_EXEC != OpenFlags::empty() {
+        return Err(Error::InvalidInput);
+    }
+    let close_on_exec = flags.contains(OpenFlags::CLOSE_ON_EXEC);
+    let (reader, writer) = pipe::pipe();
+    let reader = Shared::new(OpenFile::PipeReader(reader));
+    let writer = Shared::new(OpenFile::PipeWriter(writer));
+
+    let mut fds = (None, None);
+    SCHEDULER.current(|process| {
+        let read = process.files().insert(reader, close_on_exec);
+        let write = read.and_then(|_| process.files().insert(writer, close_on_exec));
+        if let (Some(read), None) = (read, write) {
+            process.files().remove(read);
+        }
+        fds = (read, write);
+    });
+    match fds {
+        (Some(read), Some(write)) => {
+            tf.x0 = read as u64;
+            tf.x1 = write as u64;
+            Ok(())
+        }
+        _ => Err(Error::TooManyFiles),
+    }
+}
+
+/// Opens the lowest free descriptor onto the file of `fd` and returns it.
+pub fn dup(fd: u64) -> Result<u64, Error> {
+    let handle = handle(fd)?;
+    let mut new = None;
+    SCHEDULER.current(|process| new = process.files().insert(handle, false));
+    new.map(|fd| fd as u64).ok_or(Error::TooManyFiles)
+}
+
+/// Makes the descriptor `new` refer to the file of `fd`, closing the file
+/// `new` referred to, and returns `new`. Nothing changes if both are equal.
+pub fn dup2(fd: u64, new: u64) -> Result<u64, Error> {
+    let handle = handle(fd)?;
+    if fd == new {
+        return Ok(new);
+    }
+    let mut old = None;
+    SCHEDULER.current(|process| old = process.files().replace(new as usize, handle, false));
+    // the replaced file is closed outside of the scheduler lock
+    old.map(|_| new).ok_or(Error::BadDescriptor)
+}
+
+/// Reads up to `len` bytes from `fd` into `buf` and returns the number of
+/// bytes read.
+///
+/// Fails with `Error::WouldBlock` if no data is available yet.
+pub fn read(fd: u64, buf: u64, len: u64) -> Result<u64, Error> {
+    let handle = handle(fd)?;
+    check_user(buf, len, Prot::RW)?;
+    let len = len as usize;
+    let mut chunk = buffer(min(len, IO_CHUNK))?;
+    let mut done = 0;
+    while done < len {
+        let n = min(chunk.len(), len - done);
+        let result = match *handle.borrow_mut() {
+            OpenFile::File(ref mut file) => file.read(&mut chunk[..n]),
+            OpenFile::PipeReader(ref mut reader) => reader.read(&mut chunk[..n]),
+            _ => return Err(Error::InvalidInput),
+        };
+        let read = match result {
+            Ok(read) => read,
+            Err(_) if done > 0 => break,
+            Err(err) => return Err(err.into()),
+        };
+        write_user(buf + done as u64, &chunk[..read])?;
+        done += read;
+        if read < n {
+            break;
+        }
+    }
+    Ok(done as u64)
+}
+
+/// Returns the id of the current process.
+fn current_id() -> Id {
+    let mut id = None;
+    SCHEDULER.current(|process| id = process.id());
+    id.expect("current process")
+}
+
+/// Makes the current process wait until `fd` is readable and restarts the
+/// system call that would have blocked on it.
+fn block_read(fd: u64, tf: &mut TrapFrame) {
+    let handle = match handle(fd) {
+        Ok(handle) => handle,
+        Err(err) => {
+            tf.x7 = err as u64;
+            return;
+        }
+    };
+    let (queue, pipe) = match *handle.borrow() {
+        OpenFile::File(ref file) => (file.read_queue(), None),
+        OpenFile::PipeReader(ref reader) => (None, Some(reader.pipe())),
+        _ => (None, None),
+    };
+    // re-execute the `svc` once the file is readable, or after a tick for
+    // files that are not woken
+    tf.elr -= 4;
+    match (queue, pipe) {
+        (Some(queue), _) => SCHEDULER.block(&[queue], None, None, tf),
+        (None, Some(pipe)) => {
+            // the pipe may have been written to meanwhile: retry right away
+            if pipe.wait_readable(current_id()) {
+                SCHEDULER.block(&[], None, None, tf);
+            }
         }
-        has
+        (None, None) => SCHEDULER.block(&[], Some(current_time() + TICK as u64), None, tf),
+    }
+}
+
+/// Writes `len` bytes from `buf` to `fd` and returns the number of bytes
+/// written.
+///
+/// Fails with `Error::WouldBlock` if a pipe is full, and with
+/// `Error::BrokenPipe` if the read end of a pipe is closed, in which case
+/// the current process is sent `SIGPIPE`.
+pub fn write(fd: u64, buf: u64, len: u64) -> Result<u64, Error> {
+    let handle = handle(fd)?;
+    check_user(buf, len, Prot::READ)?;
+    let len = len as usize;
+    let mut chunk = buffer(min(len, IO_CHUNK))?;
+    let mut done = 0;
+    while done < len {
+        let n = min(chunk.len(), len - done);
+        read_user(buf + done as u64, &mut chunk[..n])?;
+        let result = match *handle.borrow_mut() {
+            OpenFile::File(ref mut file) => file.write(&chunk[..n]),
+            OpenFile::PipeWriter(ref mut writer) => writer.write(&chunk[..n]),
+            _ => return Err(Error::InvalidInput),
+        };
+        match result {
+            Ok(written) => {
+                done += written;
+                if written < n {
+                    break;
+                }
+            }
+            Err(_) if done > 0 => break,
+            Err(ref err) if err.kind() == io::ErrorKind::BrokenPipe => {
+                SCHEDULER.current(|process| { process.signal(SIGPIPE); });
+                return Err(Error::BrokenPipe);
+            }
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(done as u64)
+}
+
+/// Makes the current process wait until the pipe `fd` is writable and
+/// restarts the system call that would have blocked on it.
+fn block_write(fd: u64, tf: &mut TrapFrame) {
+    let handle = match handle(fd) {
+        Ok(handle) => handle,
+        Err(err) => {
+            tf.x7 = err as u64;
+            return;
+        }
+    };
+    let pipe = match *handle.borrow() {
+        OpenFile::PipeWriter(ref writer) => writer.pipe(),
+        _ => {
+            tf.x7 = Error::WouldBlock as u64;
+            return;
+        }
+    };
+    tf.elr -= 4;
+    if pipe.wait_writable(current_id()) {
+        SCHEDULER.block(&[], None, None, tf);
+    }
+}
+
+/// Moves the position of `fd` by `offset` bytes relative to the start (`0`),
+/// the current position (`1`) or the end (`2`) and returns the new position.
+pub fn seek(fd: u64, offset: u64, whence: u64) -> Result<u64, Error> {
+    let pos = match whence {
+        0 => io::SeekFrom::Start(offset),
+        1 => io::SeekFrom::Current(offset as i64),
+        2 => io::SeekFrom::End(offset as i64),
+        _ => return Err(Error::InvalidInput),
+    };
+    let handle = handle(fd)?;
+    let result = match *handle.borrow_mut() {
+        OpenFile::File(ref mut file) => file.seek(pos),
+        _ => return Err(Error::InvalidInput),
+    };
+    Ok(result?)
+}
+
+/// Performs the control operation `request` with the argument `arg` on the
+/// device `fd` and returns its result. The requests are listed in
+/// `fs::devfs::ioctl`. Fails with `Error::InvalidInput` if the file does not
+/// support the request.
+pub fn ioctl(fd: u64, request: u64, arg: u64) -> Result<u64, Error> {
+    let handle = handle(fd)?;
+    let result = match *handle.borrow_mut() {
+        OpenFile::File(ref mut file) => file.ioctl(request, arg),
+        _ => return Err(Error::InvalidInput),
+    };
+    Ok(result?)
+}
+
+/// Writes the metadata of the node at the absolute path `path` (`len`
+/// bytes) to the `Stat` at `stat`.
+pub fn stat(path: u64, len: u64, stat: u64) -> Result<(), Error> {
+    let path = user_string(path, len)?;
+    let result = FILE_SYSTEM.stat(&path)?;
+    put_user(stat, &result)
+}
+
+/// Reads the next entry of the directory `fd`: writes its metadata to the
+/// `Stat` at `stat` and its name to `name` (`len` bytes), and returns the
+/// length of the name. Returns `0` once all entries were read.
+pub fn readdir(fd: u64, stat: u64, name: u64, len: u64) -> Result<u64, Error> {
+    let handle = handle(fd)?;
+    let mut open = handle.borrow_mut();
+    match *open {
+        OpenFile::Dir { ref entries, ref mut position } => {
+            let entry = match entries.get(*position) {
+                Some(entry) => entry,
+                None => return Ok(0),
+            };
+            let bytes = entry.name.as_bytes();
+            if bytes.len() as u64 > len {
+                return Err(Error::InvalidInput);
+            }
+            put_user(stat, &entry.stat)?;
+            write_user(name, bytes)?;
+            *position += 1;
+            Ok(bytes.len() as u64)
+        }
+        _ => Err(Error::InvalidInput),
+    }
+}
+
+/// Loads the executable at the absolute path `path` (`len` bytes) into a new
+/// process. `args` and `env` point to `args_len` and `env_len` bytes of null
+/// terminated strings: the arguments, starting with the program name, and the
+/// environment of the program.
+fn load(path: u64, len: u64, args: u64, args_len: u64, env: u64, env_len: u64) -> Result<Process, Error> {
+    let path = user_string(path, len)?;
+    let args = user_string(args, args_len)?;
+    let args: Vec<&str> = args.split_terminator('\0').collect();
+    let env = user_string(env, env_len)?;
+    let env: Vec<&str> = env.split_terminator('\0').collect();
+
+    let mut file = FILE_SYSTEM.open_file(&path)?;
+    elf::spawn(&mut *file, &args, &env).map_err(|err| match err.kind() {
+        io::ErrorKind::InvalidData => Error::NotExecutable,
+        _ => Error::from(err),
+    })
+}
+
+/// Starts the executable at `path` as a new process and returns its id. See
+/// `load` for the parameters. The process inherits the open files of the
+/// current process, except for those opened with
+/// `OpenFlags::CLOSE_ON_EXEC`.
+pub fn spawn(path: u64, len: u64, args: u64, args_len: u64, env: u64, env_len: u64) -> Result<u64, Error> {
+    let mut process = load(path, len, args, args_len, env, env_len)?;
+    let mut closed = Vec::new();
+    SCHEDULER.current(|current| {
+        process.parent = current.process_id();
+        *process.files() = current.files().clone();
+        closed = process.files().close_on_exec();
     });
-    SCHEDULER.switch(State::Waiting(f), tf).expect("sleep");
+    drop(closed);
+    SCHEDULER.add(process).map(|id| id.as_u64()).ok_or(Error::Other)
+}
+
+/// Replaces the program of the current process with the executable at
+/// `path`. See `load` for the parameters. The process keeps its id and its
+/// open files, except for those opened with `OpenFlags::CLOSE_ON_EXEC`. Its
+/// other threads are killed.
+///
+/// This system call only returns on failure, leaving the process untouched.
+/// Fails with `Error::InvalidInput` if called by a thread other than the
+/// main thread of the process.
+pub fn exec(path: u64, len: u64, args: u64, args_len: u64, env: u64, env_len: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let mut leader = None;
+    SCHEDULER.current(|process| leader = process.leader);
+    if leader.is_some() {
+        return Err(Error::InvalidInput);
+    }
+    let image = load(path, len, args, args_len, env, env_len)?;
+    let mut replaced = None;
+    SCHEDULER.current(|process| replaced = Some(process.exec(image, tf)));
+    let (closed, group) = replaced.expect("exec");
+    SCHEDULER.kill_group(&group);
+    // the files are closed outside of the scheduler lock
+    drop(closed);
+    Ok(())
+}
+
+/// Creates a copy of the current process and returns the id of the copy. The
+/// copy resumes from the same point and sees 0 as the result.
+pub fn fork(tf: &mut TrapFrame) -> Result<u64, Error> {
+    let mut child = None;
+    SCHEDULER.current(|process| child = process.fork(tf));
+    let mut child = child.ok_or(Error::NoMemory)?;
+    child.trap_frame.x0 = 0;
+    SCHEDULER.add(child).map(|id| id.as_u64()).ok_or(Error::Other)
+}
+
+/// Waits for the child `pid` of the current process, or for any child if
+/// `pid` is 0, to exit and returns the id and the exit code of the child in
+/// `x0` and `x1`. With `WaitOptions::NO_HANG`, returns 0 as the id instead of
+/// blocking.
+pub fn wait(pid: u64, options: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let options = WaitOptions::from_bits(options).ok_or(Error::InvalidInput)?;
+    let pid = Id::new(pid);
+    match SCHEDULER.reap(pid, !options.contains(WaitOptions::NO_HANG)) {
+        Ok(Some((id, code))) => {
+            tf.x0 = id.as_u64();
+            tf.x1 = code as u64;
+        }
+        Ok(None) if options.contains(WaitOptions::NO_HANG) => {
+            tf.x0 = 0;
+            tf.x1 = 0;
+        }
+        Ok(None) => block_wait(tf),
+        Err(()) => return Err(Error::NoChildren),
+    }
+    Ok(())
+}
+
+/// Blocks the current process until one of its children exits: `reap` queued
+/// it on its children already. The system call is restarted to reap the
+/// child.
+fn block_wait(tf: &mut TrapFrame) {
+    tf.elr -= 4;
+    SCHEDULER.block(&[], None, None, tf);
+}
+
+/// Sets the nice value of the process `pid`, or of the current process if
+/// `pid` is 0, to `nice`: from `NICE_MIN` to `NICE_MAX`, the lower the value
+/// the larger the share of CPU time the process gets.
+pub fn setpriority(pid: u64, nice: u64) -> Result<(), Error> {
+    let nice = nice as i64;
+    if nice < NICE_MIN as i64 || nice > NICE_MAX as i64 {
+        return Err(Error::InvalidInput);
+    }
+    let nice = nice as i8;
+    match Id::new(pid) {
+        Some(id) => SCHEDULER.with_process(id, |process| process.sched.nice = nice).ok_or(Error::NotFound),
+        None => {
+            SCHEDULER.current(|process| process.sched.nice = nice);
+            Ok(())
+        }
+    }
 }
 
+/// Sends the signal `sig` to the process `pid`. With `sig` 0, only checks
+/// that the process exists.
+pub fn kill(pid: u64, sig: u64) -> Result<(), Error> {
+    let id = Id::new(pid).ok_or(Error::InvalidInput)?;
+    if sig >= NSIG as u64 {
+        return Err(Error::InvalidInput);
+    }
+    let sent = SCHEDULER.with_process(id, |process| sig == 0 || process.signal(sig as u32));
+    match sent {
+        Some(true) => Ok(()),
+        Some(false) => Err(Error::PermissionDenied),
+        None => Err(Error::NotFound),
+    }
+}
+
+/// Sets the action of the signal `sig` of the current process and returns the
+/// previous handler. `handler` is `SIG_DFL`, `SIG_IGN` or the address of a
+/// function called with the signal number, with the signals in `mask`
+/// blocked. The function returns to `restorer`, which calls `sigreturn`.
+pub fn sigaction(sig: u64, handler: u64, mask: u64, restorer: u64) -> Result<u64, Error> {
+    if sig >= NSIG as u64 {
+        return Err(Error::InvalidInput);
+    }
+    let action = match handler {
+        SIG_DFL => Action::Default,
+        SIG_IGN => Action::Ignore,
+        handler => Action::Handler { handler, mask: mask as u32, restorer },
+    };
+    let mut old = None;
+    SCHEDULER.current(|process| old = process.signals.set_action(sig as u32, action));
+    Ok(match old.ok_or(Error::InvalidInput)? {
+        Action::Default => SIG_DFL,
+        Action::Ignore => SIG_IGN,
+        Action::Handler { handler, .. } => handler,
+    })
+}
+
+/// Returns from a signal handler to the interrupted program. The process is
+/// killed with `SIGSEGV` if its stack holds no valid signal frame.
+pub fn sigreturn(tf: &mut TrapFrame) {
+    SCHEDULER.current(|process| {
+        if process.sigreturn(tf).is_none() {
+            process.signals.force(SIGSEGV);
+        }
+    });
+}
+
+/// Changes the blocked signals of the current process with `set` as `how`
+/// says (`SIG_BLOCK`, `SIG_UNBLOCK` or `SIG_SETMASK`) and returns the
+/// previously blocked signals.
+pub fn sigprocmask(how: u64, set: u64) -> Result<u64, Error> {
+    let set = set as u32;
+    let mut old = 0;
+    let mut result = Ok(());
+    SCHEDULER.current(|process| {
+        old = process.signals.blocked();
+        let blocked = match how {
+            SIG_BLOCK => old | set,
+            SIG_UNBLOCK => old & !set,
+            SIG_SETMASK => set,
+            _ => {
+                result = Err(Error::InvalidInput);
+                return;
+            }
+        };
+        process.signals.set_blocked(blocked);
+    });
+    result.map(|_| old as u64)
+}
+
+/// Returns the soft and hard limits of `resource` (`RLIMIT_*`) of the
+/// current process in `x0` and `x1`.
+pub fn getrlimit(resource: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let limit = current_group().limits().get(resource).ok_or(Error::InvalidInput)?;
+    tf.x0 = limit.cur;
+    tf.x1 = limit.max;
+    Ok(())
+}
+
+/// Sets the soft limit of `resource` (`RLIMIT_*`) of the current process to
+/// `cur` and its hard limit to `max`. The soft limit may not exceed the hard
+/// limit, which may only be lowered.
+pub fn setrlimit(resource: u64, cur: u64, max: u64) -> Result<(), Error> {
+    if cur > max {
+        return Err(Error::InvalidInput);
+    }
+    let group = current_group();
+    match group.limits().get(resource) {
+        None => Err(Error::InvalidInput),
+        Some(old) if max > old.max => Err(Error::PermissionDenied),
+        Some(_) => {
+            group.set_limit(resource, Limit { cur, max });
+            Ok(())
+        }
+    }
+}
+
+/// Creates the IPC port named by the `len` bytes at `name` and returns the
+/// descriptor of its server end. The port and its name go away once the
+/// descriptor is closed in every process sharing it.
+pub fn port_create(name: u64, len: u64) -> Result<u64, Error> {
+    let name = user_string(name, len)?;
+    let handle = Shared::new(OpenFile::PortServer(ipc::create(&name)?));
+    let mut fd = None;
+    SCHEDULER.current(|process| fd = process.files().insert(handle, false));
+    fd.map(|fd| fd as u64).ok_or(Error::TooManyFiles)
+}
+
+/// Connects to the IPC port named by the `len` bytes at `name` and returns
+/// the descriptor of the client end.
+pub fn port_connect(name: u64, len: u64) -> Result<u64, Error> {
+    let name = user_string(name, len)?;
+    let handle = Shared::new(OpenFile::PortClient(ipc::connect(&name)?));
+    let mut fd = None;
+    SCHEDULER.current(|process| fd = process.files().insert(handle, false));
+    fd.map(|fd| fd as u64).ok_or(Error::TooManyFiles)
+}
+
+/// Returns the port behind `fd`, which must be its server end if `server`
+/// and a client end otherwise.
+fn port(fd: u64, server: bool) -> Result<Arc<Port>, Error> {
+    let handle = handle(fd)?;
+    let port = match (&*handle.borrow(), server) {
+        (&OpenFile::PortServer(ref end), true) => Ok(end.port()),
+        (&OpenFile::PortClient(ref end), false) => Ok(end.port()),
+        _ => Err(Error::InvalidInput),
+    };
+    port
+}
+
+/// Checks that the current process may write a `Message` at `addr`.
+fn check_message(addr: u64) -> Result<(), Error> {
+    if addr as usize % align_of::<Message>() != 0 {
+        return Err(Error::BadAddress);
+    }
+    check_user(addr, size_of::<Message>() as u64, Prot::RW)
+}
+
+/// Reads the `Message` at `addr` and takes the capability it names from the
+/// current process: a file stays open in the process, a page is unmapped.
+fn take_message(addr: u64) -> Result<(Message, Option<Capability>), Error> {
+    let message: Message = get_user(addr)?;
+    let cap = match message.cap {
+        CAP_NONE => None,
+        CAP_FD => Some(Capability::Fd(handle(message.value)?)),
+        CAP_PAGE => {
+            let mut page = Err(FaultError::Invalid);
+            SCHEDULER.current(|process| page = process.mm().take_page(message.value as usize));
+            let page = page.map_err(|err| match err {
+                FaultError::NoMemory => Error::NoMemory,
+                _ => Error::BadAddress,
+            })?;
+            Some(Capability::Page(page))
+        }
+        _ => return Err(Error::InvalidInput),
+    };
+    Ok((message, cap))
+}
+
+/// Gives the capability of `envelope`, taken from the current process by
+/// `take_message` and not sent, back to the process: a page is mapped again
+/// where it was.
+fn untake_message(envelope: Envelope) {
+    if let Some(Capability::Page(page)) = envelope.cap {
+        let addr = envelope.message.value as usize;
+        SCHEDULER.current(|process| { process.mm().untake_page(page, addr); });
+    }
+}
+
+/// Delivers `envelope` to the current process: installs its capability and
+/// writes its message to `addr`, checked with `check_message`. The message is
+/// lost if another thread unmapped `addr` meanwhile.
+fn put_message(addr: u64, envelope: Envelope) -> Result<(), Error> {
+    let mut message = Message::default();
+    SCHEDULER.current(|process| message = ipc::deliver(process, envelope));
+    put_user(addr, &message)
+}
+
+/// Sends the `Message` at `message` through the client end `fd` of a port
+/// and waits for the server to reply, then writes the reply to `reply`.
+/// Capabilities move along with messages: see `Message`.
+///
+/// The system call restarts until the reply arrives. Fails with
+/// `Error::BrokenPipe` if the server end is closed before replying.
+pub fn port_call(fd: u64, message: u64, reply: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let port = port(fd, false)?;
+    check_message(reply)?;
+    let id = current_id();
+    match port.call_state(id)? {
+        CallState::Replied(envelope) => return put_message(reply, envelope),
+        CallState::Waiting => {}
+        CallState::Idle => {
+            let (message, cap) = take_message(message)?;
+            if let Err(envelope) = port.send(Envelope { sender: id, message, cap }) {
+                untake_message(envelope);
+                return Err(Error::BrokenPipe);
+            }
+        }
+    }
+    // re-execute the `svc` once the call is replied to
+    tf.elr -= 4;
+    SCHEDULER.block(&[], None, None, tf);
+    Ok(())
+}
+
+/// Receives the next call on the server end `fd` of a port: writes its
+/// message to `message` and returns the id of the caller in `x0`, to reply
+/// to with `port_reply`. Waits until a call arrives.
+pub fn port_receive(fd: u64, message: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let port = port(fd, true)?;
+    check_message(message)?;
+    match port.receive(current_id()) {
+        Some(envelope) => {
+            tf.x0 = envelope.sender.as_u64();
+            put_message(message, envelope)?;
+        }
+        None => {
+            // re-execute the `svc` once a call arrives
+            tf.elr -= 4;
+            SCHEDULER.block(&[], None, None, tf);
+        }
+    }
+    Ok(())
+}
+
+/// Replies the `Message` at `message` to the call of the process `caller`
+/// received on the server end `fd` of a port. Fails with `Error::NotFound`
+/// if `caller` has no such call, because it exited for instance; the
+/// capability of the message then stays with the current process.
+pub fn port_reply(fd: u64, caller: u64, message: u64) -> Result<(), Error> {
+    let port = port(fd, true)?;
+    let caller = Id::new(caller).ok_or(Error::NotFound)?;
+    let (message, cap) = take_message(message)?;
+    if let Err(envelope) = port.reply(caller, Envelope { sender: current_id(), message, cap }) {
+        untake_message(envelope);
+        return Err(Error::NotFound);
+    }
+    Ok(())
+}
+
+/// Opens the shared memory object named by the `len` bytes at `name` and
+/// returns its descriptor. With `OpenFlags::CREATE`, an object of `size`
+/// bytes is created if there is none of that name; an empty name always
+/// creates an object no other process can open by name. The only other flag
+/// accepted is `OpenFlags::CLOSE_ON_EXEC`.
+pub fn shm_open(name: u64, len: u64, size: u64, flags: u64) -> Result<u64, Error> {
+    let name = user_string(name, len)?;
+    let flags = OpenFlags::from_bits(flags).ok_or(Error::InvalidInput)?;
+    if flags - OpenFlags::CREATE - OpenFlags::CLOSE_ON_EXEC != OpenFlags::empty() {
+        return Err(Error::InvalidInput);
+    }
+    let create = if flags.contains(OpenFlags::CREATE) { Some(size as usize) } else { None };
+    let object = if name.is_empty() {
+        let size = create.ok_or(Error::InvalidInput)?;
+        Arc::new(SharedMemory::new(size).ok_or(Error::NoMemory)?)
+    } else {
+        shm::open(&name, create)?
+    };
+
+    let handle = Shared::new(OpenFile::SharedMemory(object));
+    let mut fd = None;
+    let close_on_exec = flags.contains(OpenFlags::CLOSE_ON_EXEC);
+    SCHEDULER.current(|process| fd = process.files().insert(handle, close_on_exec));
+    fd.map(|fd| fd as u64).ok_or(Error::TooManyFiles)
+}
+
+/// Removes the name of the shared memory object named by the `len` bytes at
+/// `name`. The object itself lives on while it is open or mapped.
+pub fn shm_unlink(name: u64, len: u64) -> Result<(), Error> {
+    let name = user_string(name, len)?;
+    Ok(shm::unlink(&name)?)
+}
+
+/// Maps the whole shared memory object `fd` into the current process and
+/// returns its address. `flags` must contain `Map::SHARED`; `Map::WRITE` and
+/// `Map::EXEC` make the mapping writable and executable. With `Map::FIXED`,
+/// the object is mapped at `addr`, otherwise at the first free address from
+/// `shm::MAP_BASE`.
+pub fn mmap(fd: u64, addr: u64, flags: u64) -> Result<u64, Error> {
+    if flags > u16::max_value() as u64 {
+        return Err(Error::InvalidInput);
+    }
+    let flags = Map::from_bits(flags as u16).ok_or(Error::InvalidInput)?;
+    let accepted = Map::READ | Map::WRITE | Map::EXEC | Map::SHARED | Map::FIXED;
+    if !flags.contains(Map::SHARED) || !(flags - accepted).is_empty() {
+        return Err(Error::InvalidInput);
+    }
+    let mut prot = Prot::READ;
+    if flags.contains(Map::WRITE) {
+        prot |= Prot::WRITE;
+    }
+    if flags.contains(Map::EXEC) {
+        prot |= Prot::EXEC;
+    }
+
+    let handle = handle(fd)?;
+    let object = match *handle.borrow() {
+        OpenFile::SharedMemory(ref object) => object.clone(),
+        _ => return Err(Error::InvalidInput),
+    };
+    let mut result = Err(Error::NoMemory);
+    SCHEDULER.current(|process| {
+        let addr = if flags.contains(Map::FIXED) {
+            Some(addr as usize)
+        } else {
+            process.mm().find_free(shm::MAP_BASE, shm::MAP_END, object.size())
+        };
+        result = match addr {
+            Some(addr) => process.mm().map_shared(addr, &object, prot)
+                .map(|_| addr as u64)
+                .map_err(|err| match err {
+                    FaultError::Invalid => Error::InvalidInput,
+                    _ => Error::NoMemory,
+                }),
+            None => Err(Error::NoMemory),
+        };
+    });
+    result
+}
+
+/// Unmaps the shared memory the current process mapped at `addr` with
+/// `mmap`.
+pub fn munmap(addr: u64) -> Result<(), Error> {
+    let mut unmapped = None;
+    SCHEDULER.current(|process| unmapped = process.mm().unmap_shared(addr as usize));
+    unmapped.ok_or(Error::InvalidInput)
+}
+
+/// Returns the key of the futex word at `addr` in `mm`: its physical
+/// address, once its page is backed and, unless it is shared memory, private
+/// to the current process.
+fn futex_key(mm: &mut Memory, addr: u64) -> Result<PhysicalAddr, Error> {
+    if addr % 4 != 0 {
+        return Err(Error::BadAddress);
+    }
+    mm.fault_in(addr as usize, 4, Prot::RW).map_err(fault_error)?;
+    mm.translate(VirtualAddr::from(addr as *mut u8)).ok_or(Error::BadAddress)
+}
+
+/// Blocks the current process on the futex word at `addr` if the word still
+/// holds `expected`, until `futex_wake` wakes it or, unless `timeout` is 0,
+/// `timeout` milliseconds pass. Signals end the wait too: callers check the
+/// word again once woken.
+///
+/// Fails with `Error::WouldBlock` if the word does not hold `expected`, and
+/// with `Error::TimedOut` once the timeout passed.
+pub fn futex_wait(addr: u64, expected: u64, timeout: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let group = current_group();
+    let id = current_id();
+    let (key, queued) = {
+        // the word is read with the address space locked, so that another
+        // thread cannot unmap it meanwhile
+        let mut mm = group.mm();
+        let key = futex_key(&mut mm, addr)?;
+        let queued = futex::wait(key, id, || {
+            unsafe { ptr::read_volatile(p2v(key).as_ptr() as *const u32) == expected as u32 }
+        });
+        (key, queued)
+    };
+    if !queued {
+        return Err(Error::WouldBlock);
+    }
+    let deadline = if timeout == 0 { None } else { Some(current_time() + 1000 * timeout) };
+    let on_wake = Box::new(move |process: &mut Process, wakeup: Wakeup| {
+        futex::cancel(key, id);
+        if wakeup == Wakeup::Timeout {
+            process.trap_frame.x7 = Error::TimedOut as u64;
+        }
+    });
+    SCHEDULER.block(&[], deadline, Some(on_wake), tf);
+    Ok(())
+}
+
+/// Wakes up to `n` processes waiting on the futex word at `addr` and
+/// returns how many were woken.
+pub fn futex_wake(addr: u64, n: u64) -> Result<u64, Error> {
+    let group = current_group();
+    let key = futex_key(&mut group.mm(), addr)?;
+    Ok(futex::wake(key, n as usize) as u64)
+}
+
+/// Ends the current process with the exit code `code`, of which only the
+/// low 8 bits are kept: higher bits are reserved for `KILLED`. Called from
+/// any thread, kills the other threads of the process, which exits with
+/// `code` once its main thread ended.
 pub fn exit(code: u32, tf: &mut TrapFrame) {
+    let code = code & 0xFF;
     kprintln!("EXIT: {}", code);
+    let group = current_group();
+    group.exit(code);
+    SCHEDULER.kill_group(&group);
     SCHEDULER.switch(State::Exit(code), tf).expect("exit");
 }
 
+/// Starts a thread of the current process running at `entry` with the stack
+/// pointer `stack` and `arg` in `x0`, and returns its id. The thread shares
+/// the address space and the open files of the process. It ends with
+/// `thread_exit`: `entry` must not return.
+pub fn thread_spawn(entry: u64, stack: u64, arg: u64, tf: &mut TrapFrame) -> Result<u64, Error> {
+    if entry % 4 != 0 || stack % 16 != 0 {
+        return Err(Error::InvalidInput);
+    }
+    let mut thread = None;
+    SCHEDULER.current(|process| thread = process.thread(tf, entry, stack, arg));
+    let thread = thread.ok_or(Error::NoMemory)?;
+    SCHEDULER.add(thread).map(|id| id.as_u64()).ok_or(Error::Other)
+}
+
+/// Ends the current thread with the exit code `code`, of which only the low
+/// 8 bits are kept, for `thread_join` to return. Called from the main thread,
+/// ends the process like `exit`.
+pub fn thread_exit(code: u32, tf: &mut TrapFrame) {
+    SCHEDULER.switch(State::Exit(code & 0xFF), tf).expect("thread exit");
+}
+
+/// Waits for the thread `tid` of the current process to end and returns its
+/// exit code in `x0`. Each thread can be joined once.
+///
+/// Fails with `Error::NotFound` if `tid` is not a thread of the process that
+/// is running or was not joined yet.
+pub fn thread_join(tid: u64, tf: &mut TrapFrame) -> Result<(), Error> {
+    let tid = Id::new(tid).ok_or(Error::NotFound)?;
+    let id = current_id();
+    if tid == id {
+        return Err(Error::InvalidInput);
+    }
+    let mut group = None;
+    SCHEDULER.current(|process| group = Some(process.group.clone()));
+    let group = group.expect("current process");
+
+    // queued first, so that the thread cannot exit unnoticed in between
+    group.wait_join(id);
+    let running = SCHEDULER.with_process(tid, |thread| {
+        thread.leader.is_some() && Arc::ptr_eq(&thread.group, &group)
+    });
+    if let Some(code) = group.take_exited(tid) {
+        group.cancel_join(id);
+        tf.x0 = code as u64;
+        return Ok(());
+    }
+    if running != Some(true) {
+        group.cancel_join(id);
+        return Err(Error::NotFound);
+    }
+    // re-execute the `svc` once a thread of the process exited
+    tf.elr -= 4;
+    SCHEDULER.block(&[], None, None, tf);
+    Ok(())
+}
+
 pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
     tf.x7 = 0;
     match num {
         1 => sleep(tf.x0 as u32, tf),
         2 => {
-            if let Err(err) = print(tf.x0 as *const u8, tf.x1 as usize) {
+            if let Err(err) = print(tf.x0, tf.x1) {
                 tf.x7 = err as u64;
             }
         }
         3 => exit(tf.x0 as u32, tf),
         4 => read_byte(tf),
+        5 => result(open(tf.x0, tf.x1, tf.x2), tf),
+        6 => result(close(tf.x0).map(|_| 0), tf),
+        7 => match read(tf.x0, tf.x1, tf.x2) {
+            Err(Error::WouldBlock) => block_read(tf.x0, tf),
+            r => result(r, tf),
+        },
+        8 => match write(tf.x0, tf.x1, tf.x2) {
+            Err(Error::WouldBlock) => block_write(tf.x0, tf),
+            r => result(r, tf),
+        },
+        9 => result(seek(tf.x0, tf.x1, tf.x2), tf),
+        10 => result(stat(tf.x0, tf.x1, tf.x2).map(|_| 0), tf),
+        11 => result(readdir(tf.x0, tf.x1, tf.x2, tf.x3), tf),
+        12 => result(spawn(tf.x0, tf.x1, tf.x2, tf.x3, tf.x4, tf.x5), tf),
+        13 => result(fork(tf), tf),
+        14 => {
+            if let Err(err) = exec(tf.x0, tf.x1, tf.x2, tf.x3, tf.x4, tf.x5, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        15 => {
+            if let Err(err) = wait(tf.x0, tf.x1, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        16 => result(setpriority(tf.x0, tf.x1).map(|_| 0), tf),
+        17 => result(kill(tf.x0, tf.x1).map(|_| 0), tf),
+        18 => result(sigaction(tf.x0, tf.x1, tf.x2, tf.x3), tf),
+        19 => sigreturn(tf),
+        20 => result(sigprocmask(tf.x0, tf.x1), tf),
+        21 => {
+            if let Err(err) = getrlimit(tf.x0, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        22 => result(setrlimit(tf.x0, tf.x1, tf.x2).map(|_| 0), tf),
+        23 => {
+            if let Err(err) = pipe(tf.x0, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        24 => result(dup(tf.x0), tf),
+        25 => result(dup2(tf.x0, tf.x1), tf),
+        26 => result(port_create(tf.x0, tf.x1), tf),
+        27 => result(port_connect(tf.x0, tf.x1), tf),
+        28 => {
+            if let Err(err) = port_call(tf.x0, tf.x1, tf.x2, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        29 => {
+            if let Err(err) = port_receive(tf.x0, tf.x1, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        30 => result(port_reply(tf.x0, tf.x1, tf.x2).map(|_| 0), tf),
+        31 => result(shm_open(tf.x0, tf.x1, tf.x2, tf.x3), tf),
+        32 => result(shm_unlink(tf.x0, tf.x1).map(|_| 0), tf),
+        33 => result(mmap(tf.x0, tf.x1, tf.x2), tf),
+        34 => result(munmap(tf.x0).map(|_| 0), tf),
+        35 => {
+            if let Err(err) = futex_wait(tf.x0, tf.x1, tf.x2, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        36 => result(futex_wake(tf.x0, tf.x1), tf),
+        37 => result(thread_spawn(tf.x0, tf.x1, tf.x2, tf), tf),
+        38 => thread_exit(tf.x0 as u32, tf),
+        39 => {
+            if let Err(err) = thread_join(tf.x0, tf) {
+                tf.x7 = err as u64;
+            }
+        }
+        40 => result(ioctl(tf.x0, tf.x1, tf.x2), tf),
         _ => {
             kprintln!("--- SYSCALL does not exists {:?}, x0-3: {} {} {} {}", num, tf.x0, tf.x1, tf.x2, tf.x3);
             tf.x0 = num as u64;
@@ -108,3 +1191,12 @@ pub fn handle_syscall(num: u16, tf: &mut TrapFrame) {
         }
     }
 }
+
+/// Stores the result of a system call in `tf`: the value in `x0` or the
+/// error in `x7`.
+fn result(result: Result<u64, Error>, tf: &mut TrapFrame) {
+    match result {
+        Ok(value) => tf.x0 = value,
+        Err(err) => tf.x7 = err as u64,
+    }
+}
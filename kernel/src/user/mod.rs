@@ -32,6 +32,9 @@ pub extern "C" fn el0_init() -> ! {
 
     println!("test GPIO");
 
+    // the control loop should not wait behind batch jobs
+    syscall_setpriority(0, -10).unwrap();
+
     use pi::gpio::{Gpio, GPIO_BASE};
     use pi::common::IO_BASE_RAW;
 
@@ -3,21 +3,17 @@ use core::str::from_utf8;
 use core::str::FromStr;
 
 use super::syscall::*;
+use traps::{OpenFlags, WaitOptions};
+use process::signal::{self, KILLED, CORE_DUMPED, SIGINT};
+use traps::Error as SysErr;
 
 //use pi::power;
-//use console::CONSOLE;
 
-//use std::io;
+/// The maximum length of a path handled by the shell.
+const PATH_MAX: usize = 256;
 
-//use std::path::{Path, PathBuf};
-
-//use FILE_SYSTEM;
-//use sys::fs::*;
-//
-
-fn read_byte() -> u8 {
-    syscall_read_byte().unwrap()
-}
+/// The maximum number of commands in a pipeline.
+const PIPELINE_MAX: usize = 8;
 
 /// Error type for `Command` parse failures.
 #[derive(Debug)]
@@ -67,49 +63,65 @@ pub fn shell(prefix: &str) -> u32 {
     println!("    ./ \\.");
     println!("");
 
-    //print!("(/) {}", prefix);
-    print!("{}", prefix);
+    // Ctrl-C interrupts the programs started by the shell, not the shell
+    syscall_sigaction(SIGINT, SigHandler::Ignore, 0).unwrap();
 
-    //let mut shell = Shell { cwd: PathBuf::from("/") };
-    let mut shell = Shell { exit: None };
+    let mut shell = Shell { cwd: [0; PATH_MAX], cwd_len: 1, exit: None };
+    shell.cwd[0] = b'/';
 
+    print!("({}) {}", shell.cwd(), prefix);
+
+    let mut input = [0u8; 64];
     let mut buf = [0u8; 512];
     let mut buf = StackVec::new(&mut buf);
     while shell.exit.is_none() {
-        let byte = read_byte();
-        match byte {
-            0 => (),
-            b'\r' | b'\n' => {
-                print!("\r\n");
-                {
-                    let s = from_utf8(&buf).unwrap();
-                    let mut str_buf = [""; 64];
-                    match Command::parse(s, &mut str_buf) {
-                        Err(Error::Empty) => (),
-                        Err(Error::TooManyArgs) => println!("error: too many arguments"),
-                        Ok(cmd) => shell.run(cmd),
+        let n = match syscall_read(STDIN, &mut input) {
+            Ok(n) => n,
+            Err(err) => {
+                println!("read: {:?}", err);
+                continue;
+            }
+        };
+        for &byte in &input[..n] {
+            match byte {
+                0 => (),
+                b'\r' | b'\n' => {
+                    print!("\r\n");
+                    {
+                        let s = from_utf8(&buf).unwrap();
+                        let mut str_buf = [""; 64];
+                        if s.contains('|') {
+                            shell.pipeline(s);
+                        } else {
+                            match Command::parse(s, &mut str_buf) {
+                                Err(Error::Empty) => (),
+                                Err(Error::TooManyArgs) => println!("error: too many arguments"),
+                                Ok(cmd) => shell.run(cmd),
+                            }
+                        }
+                    }
+                    buf.truncate(0);
+                    if shell.exit.is_some() {
+                        break;
                     }
+                    print!("({}) {}", shell.cwd(), prefix);
                 }
-                buf.truncate(0);
-                print!("{}", prefix);
-                //let cwd = shell.cwd.to_str().unwrap();
-                //print!("({}) {}", cwd, prefix);
-            }
-            127 | 8 => { // DEL | BS
-                if !buf.is_empty() {
-                    write_buf(&[8, 32, 8]);
-                    buf.pop();
+                127 | 8 => { // DEL | BS
+                    if !buf.is_empty() {
+                        write_buf(&[8, 32, 8]);
+                        buf.pop();
+                    }
                 }
-            }
-            c @ 32...126 => {
-                if !buf.is_full() {
-                    buf.push(c).unwrap();
-                    write_buf(&[c]);
+                c @ 32...126 => {
+                    if !buf.is_full() {
+                        buf.push(c).unwrap();
+                        write_buf(&[c]);
+                    }
+                }
+                _ => {
+                    // send bell
+                    write_buf(&[7]);
                 }
-            }
-            _ => {
-                // send bell
-                write_buf(&[7]);
             }
         }
     }
@@ -117,8 +129,31 @@ pub fn shell(prefix: &str) -> u32 {
     shell.exit.unwrap()
 }
 
+/// Appends `s` and a null terminator to `buf`.
+fn push_str(buf: &mut StackVec<u8>, s: &str) -> Option<()> {
+    for &b in s.as_bytes().iter().chain(b"\0") {
+        buf.push(b).ok()?;
+    }
+    Some(())
+}
+
+/// Waits for the program `name` started as `id` to exit and reports how it
+/// ended, unless it succeeded.
+fn wait(name: &str, id: u64) {
+    match syscall_wait(id, WaitOptions::empty()) {
+        Ok(Some((_, 0))) | Ok(None) => (),
+        Ok(Some((_, code))) if code & CORE_DUMPED != 0 => {
+            println!("{}: {} (core dumped)", name, signal::name(code & 0xFF));
+        }
+        Ok(Some((_, code))) if code & KILLED != 0 => println!("{}: {}", name, signal::name(code & 0xFF)),
+        Ok(Some((_, code))) => println!("{}: exit code {}", name, code),
+        Err(err) => println!("wait: {:?}", err),
+    }
+}
+
 struct Shell {
-    //cwd: PathBuf,
+    cwd: [u8; PATH_MAX],
+    cwd_len: usize,
     exit: Option<u32>,
 }
 
@@ -136,15 +171,13 @@ impl Shell {
                 self.exit = Some(code);
                 print!("\r\n");
             }
-            /*
             "pwd" => {
-                print!("{}", self.cwd.to_str().unwrap());
+                print!("{}", self.cwd());
                 print!("\r\n");
             }
             "cd" => self.cd(cmd.args),
             "ls" => self.ls(cmd.args),
             "cat" => self.cat(cmd.args),
-            */
 
             /*
             "poweroff" => {
@@ -160,39 +193,47 @@ impl Shell {
                 power::reset();
             }
             */
-            _ => println!("unknown command: {}", cmd.path()),
+            "exec" => {
+                if cmd.args.len() > 1 {
+                    self.spawn(&cmd.args[1..], true);
+                }
+            }
+            _ => self.spawn(&cmd.args, false),
         }
     }
-    /*
 
-    fn canonicalize<P: AsRef<Path>>(&self, p: P) -> io::Result<PathBuf> {
-        use std::path::Component::*;
+    fn cwd(&self) -> &str {
+        from_utf8(&self.cwd[..self.cwd_len]).unwrap()
+    }
 
-        let mut result = PathBuf::new();
-        for (i, c) in p.as_ref().components().enumerate() {
+    /// Resolves `path` against the working directory into `buf`, removing
+    /// `.` and `..` components. Returns `None` if the result does not fit.
+    fn canonicalize<'a>(&self, path: &str, buf: &'a mut [u8; PATH_MAX]) -> Option<&'a str> {
+        let mut result = StackVec::new(&mut buf[..]);
+        let base = if path.starts_with('/') { "" } else { self.cwd() };
+        for c in base.split('/').chain(path.split('/')) {
             match c {
-            Prefix(_) => unimplemented!(),
-            CurDir => result.push(&self.cwd),
-            RootDir => result.push(RootDir),
-            ParentDir if i == 0 => {
-                if let Some(parent) = self.cwd.parent() {
-                    result.push(parent)
-                } else {
-                    return Err(io::Error::new(io::ErrorKind::NotFound, "fail cwd.parent dir"));
+                "" | "." => (),
+                ".." => {
+                    while let Some(b) = result.pop() {
+                        if b == b'/' {
+                            break;
+                        }
+                    }
                 }
-            }
-            ParentDir => {
-                if !result.pop() {
-                    return Err(io::Error::new(io::ErrorKind::NotFound, "fail parent dir"));
+                c => {
+                    result.push(b'/').ok()?;
+                    for &b in c.as_bytes() {
+                        result.push(b).ok()?;
+                    }
                 }
             }
-            Normal(s) => result.push(s),
-            }
         }
-
-        Ok(result)
+        if result.is_empty() {
+            result.push(b'/').ok()?;
+        }
+        from_utf8(result.into_slice()).ok()
     }
-    */
 
     fn echo(&self, args: StackVec<&str>) {
         for (i, arg) in args.iter().enumerate() {
@@ -204,52 +245,255 @@ impl Shell {
         }
     }
 
-    /*
     fn cd(&mut self, args: StackVec<&str>) {
-        if args.len() == 1 {
-            self.cwd = PathBuf::from("/");
-        } else {
-            let mut dir = self.cwd.clone();
-            dir.push(args[1]);
-            let dir = self.canonicalize(&dir)
-                .and_then(|dir| FILE_SYSTEM.open_dir(&dir).map(|_| dir));
-            match dir {
-                Ok(dir) => self.cwd = dir,
-                Err(err) => println!("cd: {:?}", err),
+        let mut buf = [0u8; PATH_MAX];
+        let len = {
+            let dir = match self.canonicalize(args.get(1).cloned().unwrap_or("/"), &mut buf) {
+                Some(dir) => dir,
+                None => {
+                    println!("cd: path too long");
+                    return;
+                }
+            };
+            match syscall_stat(dir) {
+                Ok(ref stat) if stat.is_dir() => dir.len(),
+                Ok(_) => {
+                    println!("cd: {}: not a directory", dir);
+                    return;
+                }
+                Err(err) => {
+                    println!("cd: {}: {:?}", dir, err);
+                    return;
+                }
             }
-        }
+        };
+        self.cwd[..len].copy_from_slice(&buf[..len]);
+        self.cwd_len = len;
     }
 
     fn ls(&mut self, args: StackVec<&str>) {
-        let mut dir = self.cwd.clone();
-        if args.len() > 1 {
-            dir.push(args[1])
+        let mut buf = [0u8; PATH_MAX];
+        let dir = match self.canonicalize(args.get(1).cloned().unwrap_or("."), &mut buf) {
+            Some(dir) => dir,
+            None => {
+                println!("ls: path too long");
+                return;
+            }
+        };
+        let fd = match syscall_open(dir, OpenFlags::DIRECTORY) {
+            Ok(fd) => fd,
+            Err(err) => {
+                println!("ls: {}: {:?}", dir, err);
+                return;
+            }
+        };
+        let mut name = [0u8; PATH_MAX];
+        loop {
+            match syscall_readdir(fd, &mut name) {
+                Ok(Some((len, stat))) => {
+                    let name = from_utf8(&name[..len]).unwrap_or("?");
+                    if stat.is_dir() {
+                        println!("{}/", name);
+                    } else {
+                        println!("{}", name);
+                    }
+                }
+                Ok(None) => break,
+                Err(err) => {
+                    println!("ls: {}: {:?}", dir, err);
+                    break;
+                }
+            }
+        }
+        syscall_close(fd).unwrap();
+    }
+
+    /// Runs the program `args[0]` names and waits for it to exit. See
+    /// `start` for the parameters.
+    fn spawn(&mut self, args: &[&str], exec: bool) {
+        if let Some(id) = self.start(args, exec) {
+            wait(args[0], id);
+        }
+    }
+
+    /// Runs the programs of `line` separated by `|`, the standard output of
+    /// each connected to the standard input of the next by a pipe, and waits
+    /// for all of them to exit. Built-in commands cannot be part of a
+    /// pipeline.
+    fn pipeline(&mut self, line: &str) {
+        let saved = match (syscall_dup(STDIN), syscall_dup(STDOUT)) {
+            (Ok(stdin), Ok(stdout)) => (stdin, stdout),
+            (stdin, stdout) => {
+                println!("pipe: {:?}", stdin.and(stdout).unwrap_err());
+                return;
+            }
+        };
+
+        let mut started = [("", 0); PIPELINE_MAX];
+        let mut count = 0;
+        let mut input = None;
+        let mut stages = line.split('|').peekable();
+        while let Some(stage) = stages.next() {
+            let name = stage.split(' ').find(|a| !a.is_empty()).unwrap_or("");
+            let mut str_buf = [""; 64];
+            let cmd = match Command::parse(stage, &mut str_buf) {
+                Ok(cmd) => cmd,
+                Err(Error::Empty) => {
+                    println!("error: empty command in pipeline");
+                    break;
+                }
+                Err(Error::TooManyArgs) => {
+                    println!("error: too many arguments");
+                    break;
+                }
+            };
+            if count == PIPELINE_MAX {
+                println!("error: too many commands in pipeline");
+                break;
+            }
+            // the pipes are only inherited as standard input and output
+            let pipe = if stages.peek().is_some() {
+                match syscall_pipe(OpenFlags::CLOSE_ON_EXEC) {
+                    Ok(pipe) => Some(pipe),
+                    Err(err) => {
+                        println!("pipe: {:?}", err);
+                        break;
+                    }
+                }
+            } else {
+                None
+            };
+
+            if let Some(read) = input {
+                syscall_dup2(read, STDIN).unwrap();
+            }
+            if let Some((_, write)) = pipe {
+                syscall_dup2(write, STDOUT).unwrap();
+            }
+            let id = self.start(&cmd.args, false);
+            syscall_dup2(saved.0, STDIN).unwrap();
+            syscall_dup2(saved.1, STDOUT).unwrap();
+
+            // the programs hold the ends they use: readers see the end of
+            // the data once the program before them exits
+            if let Some(read) = input.take() {
+                syscall_close(read).unwrap();
+            }
+            if let Some((read, write)) = pipe {
+                syscall_close(write).unwrap();
+                input = Some(read);
+            }
+            match id {
+                Some(id) => {
+                    started[count] = (name, id);
+                    count += 1;
+                }
+                None => break,
+            }
+        }
+        if let Some(read) = input {
+            syscall_close(read).unwrap();
+        }
+        syscall_close(saved.0).unwrap();
+        syscall_close(saved.1).unwrap();
+
+        for &(name, id) in &started[..count] {
+            wait(name, id);
         }
-        match FILE_SYSTEM.open_dir(&dir).and_then(|e| e.entries()) {
-            Ok(entries) => {
-                for e in entries {
-                    println!("{}", e.name());
+    }
+
+    /// Starts the program `args[0]` names: a path, or the name of an
+    /// executable in `/bin`. The program receives `args` and the working
+    /// directory as `PWD` in its environment, and inherits the standard
+    /// input and output of the shell. If `exec` is set, the program replaces
+    /// the shell. Returns the id of the program, or `None` if it could not
+    /// be started.
+    fn start(&mut self, args: &[&str], exec: bool) -> Option<u64> {
+        let name = args[0];
+        let mut buf = [0u8; PATH_MAX];
+        let path = if name.contains('/') {
+            self.canonicalize(name, &mut buf)
+        } else {
+            let mut path = StackVec::new(&mut buf[..]);
+            for &b in b"/bin/".iter().chain(name.as_bytes()) {
+                if path.push(b).is_err() {
+                    break;
                 }
             }
-            Err(err) => println!("ls: {}", err),
+            from_utf8(path.into_slice()).ok()
+        };
+        let path = match path {
+            Some(path) => path,
+            None => {
+                println!("{}: path too long", name);
+                return None;
+            }
+        };
+
+        let mut arg_buf = [0u8; 512];
+        let mut arg_buf = StackVec::new(&mut arg_buf[..]);
+        for arg in args {
+            if push_str(&mut arg_buf, arg).is_none() {
+                println!("{}: argument list too long", name);
+                return None;
+            }
+        }
+        let mut env = [0u8; PATH_MAX + 8];
+        let mut env = StackVec::new(&mut env[..]);
+        for &b in b"PWD=" {
+            env.push(b).unwrap();
+        }
+        push_str(&mut env, self.cwd()).unwrap();
+
+        let result = if exec {
+            Err(syscall_exec(path, &arg_buf, &env))
+        } else {
+            syscall_spawn(path, &arg_buf, &env)
+        };
+        match result {
+            Ok(id) => Some(id),
+            Err(SysErr::NotFound) => {
+                println!("unknown command: {}", name);
+                None
+            }
+            Err(err) => {
+                println!("{}: {:?}", path, err);
+                None
+            }
         }
     }
 
     fn cat(&mut self, args: StackVec<&str>) {
-        if args.len() == 1 {
-            return
-        }
         for arg in &args[1..] {
-            let mut dir = self.cwd.clone();
-            dir.push(arg);
-            match FILE_SYSTEM.open_file(&dir) {
-                Ok(mut r) => {
-                    let mut w = CONSOLE.lock();
-                    io::copy(&mut r, &mut *w).unwrap();
+            let mut buf = [0u8; PATH_MAX];
+            let path = match self.canonicalize(arg, &mut buf) {
+                Some(path) => path,
+                None => {
+                    println!("cat: path too long");
+                    continue;
+                }
+            };
+            let fd = match syscall_open(path, OpenFlags::empty()) {
+                Ok(fd) => fd,
+                Err(err) => {
+                    println!("cat: {}: {:?}", path, err);
+                    continue;
+                }
+            };
+            let mut data = [0u8; 512];
+            loop {
+                match syscall_read(fd, &mut data) {
+                    Ok(0) => break,
+                    Ok(n) => {
+                        syscall_write(STDOUT, &data[..n]).unwrap();
+                    }
+                    Err(err) => {
+                        println!("cat: {}: {:?}", path, err);
+                        break;
+                    }
                 }
-                Err(err) => println!("cat: {}", err),
             }
+            syscall_close(fd).unwrap();
         }
     }
-    */
 }
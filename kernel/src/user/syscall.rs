@@ -1,5 +1,13 @@
 use core::fmt::{self, Write};
+use core::mem;
+use sys::io::SeekFrom;
 use traps::Error as SysErr;
+use traps::{OpenFlags, WaitOptions};
+use fs::vfs::Stat;
+use process::signal::{SIG_DFL, SIG_IGN};
+use process::rlimit::Limit;
+use process::ipc::Message;
+use vm::Map;
 
 pub struct Stdout;
 
@@ -102,3 +110,895 @@ pub fn syscall_read_byte() -> Result<u8, SysErr> {
         Err(SysErr::from(error))
     }
 }
+
+/// The descriptor of the standard input.
+pub const STDIN: usize = 0;
+/// The descriptor of the standard output.
+pub const STDOUT: usize = 1;
+/// The descriptor of the standard error.
+pub const STDERR: usize = 2;
+
+pub fn syscall_open(path: &str, flags: OpenFlags) -> Result<usize, SysErr> {
+    let error: u64;
+    let fd: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            svc 5
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(fd)
+            : "r"(path.as_ptr()), "r"(path.len()), "r"(flags.bits())
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(fd as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+pub fn syscall_close(fd: usize) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            svc 6
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(fd)
+            : "x0", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Reads into `buf` from `fd`, waiting until at least one byte is available,
+/// and returns the number of bytes read. Returns `0` at the end of a file.
+pub fn syscall_read(fd: usize, buf: &mut [u8]) -> Result<usize, SysErr> {
+    let error: u64;
+    let count: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            svc 7
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(count)
+            : "r"(fd), "r"(buf.as_mut_ptr()), "r"(buf.len())
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(count as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+pub fn syscall_write(fd: usize, buf: &[u8]) -> Result<usize, SysErr> {
+    let error: u64;
+    let count: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            svc 8
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(count)
+            : "r"(fd), "r"(buf.as_ptr()), "r"(buf.len())
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(count as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+pub fn syscall_seek(fd: usize, pos: SeekFrom) -> Result<u64, SysErr> {
+    let (offset, whence) = match pos {
+        SeekFrom::Start(offset) => (offset, 0u64),
+        SeekFrom::Current(offset) => (offset as u64, 1),
+        SeekFrom::End(offset) => (offset as u64, 2),
+    };
+    let error: u64;
+    let position: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            svc 9
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(position)
+            : "r"(fd), "r"(offset), "r"(whence)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(position)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Performs the control operation `request` with the argument `arg` on the
+/// device `fd` and returns its result. See `fs::devfs::ioctl` for the
+/// requests.
+pub fn syscall_ioctl(fd: usize, request: u64, arg: u64) -> Result<u64, SysErr> {
+    let error: u64;
+    let value: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            svc 40
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(value)
+            : "r"(fd), "r"(request), "r"(arg)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(value)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+pub fn syscall_stat(path: &str) -> Result<Stat, SysErr> {
+    let mut stat: Stat = unsafe { mem::zeroed() };
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            mov x2, $3
+            svc 10
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(path.as_ptr()), "r"(path.len()), "r"(&mut stat as *mut Stat)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(stat)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Reads the next entry of the directory `fd`, storing its name in `name`.
+/// Returns the length of the name and the entry's metadata, or `None` once
+/// all entries were read.
+pub fn syscall_readdir(fd: usize, name: &mut [u8]) -> Result<Option<(usize, Stat)>, SysErr> {
+    let mut stat: Stat = unsafe { mem::zeroed() };
+    let error: u64;
+    let len: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            mov x3, $5
+            svc 11
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(len)
+            : "r"(fd), "r"(&mut stat as *mut Stat), "r"(name.as_mut_ptr()), "r"(name.len())
+            : "x0", "x1", "x2", "x3", "x7"
+            : "volatile");
+    }
+    if error != 0 {
+        Err(SysErr::from(error))
+    } else if len == 0 {
+        Ok(None)
+    } else {
+        Ok(Some((len as usize, stat)))
+    }
+}
+
+/// Starts the executable at `path` with the null terminated strings in
+/// `args` as its arguments and those in `env` as its environment.
+pub fn syscall_spawn(path: &str, args: &[u8], env: &[u8]) -> Result<u64, SysErr> {
+    let error: u64;
+    let id: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            mov x3, $5
+            mov x4, $6
+            mov x5, $7
+            svc 12
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(id)
+            : "r"(path.as_ptr()), "r"(path.len()),
+              "r"(args.as_ptr()), "r"(args.len()),
+              "r"(env.as_ptr()), "r"(env.len())
+            : "x0", "x1", "x2", "x3", "x4", "x5", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(id)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Creates a copy of the calling process. Returns the id of the copy in the
+/// caller and 0 in the copy.
+pub fn syscall_fork() -> Result<u64, SysErr> {
+    let error: u64;
+    let id: u64;
+    unsafe {
+        asm!("
+            svc 13
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(id)
+            :
+            : "x0", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(id)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Replaces the program of the calling process with the executable at `path`.
+/// See `syscall_spawn` for `args` and `env`. Only returns on failure.
+pub fn syscall_exec(path: &str, args: &[u8], env: &[u8]) -> SysErr {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            mov x2, $3
+            mov x3, $4
+            mov x4, $5
+            mov x5, $6
+            svc 14
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(path.as_ptr()), "r"(path.len()),
+              "r"(args.as_ptr()), "r"(args.len()),
+              "r"(env.as_ptr()), "r"(env.len())
+            : "x0", "x1", "x2", "x3", "x4", "x5", "x7"
+            : "volatile");
+    }
+    SysErr::from(error)
+}
+
+/// Waits for the child `pid`, or any child if `pid` is 0, to exit. Returns the
+/// id and the exit code of the child, or `None` if no child exited and
+/// `options` contains `WaitOptions::NO_HANG`.
+pub fn syscall_wait(pid: u64, options: WaitOptions) -> Result<Option<(u64, u32)>, SysErr> {
+    let error: u64;
+    let id: u64;
+    let code: u64;
+    unsafe {
+        asm!("
+            mov x0, $3
+            mov x1, $4
+            svc 15
+            mov $0, x7
+            mov $1, x0
+            mov $2, x1
+            "
+            : "=r"(error), "=r"(id), "=r"(code)
+            : "r"(pid), "r"(options.bits())
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error != 0 {
+        Err(SysErr::from(error))
+    } else if id == 0 {
+        Ok(None)
+    } else {
+        Ok(Some((id, code as u32)))
+    }
+}
+
+/// Sets the nice value of the process `pid`, or of the calling process if
+/// `pid` is 0. Processes with lower values get a larger share of CPU time.
+pub fn syscall_setpriority(pid: u64, nice: i8) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            svc 16
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(pid), "r"(nice as i64)
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Sends the signal `sig` to the process `pid`.
+pub fn syscall_kill(pid: u64, sig: u32) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            svc 17
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(pid), "r"(sig as u64)
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// What happens when a signal is delivered, for `syscall_sigaction`.
+pub enum SigHandler {
+    Default,
+    Ignore,
+    /// The function is called with the signal number.
+    Handler(extern "C" fn(u32)),
+}
+
+/// Returns from a signal handler. Handlers installed by `syscall_sigaction`
+/// return here.
+extern "C" fn sigreturn() -> ! {
+    unsafe { asm!("svc 19" :::: "volatile"); }
+    unreachable!("sigreturn");
+}
+
+/// Sets what happens when the signal `sig` is delivered. While a handler
+/// runs, the signals in `mask` and `sig` itself are blocked.
+pub fn syscall_sigaction(sig: u32, handler: SigHandler, mask: u32) -> Result<(), SysErr> {
+    let handler = match handler {
+        SigHandler::Default => SIG_DFL,
+        SigHandler::Ignore => SIG_IGN,
+        SigHandler::Handler(f) => f as u64,
+    };
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            mov x2, $3
+            mov x3, $4
+            svc 18
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(sig as u64), "r"(handler), "r"(mask as u64), "r"(sigreturn as u64)
+            : "x0", "x1", "x2", "x3", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Changes the blocked signals with `set` as `how` says (`SIG_BLOCK`,
+/// `SIG_UNBLOCK` or `SIG_SETMASK`) and returns the previously blocked
+/// signals.
+pub fn syscall_sigprocmask(how: u64, set: u32) -> Result<u32, SysErr> {
+    let error: u64;
+    let old: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            svc 20
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(old)
+            : "r"(how), "r"(set as u64)
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(old as u32)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Returns the soft and hard limits of `resource` (`RLIMIT_*`).
+pub fn syscall_getrlimit(resource: u64) -> Result<Limit, SysErr> {
+    let error: u64;
+    let cur: u64;
+    let max: u64;
+    unsafe {
+        asm!("
+            mov x0, $3
+            svc 21
+            mov $0, x7
+            mov $1, x0
+            mov $2, x1
+            "
+            : "=r"(error), "=r"(cur), "=r"(max)
+            : "r"(resource)
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(Limit { cur, max })
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Sets the limits of `resource` (`RLIMIT_*`). The soft limit may not exceed
+/// the hard limit, which may only be lowered.
+pub fn syscall_setrlimit(resource: u64, limit: Limit) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            mov x2, $3
+            svc 22
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(resource), "r"(limit.cur), "r"(limit.max)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Creates a pipe and returns the descriptors of its read and write ends.
+/// `flags` may only contain `OpenFlags::CLOSE_ON_EXEC`.
+pub fn syscall_pipe(flags: OpenFlags) -> Result<(usize, usize), SysErr> {
+    let error: u64;
+    let read: u64;
+    let write: u64;
+    unsafe {
+        asm!("
+            mov x0, $3
+            svc 23
+            mov $0, x7
+            mov $1, x0
+            mov $2, x1
+            "
+            : "=r"(error), "=r"(read), "=r"(write)
+            : "r"(flags.bits())
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok((read as usize, write as usize))
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Returns a new descriptor, the lowest free one, referring to the file of
+/// `fd`.
+pub fn syscall_dup(fd: usize) -> Result<usize, SysErr> {
+    let error: u64;
+    let new: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            svc 24
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(new)
+            : "r"(fd)
+            : "x0", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(new as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Makes the descriptor `new` refer to the file of `fd`, closing the file it
+/// referred to.
+pub fn syscall_dup2(fd: usize, new: usize) -> Result<usize, SysErr> {
+    let error: u64;
+    let result: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            svc 25
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(result)
+            : "r"(fd), "r"(new)
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(result as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Creates the IPC port `name` and returns the descriptor of its server end.
+pub fn syscall_port_create(name: &str) -> Result<usize, SysErr> {
+    let error: u64;
+    let fd: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            svc 26
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(fd)
+            : "r"(name.as_ptr()), "r"(name.len())
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(fd as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Connects to the IPC port `name` and returns the descriptor of the client
+/// end.
+pub fn syscall_port_connect(name: &str) -> Result<usize, SysErr> {
+    let error: u64;
+    let fd: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            svc 27
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(fd)
+            : "r"(name.as_ptr()), "r"(name.len())
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(fd as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Sends `message` to the server of the port `fd` and returns its reply.
+pub fn syscall_port_call(fd: usize, message: &Message) -> Result<Message, SysErr> {
+    let mut reply = Message::default();
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            mov x2, $3
+            svc 28
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(fd), "r"(message as *const Message), "r"(&mut reply as *mut Message)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(reply)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Waits for the next call on the port `fd` and returns the id of the caller
+/// and its message.
+pub fn syscall_port_receive(fd: usize) -> Result<(u64, Message), SysErr> {
+    let mut message = Message::default();
+    let error: u64;
+    let caller: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            svc 29
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(caller)
+            : "r"(fd), "r"(&mut message as *mut Message)
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok((caller, message))
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Replies `message` to the call of `caller` received on the port `fd`.
+pub fn syscall_port_reply(fd: usize, caller: u64, message: &Message) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            mov x2, $3
+            svc 30
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(fd), "r"(caller), "r"(message as *const Message)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Opens the shared memory object `name`, creating it with `size` bytes if
+/// `flags` contains `OpenFlags::CREATE`, and returns its descriptor.
+pub fn syscall_shm_open(name: &str, size: usize, flags: OpenFlags) -> Result<usize, SysErr> {
+    let error: u64;
+    let fd: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            mov x3, $5
+            svc 31
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(fd)
+            : "r"(name.as_ptr()), "r"(name.len()), "r"(size), "r"(flags.bits())
+            : "x0", "x1", "x2", "x3", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(fd as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Removes the name of the shared memory object `name`.
+pub fn syscall_shm_unlink(name: &str) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            svc 32
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(name.as_ptr()), "r"(name.len())
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Maps the shared memory object `fd` and returns its address: `addr` with
+/// `Map::FIXED`, one picked by the kernel otherwise.
+pub fn syscall_mmap(fd: usize, addr: usize, flags: Map) -> Result<usize, SysErr> {
+    let error: u64;
+    let result: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            svc 33
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(result)
+            : "r"(fd), "r"(addr), "r"(flags.bits() as u64)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(result as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Unmaps the shared memory mapped at `addr`.
+pub fn syscall_munmap(addr: usize) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            svc 34
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(addr)
+            : "x0", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Waits until the futex word `word` is woken, if it still holds `expected`.
+/// Gives up after `timeout` milliseconds unless `timeout` is 0.
+pub fn syscall_futex_wait(word: *const u32, expected: u32, timeout: u64) -> Result<(), SysErr> {
+    let error: u64;
+    unsafe {
+        asm!("
+            mov x0, $1
+            mov x1, $2
+            mov x2, $3
+            svc 35
+            mov $0, x7
+            "
+            : "=r"(error)
+            : "r"(word), "r"(expected as u64), "r"(timeout)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(())
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Wakes up to `n` processes waiting on the futex word `word` and returns
+/// how many were woken.
+pub fn syscall_futex_wake(word: *const u32, n: usize) -> Result<usize, SysErr> {
+    let error: u64;
+    let woken: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            svc 36
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(woken)
+            : "r"(word), "r"(n)
+            : "x0", "x1", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(woken as usize)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Starts a thread running `entry` with `arg` on the stack ending at
+/// `stack`, and returns its id. `entry` ends the thread with
+/// `syscall_thread_exit`.
+pub fn syscall_thread_spawn(entry: extern "C" fn(u64) -> !, stack: usize, arg: u64) -> Result<u64, SysErr> {
+    let error: u64;
+    let id: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            mov x1, $3
+            mov x2, $4
+            svc 37
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(id)
+            : "r"(entry as usize), "r"(stack), "r"(arg)
+            : "x0", "x1", "x2", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(id)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
+
+/// Ends the calling thread with the exit code `code`.
+pub fn syscall_thread_exit(code: u32) -> ! {
+    unsafe {
+        asm!("
+            mov x0, $0
+            svc 38
+            "
+            :
+            : "r"(code)
+            : "x0"
+            : "volatile");
+    }
+    unreachable!("syscall_thread_exit");
+}
+
+/// Waits for the thread `id` to end and returns its exit code.
+pub fn syscall_thread_join(id: u64) -> Result<u32, SysErr> {
+    let error: u64;
+    let code: u64;
+    unsafe {
+        asm!("
+            mov x0, $2
+            svc 39
+            mov $0, x7
+            mov $1, x0
+            "
+            : "=r"(error), "=r"(code)
+            : "r"(id)
+            : "x0", "x7"
+            : "volatile");
+    }
+    if error == 0 {
+        Ok(code as u32)
+    } else {
+        Err(SysErr::from(error))
+    }
+}
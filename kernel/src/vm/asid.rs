@@ -0,0 +1,90 @@
+use sys::Mutex;
+use pi::local::NCORES;
+use aarch64;
+
+/// The bits of an ASID value holding the id written to `TTBR0`. The bits
+/// above hold the generation the id was allocated in.
+const ID_MASK: u64 = 0xFFFF;
+
+/// Hands out the ids tagging the TLB entries of address spaces. Ids are
+/// allocated in generations: once every id of a generation is taken, the TLB
+/// is flushed and a new generation starts over. Address spaces holding an id
+/// of an older generation get a new one the next time they run.
+struct Allocator {
+    /// The current generation, above `ID_MASK`.
+    generation: u64,
+    /// The next id to hand out. Id 0 is left to the kernel.
+    next: u64,
+    /// The number of bits of an id supported by the MMU.
+    bits: u32,
+    /// The ASID values of the address spaces running on each core.
+    active: [u64; NCORES],
+    /// The ASID values of the address spaces running at the last rollover:
+    /// their ids are kept, as the TLB may still be filled with them.
+    reserved: [u64; NCORES],
+}
+
+static ALLOCATOR: Mutex<Allocator> = Mutex::new(Allocator {
+    generation: ID_MASK + 1,
+    next: 1,
+    bits: 8,
+    active: [0; NCORES],
+    reserved: [0; NCORES],
+});
+
+/// Sets the number of bits of an id: 8 or 16.
+pub fn initialize(bits: u32) {
+    ALLOCATOR.lock().unwrap().bits = bits;
+}
+
+/// Returns the id of the ASID value `asid`.
+pub fn id(asid: u64) -> u16 {
+    (asid & ID_MASK) as u16
+}
+
+/// Makes `asid`, the ASID value of an address space, valid for the current
+/// generation and records that the address space runs on `core`. Returns the
+/// id to tag its translations with. An `asid` of 0 is not allocated yet.
+pub fn activate(asid: &mut u64, core: usize) -> u16 {
+    let mut allocator = ALLOCATOR.lock().unwrap();
+    if *asid & !ID_MASK != allocator.generation {
+        *asid = allocator.renew(*asid);
+    }
+    allocator.active[core] = *asid;
+    id(*asid)
+}
+
+impl Allocator {
+    /// Returns the ASID value replacing `old` in the current generation.
+    fn renew(&mut self, old: u64) -> u64 {
+        loop {
+            // still running at the last rollover: keep the id
+            if old != 0 && self.reserved.contains(&old) {
+                let new = self.generation | (old & ID_MASK);
+                for reserved in self.reserved.iter_mut().filter(|r| **r == old) {
+                    *reserved = new;
+                }
+                return new;
+            }
+            if self.next == 1 << self.bits {
+                self.rollover();
+                continue;
+            }
+
+            let next = self.next;
+            self.next += 1;
+            if !self.reserved.iter().any(|&r| r & ID_MASK == next) {
+                return self.generation | next;
+            }
+        }
+    }
+
+    /// Starts a new generation. The TLB entries of the old one are flushed
+    /// on every core, except that the running address spaces keep their ids.
+    fn rollover(&mut self) {
+        self.generation += ID_MASK + 1;
+        self.next = 1;
+        self.reserved = self.active;
+        aarch64::flush_tlb_all();
+    }
+}
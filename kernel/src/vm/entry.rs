@@ -16,6 +16,8 @@ bitflags! {
 
         // 58-55 for software use
         const NEED_DROP = 1 << 58;
+        const COW = 1 << 57;
+        const SHARED = 1 << 56;
 
         // Next-level attributes in stage 1 VMSAv8-64 Table descriptors
         // 58-51 bits is ignored
@@ -57,11 +59,13 @@ bitflags! {
         const OSH = 0b10 << 8;
         const ISH = 0b11 << 8;
 
-        const USER_BASE = Self::AF.bits | Self::AP_EL0.bits | Self::ISH.bits;
+        // user translations are tagged with the ASID of their address space
+        const USER_BASE = Self::AF.bits | Self::AP_EL0.bits | Self::ISH.bits | Self::N_G.bits;
         const USER_RO = Self::USER_BASE.bits | Self::XN.bits | Self::PXN.bits | Self::AP_RO.bits;
         const USER_RW = Self::USER_BASE.bits | Self::XN.bits | Self::PXN.bits;
         const USER_RX = Self::USER_BASE.bits | Self::AP_RO.bits;
-        const USER_DEV = Self::AF.bits | Self::AP_EL0.bits | Self::OSH.bits | Self::XN.bits | Self::ATTR_1.bits;
+        const USER_RWX = Self::USER_BASE.bits;
+        const USER_DEV = Self::AF.bits | Self::AP_EL0.bits | Self::OSH.bits | Self::XN.bits | Self::ATTR_1.bits | Self::N_G.bits;
     }
 }
 
@@ -92,6 +96,16 @@ impl Entry {
         self.contains(Self::VALID | Self::NEED_DROP)
     }
 
+    pub fn is_cow(&self) -> bool {
+        self.contains(Self::VALID | Self::COW)
+    }
+
+    /// Returns `true` if the entry maps a frame of a shared memory object,
+    /// which is never copied on write.
+    pub fn is_shared(&self) -> bool {
+        self.contains(Self::VALID | Self::SHARED)
+    }
+
     pub fn is_valid(&self) -> bool {
         self.contains(Self::VALID)
     }
@@ -3,6 +3,9 @@ mod entry;
 mod table;
 mod huge;
 mod page;
+mod refcount;
+mod asid;
+pub mod shm;
 
 use alloc::boxed::Box;
 use alloc::vec::Vec;
@@ -10,9 +13,14 @@ use alloc::vec::Vec;
 use sys::volatile::prelude::*;
 
 use pi::common::IO_BASE as _IO_BASE;
+use pi::common::LOCAL_BASE_RAW;
+use core::cmp::{min, max};
+use core::ptr;
 use core::fmt;
 
 use aarch64;
+use smp;
+use sys::volatile::Volatile;
 
 pub use self::entry::Entry;
 pub use self::table::{Table, Level, L0, L1, L2, L3};
@@ -20,6 +28,7 @@ pub use self::address::{PhysicalAddr, VirtualAddr};
 
 pub use self::huge::{Huge, HUGESZ};
 pub use self::page::{Page, PAGESZ};
+pub use self::shm::SharedMemory;
 
 pub const UPPER_SPACE_MASK: usize = 0xFFFFFF80_00000000;
 pub const LOWER_SPACE_MASK: usize = 0x0000007F_FFFFFFFF;
@@ -64,6 +73,25 @@ pub fn p2v(p: PhysicalAddr) -> VirtualAddr {
 pub struct Memory {
     root: L1,
     areas: Vec<Area>,
+    /// The number of pages backing the address space, shared ones included.
+    resident: usize,
+    /// The number of pages that may back the address space.
+    page_limit: usize,
+    /// The ASID tagging the translations of the address space in the TLB,
+    /// with its generation: see `activate`.
+    asid: u64,
+}
+
+/// Why an access of user space could not be resolved.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultError {
+    /// The address lies outside of every area, or its area does not permit
+    /// the access.
+    Invalid,
+    /// The address space holds as many pages as its limit permits.
+    Limit,
+    /// There is no memory left.
+    NoMemory,
 }
 
 unsafe impl Send for Memory {}
@@ -80,13 +108,44 @@ impl Memory {
         Some(safe_box(Self {
             root: Page::new_zeroed()?.into(),
             areas: Vec::new(),
+            resident: 0,
+            page_limit: usize::max_value(),
+            asid: 0,
         })?)
     }
 
+    /// Returns the number of pages backing this address space, including the
+    /// pages shared copy-on-write with other address spaces.
+    pub fn resident(&self) -> usize {
+        self.resident
+    }
+
+    /// Limits the number of pages backing this address space to `limit`.
+    /// Pages already backing it are kept.
+    pub fn set_page_limit(&mut self, limit: usize) {
+        self.page_limit = limit;
+    }
+
     pub fn ttbr(&mut self) -> PhysicalAddr {
         self.root.physical()
     }
 
+    /// Returns the ASID to run this address space with on the calling core,
+    /// allocating a new one if it has none or its ASID was recycled since it
+    /// last ran. Its translations are only cached in the TLB under that ASID,
+    /// so that switching address spaces does not need to flush the TLB.
+    pub fn activate(&mut self) -> u16 {
+        asid::activate(&mut self.asid, smp::core())
+    }
+
+    /// Invalidates the cached translations of this address space on every
+    /// core, after its tables changed.
+    fn flush_tlb(&self) {
+        if self.asid != 0 {
+            aarch64::flush_tlb_asid(asid::id(self.asid));
+        }
+    }
+
     #[must_use]
     pub fn area_rx(&mut self, area: Area) -> Option<()> {
         self.area(area.prot(Prot::RX))
@@ -122,6 +181,9 @@ impl Memory {
         let entry = area.entry;
         let p = area.physical;
 
+        if self.areas.iter().any(|other| other.overlaps(&area)) {
+            return None;
+        }
         self.areas.try_reserve(1).ok()?;
         self.areas.push(area);
 
@@ -146,6 +208,10 @@ impl Memory {
         Some(())
     }
 
+    pub fn areas(&self) -> &[Area] {
+        &self.areas
+    }
+
     pub fn find_area_mut(&mut self, addr: VirtualAddr) -> Option<&mut Area> {
         self.areas.iter_mut().find(|area| area.contains(addr))
     }
@@ -158,17 +224,354 @@ impl Memory {
         self.areas.iter().any(|area| area.contains(addr))
     }
 
-    pub fn page_fault(&mut self, addr: u64) {
-        let addr = VirtualAddr::from((addr as usize) as *mut u8);
-        let page = Page::new().expect("allocate page");
-        unsafe { self.add_page(addr, page).expect("add page") };
+    /// Returns the physical address the virtual address `v` is mapped to, or
+    /// `None` if it is not mapped.
+    pub fn translate(&mut self, v: VirtualAddr) -> Option<PhysicalAddr> {
+        let l2 = self.root.next_table(v)?;
+        let entry = l2.read(v);
+        if entry.is_block() {
+            return Some((entry.addr().as_usize() + v.as_usize() % HUGESZ).into());
+        }
+        let l3 = l2.next_table(v)?;
+        let entry = l3.read(v);
+        if !entry.is_valid() {
+            return None;
+        }
+        Some((entry.addr().as_usize() + v.as_usize() % PAGESZ).into())
+    }
+
+    /// Checks that user space may access the `len` bytes at `addr` with
+    /// `prot` and backs the pages of the range that were not touched yet, so
+    /// the kernel can access them without faulting.
+    #[must_use]
+    pub fn check_user(&mut self, addr: usize, len: usize, prot: Prot) -> Option<()> {
+        self.fault_in(addr, len, prot).ok()
+    }
+
+    /// Like `check_user`, but tells why the range cannot be accessed.
+    pub fn fault_in(&mut self, addr: usize, len: usize, prot: Prot) -> Result<(), FaultError> {
+        let end = addr.checked_add(len).ok_or(FaultError::Invalid)?;
+        let mut page = align_down(addr, PAGESZ);
+        while page < end {
+            let v = VirtualAddr::from(max(page, addr) as *mut u8);
+            let permitted = self.find_area(v).map_or(false, |area| area.protection().contains(prot));
+            if !permitted {
+                return Err(FaultError::Invalid);
+            }
+            if self.translate(v).is_none() {
+                self.back_page(v)?;
+            }
+            let cow = prot.contains(Prot::WRITE) &&
+                self.page_entry(v).map_or(false, |e| e.read().is_cow());
+            if cow {
+                self.copy_on_write(v)?;
+            }
+            page += PAGESZ;
+        }
+        Ok(())
+    }
+
+    /// Backs every page of the `len` bytes at `addr` that is not mapped yet
+    /// with a zeroed page. The range must lie within areas.
+    #[must_use]
+    pub fn populate(&mut self, addr: usize, len: usize) -> Option<()> {
+        let end = addr.checked_add(len)?;
+        let mut page = align_down(addr, PAGESZ);
+        while page < end {
+            let v = VirtualAddr::from(max(page, addr) as *mut u8);
+            if self.translate(v).is_none() {
+                self.back_page(v).ok()?;
+            }
+            page += PAGESZ;
+        }
+        Some(())
+    }
+
+    /// Copies `bytes` to the `addr` of this address space, backing the pages
+    /// that are not mapped yet. The range must lie within areas.
+    #[must_use]
+    pub fn copy_to(&mut self, addr: usize, bytes: &[u8]) -> Option<()> {
+        self.populate(addr, bytes.len())?;
+        let mut done = 0;
+        while done < bytes.len() {
+            let v = addr + done;
+            let len = min(bytes.len() - done, PAGESZ - v % PAGESZ);
+            let p = self.translate(VirtualAddr::from(v as *mut u8))?;
+            unsafe { ptr::copy_nonoverlapping(bytes[done..].as_ptr(), p2v(p).as_mut_ptr(), len) };
+            done += len;
+        }
+        Some(())
+    }
+
+    /// Copies the bytes at `addr` of this address space to `buf`. The range
+    /// must be backed: see `check_user`.
+    #[must_use]
+    pub fn copy_from(&mut self, addr: usize, buf: &mut [u8]) -> Option<()> {
+        let mut done = 0;
+        while done < buf.len() {
+            let v = addr + done;
+            let len = min(buf.len() - done, PAGESZ - v % PAGESZ);
+            let p = self.translate(VirtualAddr::from(v as *mut u8))?;
+            unsafe { ptr::copy_nonoverlapping(p2v(p).as_ptr(), buf[done..].as_mut_ptr(), len) };
+            done += len;
+        }
+        Some(())
+    }
+
+    /// Returns a copy of this address space. Pages backed by memory of the
+    /// address space are shared copy-on-write by both copies, mapped memory
+    /// is mapped into the copy as well.
+    pub fn fork(&mut self) -> Option<Box<Self>> {
+        let mut child = Self::new()?;
+        child.page_limit = self.page_limit;
+        child.areas.try_reserve(self.areas.len()).ok()?;
+
+        for i in 0..self.areas.len() {
+            let area = self.areas[i].clone();
+            let mapped = area.physical.is_some();
+            let mut addr = align_down(area.start.as_usize(), PAGESZ);
+            let end = align_up(area.end.as_usize(), PAGESZ);
+            child.area(area)?;
+            if mapped {
+                continue;
+            }
+
+            while addr < end {
+                let v = VirtualAddr::from(addr as *mut u8);
+                addr += PAGESZ;
+                // pages shared by adjacent areas are copied once
+                if child.translate(v).is_some() {
+                    continue;
+                }
+                let entry = match self.page_entry(v) {
+                    Some(entry) => {
+                        let mut e = entry.read();
+                        if !e.is_valid() {
+                            continue;
+                        }
+                        // frames of shared memory stay shared
+                        if !e.contains(Entry::AP_RO) && !e.contains(Entry::SHARED) {
+                            e |= Entry::AP_RO | Entry::COW;
+                            entry.write(e);
+                        }
+                        e
+                    }
+                    None => continue,
+                };
+                refcount::share(entry.addr());
+                let l2 = child.root.next_table_or(v, Entry::USER_BASE)?;
+                let l3 = l2.next_table_or(v, Entry::USER_BASE)?;
+                l3[v].write(entry);
+                child.resident += 1;
+            }
+        }
+
+        self.flush_tlb();
+        Some(child)
+    }
+
+    /// Resolves a write to the copy-on-write page at `v`: unless this address
+    /// space is the last owner of the page, the page is copied, and it is
+    /// mapped writable. Fails if `v` is not mapped copy-on-write or there is
+    /// no memory for the copy.
+    ///
+    /// The other owners may resolve their writes on other cores meanwhile:
+    /// the page is copied before its reference is released, and freed if the
+    /// others released theirs in between.
+    pub fn copy_on_write(&mut self, v: VirtualAddr) -> Result<(), FaultError> {
+        let e = self.page_entry(v).ok_or(FaultError::Invalid)?.read();
+        if !e.is_cow() {
+            return Err(FaultError::Invalid);
+        }
+        let mut entry = e - Entry::COW - Entry::AP_RO;
+        if refcount::is_shared(e.addr()) {
+            let page = Page::new().ok_or(FaultError::NoMemory)?;
+            unsafe {
+                ptr::copy_nonoverlapping(p2v(e.addr()).as_ptr(), page.ptr.as_ptr() as *mut u8, PAGESZ);
+            }
+            if refcount::release(e.addr()) {
+                drop(unsafe { Page::from_physical(e.addr()) });
+            }
+            entry = (entry - Entry::ADDRESS_MASK).with_addr(page.into());
+        }
+        self.page_entry(v).ok_or(FaultError::Invalid)?.write(entry);
+        self.flush_tlb();
+        Ok(())
+    }
+
+    /// Unmaps the page at `addr` and returns it, to be mapped into another
+    /// address space. The page is backed and made private first if it was
+    /// not; the address reads as zeroes afterwards. Fails if `addr` is not
+    /// page aligned or does not lie in a writable area backed by memory of
+    /// the address space.
+    pub fn take_page(&mut self, addr: usize) -> Result<Page, FaultError> {
+        let v = VirtualAddr::from(addr as *mut u8);
+        let mapped = self.find_area(v).map_or(true, |area| area.physical.is_some());
+        if addr % PAGESZ != 0 || mapped {
+            return Err(FaultError::Invalid);
+        }
+        self.fault_in(addr, PAGESZ, Prot::RW)?;
+        let e = {
+            let entry = self.page_entry(v).ok_or(FaultError::Invalid)?;
+            let e = entry.read();
+            if !e.need_drop() || e.is_shared() {
+                return Err(FaultError::Invalid);
+            }
+            entry.write(Entry::INVALID);
+            e
+        };
+        self.flush_tlb();
+        self.resident -= 1;
+        Ok(unsafe { Page::from_entry(e) })
+    }
+
+    /// Maps `page`, returned by `take_page` for `addr`, back at `addr`. Fails
+    /// if the address was backed again meanwhile; the page is freed then.
+    pub fn untake_page(&mut self, page: Page, addr: usize) -> Option<()> {
+        let v = VirtualAddr::from(addr as *mut u8);
+        if self.page_entry(v).map_or(false, |entry| entry.read().is_valid()) {
+            return None;
+        }
+        unsafe { self.add_page(v, page) }
+    }
+
+    /// Maps `page` read-write at the first free address of the `count` pages
+    /// from `base` and returns the address, or returns `None` if there is no
+    /// free address or no memory for the tables.
+    pub fn give_page(&mut self, page: Page, base: usize, count: usize) -> Option<usize> {
+        let addr = (0..count)
+            .map(|i| base + i * PAGESZ)
+            .find(|&addr| !self.has_addr(VirtualAddr::from(addr as *mut u8)))?;
+        self.area_rw(Area::new(addr, addr + PAGESZ))?;
+        unsafe { self.add_page(VirtualAddr::from(addr as *mut u8), page)? };
+        Some(addr)
+    }
+
+    /// Returns the first page aligned address from `base` where `len` bytes
+    /// fit below `end` without overlapping an area.
+    pub fn find_free(&self, base: usize, end: usize, len: usize) -> Option<usize> {
+        let mut addr = align_up(base, PAGESZ);
+        loop {
+            let stop = addr.checked_add(len)?;
+            if stop > end {
+                return None;
+            }
+            let range = Area::new(addr, stop);
+            let next = self.areas.iter()
+                .filter(|area| area.overlaps(&range))
+                .map(|area| area.end.as_usize())
+                .max();
+            match next {
+                Some(next) => addr = align_up(next, PAGESZ),
+                None => return Some(addr),
+            }
+        }
+    }
+
+    /// Maps the frames of `object` at the page aligned `addr` with `prot`:
+    /// writes through any mapping of the object are seen by all of them,
+    /// forks included. Fails if the range overlaps an area, the address space
+    /// would exceed its page limit, or there is no memory for the tables.
+    pub fn map_shared(&mut self, addr: usize, object: &SharedMemory, prot: Prot) -> Result<(), FaultError> {
+        let frames = object.frames();
+        if addr % PAGESZ != 0 {
+            return Err(FaultError::Invalid);
+        }
+        if self.resident + frames.len() > self.page_limit {
+            return Err(FaultError::Limit);
+        }
+        let end = addr.checked_add(object.size()).ok_or(FaultError::Invalid)?;
+        let area = Area::new(addr, end).prot(prot).shared();
+        let entry = area.entry;
+        self.area(area).ok_or(FaultError::Invalid)?;
+
+        for (i, &p) in frames.iter().enumerate() {
+            let v = VirtualAddr::from((addr + i * PAGESZ) as *mut u8);
+            let mapped = self.map_frame(v, p, entry);
+            if mapped.is_none() {
+                self.unmap_shared(addr);
+                return Err(FaultError::NoMemory);
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps the frame `p` of a shared memory object at `v`, adding an owner
+    /// to it.
+    fn map_frame(&mut self, v: VirtualAddr, p: PhysicalAddr, entry: Entry) -> Option<()> {
+        let l2 = self.root.next_table_or(v, Entry::USER_BASE)?;
+        let l3 = l2.next_table_or(v, Entry::USER_BASE)?;
+        refcount::share(p);
+        l3[v].write(Entry::page(p) | Entry::NEED_DROP | entry);
+        self.resident += 1;
+        Some(())
+    }
+
+    /// Unmaps the shared memory mapped at `addr` by `map_shared`, releasing
+    /// its frames: a frame is freed once no object or mapping owns it
+    /// anymore. Returns `None` if no shared memory is mapped at `addr`.
+    pub fn unmap_shared(&mut self, addr: usize) -> Option<()> {
+        let start = VirtualAddr::from(addr as *mut u8);
+        let i = self.areas.iter().position(|area| area.start == start && area.is_shared())?;
+        let area = self.areas.remove(i);
+
+        let mut released = Vec::new();
+        let mut page = area.start.as_usize();
+        while page < area.end.as_usize() {
+            let v = VirtualAddr::from(page as *mut u8);
+            page += PAGESZ;
+            match self.page_entry(v) {
+                Some(entry) => {
+                    let e = entry.read();
+                    if e.is_valid() {
+                        entry.write(Entry::INVALID);
+                        released.push(e);
+                    }
+                }
+                None => continue,
+            }
+        }
+        self.resident -= released.len();
+        // no core may still write to a frame once it is freed
+        self.flush_tlb();
+        for e in released {
+            if refcount::release(e.addr()) {
+                drop(unsafe { Page::from_entry(e) });
+            }
+        }
+        Some(())
+    }
+
+    /// Returns the page table entry of the page at `v`, if `v` is not part of
+    /// a block.
+    fn page_entry(&mut self, v: VirtualAddr) -> Option<&mut Volatile<Entry>> {
+        let l3 = self.root.next_table(v)?.next_table(v)?;
+        Some(&mut l3[v])
+    }
+
+    /// Resolves a translation fault of user space accessing `addr` with
+    /// `access` by backing the page with a zeroed page. Fails if `addr` lies
+    /// outside of every area, its area does not permit `access`, the address
+    /// space reached its page limit, or there is no memory.
+    pub fn page_fault(&mut self, addr: usize, access: Prot) -> Result<(), FaultError> {
+        self.fault_in(addr, 1, access)
+    }
+
+    /// Backs the page at `v` with a zeroed page, within the page limit.
+    fn back_page(&mut self, v: VirtualAddr) -> Result<(), FaultError> {
+        if self.resident >= self.page_limit {
+            return Err(FaultError::Limit);
+        }
+        let page = Page::new_zeroed().ok_or(FaultError::NoMemory)?;
+        unsafe { self.add_page(v, page) }.ok_or(FaultError::NoMemory)
     }
 
     unsafe fn add_page(&mut self, v: VirtualAddr, p: Page) -> Option<()> {
         let entry = self.find_area(v)?.entry;
         let l2 = self.root.next_table_or(v, Entry::USER_BASE)?;
         let l3 = l2.next_table_or(v, Entry::USER_BASE)?;
-        l3[v].write(Entry::page(p.into()) | entry);
+        l3[v].write(Entry::page(p.into()) | Entry::NEED_DROP | entry);
+        self.resident += 1;
         Some(())
     }
 }
@@ -182,11 +585,12 @@ bitflags! {
         const RO = Self::READ.bits;
         const RW = Self::READ.bits | Self::WRITE.bits;
         const RX = Self::READ.bits | Self::EXEC.bits;
+        const RWX = Self::READ.bits | Self::WRITE.bits | Self::EXEC.bits;
     }
 }
 
 bitflags! {
-    pub struct Map: u8 {
+    pub struct Map: u16 {
         const EXEC  = 1 << 0;
         const WRITE = 1 << 1;
         const READ  = 1 << 2;
@@ -203,7 +607,7 @@ bitflags! {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Area {
     pub start: VirtualAddr,
     pub end: VirtualAddr,
@@ -230,10 +634,33 @@ impl Area {
             Prot::RO => Entry::USER_RO,
             Prot::RW => Entry::USER_RW,
             Prot::RX => Entry::USER_RX,
+            Prot::RWX => Entry::USER_RWX,
             _ => unimplemented!(),
         };
         self
     }
+    /// Returns the access rights user space has to this area.
+    pub fn protection(&self) -> Prot {
+        let mut prot = Prot::empty();
+        if self.entry.contains(Entry::AF | Entry::AP_EL0) {
+            prot |= Prot::READ;
+            if !self.entry.contains(Entry::AP_RO) {
+                prot |= Prot::WRITE;
+            }
+            if !self.entry.contains(Entry::XN) {
+                prot |= Prot::EXEC;
+            }
+        }
+        prot
+    }
+    /// Marks the area as mapping shared memory: see `Memory::map_shared`.
+    pub fn shared(mut self) -> Self {
+        self.entry |= Entry::SHARED;
+        self
+    }
+    pub fn is_shared(&self) -> bool {
+        self.entry.contains(Entry::SHARED)
+    }
     pub fn map_to(mut self, p: PhysicalAddr) -> Self {
         self.physical = Some(p);
         self
@@ -250,16 +677,23 @@ impl Area {
     pub fn intersects(&self, other: Self) -> bool {
         self.contains(other.start) || self.contains(other.end)
     }
+
+    pub fn overlaps(&self, other: &Self) -> bool {
+        self.start < other.end && other.start < self.end
+    }
 }
 
 /// Set up page translation tables and enable virtual memory
+/// The translation tables of the kernel, shared by all cores.
+static mut L1: Table<L1> = Table::empty();
+static mut L2: Table<L2> = Table::empty();
+static mut L3: Table<L3> = Table::empty();
+
+/// Sets up the translation tables of the kernel and enables the MMU and the
+/// caches of the calling core: core 0.
 pub fn initialize() {
     #![allow(non_snake_case)]
 
-    static mut L1: Table<L1> = Table::empty();
-    static mut L2: Table<L2> = Table::empty();
-    static mut L3: Table<L3> = Table::empty();
-
     let NORMAL: Entry = Entry::AF | Entry::ISH;
     let DATA: Entry  = NORMAL | Entry::XN | Entry::PXN;
     let CODE: Entry  = NORMAL | Entry::AP_RO;
@@ -268,7 +702,7 @@ pub fn initialize() {
     let start = kernel_start().as_usize();
     let data = kernel_data().as_usize();
 
-    let ttbr = unsafe {
+    unsafe {
         L1.get_mut(0).write(Entry::table((&mut L2 as *mut Table<L2>).into()) | NORMAL);
         L2.get_mut(0).write(Entry::table((&mut L3 as *mut Table<L3>).into()) | NORMAL);
         for n in 1..512usize {
@@ -279,9 +713,19 @@ pub fn initialize() {
             let addr = n * PAGESZ;
             L3.get_mut(n).write(Entry::page(addr.into()) | if addr < start || addr >= data { DATA } else { CODE });
         }
+        // the per-core peripherals, right above the others
+        L1.get_mut(1).write(Entry::block(LOCAL_BASE_RAW.into()) | DEV);
+    }
+    asid::initialize(aarch64::MMFR::new().asid_bits());
+
+    initialize_core();
+}
 
-        &mut L1 as *mut _ as u64
-    };
+/// Enables the MMU and the caches of the calling core with the translation
+/// tables set up by `initialize`. Secondary cores call this before touching
+/// memory shared with the other cores.
+pub fn initialize_core() {
+    let ttbr = unsafe { &mut L1 as *mut _ as u64 };
 
     aarch64::set_ttbr0_el1(0, ttbr, true);
     aarch64::set_ttbr1_el1(1, ttbr, true);
@@ -296,6 +740,7 @@ pub fn initialize() {
     assert!(par >= aarch64::PhysicalAddressRange::R36, "36 bit address space not supported");
 
     let ips = par.raw() as u64;
+    let asid16 = (mmfr.asid_bits() == 16) as u64;
 
     // first, set Memory Attributes array,
     // indexed by PT_MEM, PT_DEV, PT_NC in our example
@@ -307,12 +752,14 @@ pub fn initialize() {
     // next, specify mapping characteristics in translate control register
     let tcr: u64 =
         (0b00 << 37) | // TBI=0, no tagging
+        (asid16 << 36) | // AS=1 if 16 bit ASIDs are supported
         (ips  << 32) | // IPS=autodetected
         (0b10 << 30) | // TG1=4k
         (0b11 << 28) | // SH1=3 inner
         (0b01 << 26) | // ORGN1=1 write back
         (0b01 << 24) | // IRGN1=1 write back
         (0b0  << 23) | // EPD1 enable higher half
+        (0b0  << 22) | // A1=0, the ASID is defined by TTBR0
         (25   << 16) | // T1SZ=25, 3 levels (512G)
         (0b00 << 14) | // TG0=4k
         (0b11 << 12) | // SH0=3 inner
@@ -340,12 +787,12 @@ pub fn initialize() {
         r &= !((1<<25)  |   // clear EE, little endian translation tables
                (1<<24)  |   // clear E0E
                (1<<19)  |   // clear WXN
-               (1<<12)  |   // clear I, no instruction cache
                (1<< 4)  |   // clear SA0
                (1<< 3)  |   // clear SA
-               (1<< 2)  |   // clear C, no cache at all
                (1<< 1));    // clear A, no aligment check
-        r |=    1<< 0;      // set M, enable MMU
+        r |=   (1<<12)  |   // set I, instruction cache
+               (1<< 2)  |   // set C, data cache: exclusive accesses need it
+               (1<< 0);     // set M, enable MMU
 
         asm!("msr sctlr_el1, $0; isb" :: "r"(r) :: "volatile");
     }
@@ -41,6 +41,13 @@ impl Page {
         Self { ptr, need_drop: e.need_drop() }
     }
 
+    /// Takes back the page at `p`, given up with `into`.
+    #[must_use]
+    pub unsafe fn from_physical(p: PhysicalAddr) -> Self {
+        let ptr = NonNull::new_unchecked(p2v(p).as_mut_ptr());
+        Self { ptr, need_drop: true }
+    }
+
     pub fn into_ptr<T>(self) -> *mut T {
         let ptr = self.ptr.cast().as_ptr();
         forget(self);
@@ -0,0 +1,43 @@
+use alloc::BTreeMap;
+
+use sys::Mutex;
+use vm::PhysicalAddr;
+
+/// The number of address spaces sharing each page that is shared. Pages that
+/// are not in the map have a single owner.
+static SHARED: Mutex<Option<BTreeMap<PhysicalAddr, usize>>> = Mutex::new(None);
+
+/// Adds an owner to the page at `p`.
+pub fn share(p: PhysicalAddr) {
+    let mut guard = SHARED.lock().unwrap();
+    let count = guard.get_or_insert_with(BTreeMap::new).entry(p).or_insert(1);
+    *count += 1;
+}
+
+/// Returns `true` if the page at `p` has more than one owner.
+pub fn is_shared(p: PhysicalAddr) -> bool {
+    let guard = SHARED.lock().unwrap();
+    guard.as_ref().map_or(false, |shared| shared.contains_key(&p))
+}
+
+/// Removes an owner from the page at `p`. Returns `true` if it was the last
+/// one, in which case the caller must free the page.
+#[must_use]
+pub fn release(p: PhysicalAddr) -> bool {
+    let mut guard = SHARED.lock().unwrap();
+    let shared = match guard.as_mut() {
+        Some(shared) => shared,
+        None => return true,
+    };
+    let last = match shared.get_mut(&p) {
+        Some(count) => {
+            *count -= 1;
+            *count == 1
+        }
+        None => return true,
+    };
+    if last {
+        shared.remove(&p);
+    }
+    false
+}
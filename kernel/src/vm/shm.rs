@@ -0,0 +1,98 @@
+use alloc::arc::Arc;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+use sys::io;
+use sys::Mutex;
+use allocator::util::align_up;
+use vm::{Page, PhysicalAddr, PAGESZ};
+use vm::refcount;
+
+/// Where shared memory objects are mapped when the caller leaves the
+/// address to the kernel: the first free range below `MAP_END`.
+pub const MAP_BASE: usize = 6 * 0x4000_0000;
+pub const MAP_END: usize = 8 * 0x4000_0000;
+
+/// Zeroed frames that address spaces map at once, each at an address of its
+/// own, and see each other's writes to. Every mapping of a frame and the
+/// object itself own a reference to the frame: it is freed once the object
+/// and its last mapping are gone.
+pub struct SharedMemory {
+    frames: Vec<PhysicalAddr>,
+}
+
+impl SharedMemory {
+    /// Returns a new object of `size` bytes, rounded up to whole pages, or
+    /// `None` if there is no memory for it.
+    pub fn new(size: usize) -> Option<Self> {
+        let count = align_up(size, PAGESZ) / PAGESZ;
+        let mut object = SharedMemory { frames: Vec::new() };
+        object.frames.try_reserve(count).ok()?;
+        for _ in 0..count {
+            object.frames.push(Page::new_zeroed()?.into());
+        }
+        Some(object)
+    }
+
+    /// Returns the size of the object in bytes.
+    pub fn size(&self) -> usize {
+        self.frames.len() * PAGESZ
+    }
+
+    /// Returns the frames of the object, in order.
+    pub(super) fn frames(&self) -> &[PhysicalAddr] {
+        &self.frames
+    }
+}
+
+impl Drop for SharedMemory {
+    fn drop(&mut self) {
+        for &p in &self.frames {
+            if refcount::release(p) {
+                drop(unsafe { Page::from_physical(p) });
+            }
+        }
+    }
+}
+
+/// The objects that have a name, by name.
+static OBJECTS: Mutex<Option<Vec<(String, Arc<SharedMemory>)>>> = Mutex::new(None);
+
+/// Returns the object named `name`. If there is none and `create` is set,
+/// creates one of `create` bytes under that name.
+///
+/// # Errors
+///
+/// Fails with `NotFound` if there is no such object and `create` is `None`,
+/// and with `Other` if there is no memory for a new object.
+pub fn open(name: &str, create: Option<usize>) -> io::Result<Arc<SharedMemory>> {
+    let mut guard = OBJECTS.lock().unwrap();
+    let objects = guard.get_or_insert_with(Vec::new);
+    if let Some(&(_, ref object)) = objects.iter().find(|&&(ref n, _)| n == name) {
+        return Ok(object.clone());
+    }
+    let size = create.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such object"))?;
+    let object = Arc::new(SharedMemory::new(size)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "out of memory"))?);
+    objects.push((String::from(name), object.clone()));
+    Ok(object)
+}
+
+/// Removes the name `name`. The object lives on until it is neither open
+/// nor mapped anymore.
+///
+/// # Errors
+///
+/// Fails with `NotFound` if there is no such object.
+pub fn unlink(name: &str) -> io::Result<()> {
+    let removed = {
+        let mut guard = OBJECTS.lock().unwrap();
+        let objects = guard.get_or_insert_with(Vec::new);
+        let i = objects.iter().position(|&(ref n, _)| n == name)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no such object"))?;
+        objects.remove(i)
+    };
+    // the frames are released outside of the lock
+    drop(removed);
+    Ok(())
+}
@@ -7,6 +7,7 @@ use sys::volatile::Volatile;
 use sys::volatile::prelude::*;
 
 use vm::{Entry, VirtualAddr, PhysicalAddr, Page, Huge};
+use vm::refcount;
 
 #[repr(align(4096))]
 #[derive(Clone)]
@@ -182,6 +183,10 @@ impl Drop for L2 {
 impl Drop for L3 {
     fn drop(&mut self) {
         for e in self.iter().filter(Entry::is_valid) {
+            // pages shared with other address spaces stay alive
+            if e.need_drop() && !refcount::release(e.addr()) {
+                continue;
+            }
             unsafe { drop(Page::from_entry(e)) }
         }
     }
@@ -6,6 +6,17 @@ pub const IO_BASE: usize = 0xFFFFFF80_00000000 | IO_BASE_RAW;
 #[cfg(not(feature = "higher_half"))]
 pub const IO_BASE: usize = IO_BASE_RAW;
 
+/// The address where the BCM2836 per-core peripherals are mapped to.
+pub const LOCAL_BASE_RAW: usize = 0x40000000;
+
+#[cfg(feature = "higher_half")]
+pub const LOCAL_BASE: usize = 0xFFFFFF80_00000000 | LOCAL_BASE_RAW;
+#[cfg(not(feature = "higher_half"))]
+pub const LOCAL_BASE: usize = LOCAL_BASE_RAW;
+
+/// The size of a data cache line of the Cortex-A53.
+pub const CACHE_LINE: usize = 64;
+
 /// Generates `pub enums` with no variants for each `ident` passed in.
 pub macro states($($name:ident),*) {
     $(
@@ -53,3 +64,15 @@ pub fn spin_wait<F>(mut f: F)
         f()
     } {}
 }
+
+/// Cleans and invalidates the data cache lines holding the `len` bytes at
+/// `addr`: writes pending in the cache reach memory, and later reads fetch
+/// what devices wrote to memory meanwhile.
+pub fn clean_invalidate_dcache(addr: usize, len: usize) {
+    let mut line = addr & !(CACHE_LINE - 1);
+    while line < addr + len {
+        unsafe { asm!("dc civac, $0" :: "r"(line) :: "volatile"); }
+        line += CACHE_LINE;
+    }
+    unsafe { asm!("dsb sy" :::: "volatile"); }
+}
@@ -6,6 +6,7 @@ use sys::volatile::{Volatile, WriteVolatile, ReadVolatile, Reserved};
 
 /// An alternative GPIO function.
 #[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Function {
     Input = 0b000,
     Output = 0b001,
@@ -96,12 +97,38 @@ impl<T> Gpio<T> {
         }
     }
 
+    /// Returns the function currently selected for the pin.
+    pub fn function(&self) -> Function {
+        let index = (self.pin / 10) as usize;
+        let shift = ((self.pin % 10) * 3) as u32;
+        match (self.registers.FSEL[index].read() >> shift) & 0b111 {
+            0b000 => Function::Input,
+            0b001 => Function::Output,
+            0b100 => Function::Alt0,
+            0b101 => Function::Alt1,
+            0b110 => Function::Alt2,
+            0b111 => Function::Alt3,
+            0b011 => Function::Alt4,
+            _ => Function::Alt5,
+        }
+    }
+
+    /// Reads the pin's value. Returns `true` if the level is high and `false`
+    /// if the level is low.
+    ///
+    /// The level can be read whatever function the pin is set to.
+    pub fn level(&mut self) -> bool {
+        let index = (self.pin / 32) as usize;
+        let shift = (self.pin % 32) as u32;
+        self.registers.LEV[index].read() & (1 << shift) != 0
+    }
+
     pub fn set_pud(&mut self, pud: Pud) {
         let index = (self.pin / 32) as usize;
         let shift = (self.pin % 32) as u32;
         self.registers.PUD.write(pud as u32);
         spin_sleep_cycles(150);
-        self.registers.PUDCLK[index].and_mask(1 << shift);
+        self.registers.PUDCLK[index].write(1 << shift);
         spin_sleep_cycles(150);
         self.registers.PUD.write(0);
         self.registers.PUDCLK[index].write(0);
@@ -235,16 +262,6 @@ impl Gpio<Output> {
     }
 }
 
-impl Gpio<Input> {
-    /// Reads the pin's value. Returns `true` if the level is high and `false`
-    /// if the level is low.
-    pub fn level(&mut self) -> bool {
-        let index = (self.pin / 32) as usize;
-        let shift = (self.pin % 32) as u32;
-        self.registers.LEV[index].read() & (1 << shift) != 0
-    }
-}
-
 impl Gpio<Alt> {
     pub fn pull(&mut self, value: Pud) {
         self.set_pud(value);
@@ -19,3 +19,5 @@ pub mod common;
 pub mod atags;
 pub mod interrupt;
 pub mod mbox;
+pub mod rng;
+pub mod local;
@@ -0,0 +1,121 @@
+use core::cmp::min;
+
+use common::LOCAL_BASE;
+use timer::current_time;
+use sys::volatile::prelude::*;
+use sys::volatile::{Volatile, ReadVolatile, WriteVolatile, Reserved};
+
+/// The number of cores.
+pub const NCORES: usize = 4;
+
+/// An interrupt source of a core, as reported by the BCM2836 per-core
+/// interrupt controller.
+#[repr(u32)]
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum LocalInterrupt {
+    /// The non-secure physical timer of the core: see `tick_in`.
+    Timer = 1,
+    Mailbox0 = 4,
+    Mailbox1 = 5,
+    Mailbox2 = 6,
+    Mailbox3 = 7,
+    /// An interrupt of the GPU interrupt controller, routed to core 0: see
+    /// `interrupt::Controller`.
+    Gpu = 8,
+}
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    __r0: [Reserved<u32>; 16],
+    TIMER_INT_CONTROL: [Volatile<u32>; NCORES],
+    MAILBOX_INT_CONTROL: [Volatile<u32>; NCORES],
+    IRQ_SOURCE: [ReadVolatile<u32>; NCORES],
+    FIQ_SOURCE: [ReadVolatile<u32>; NCORES],
+    MAILBOX_SET: [[WriteVolatile<u32>; 4]; NCORES],
+    MAILBOX_CLEAR: [[Volatile<u32>; 4]; NCORES],
+}
+
+/// The interrupt controller and the mailboxes of a core. Writing to the
+/// mailbox of another core interrupts that core.
+pub struct LocalController {
+    registers: &'static mut Registers,
+    core: usize,
+}
+
+impl LocalController {
+    /// Returns a new handle to the interrupt controller of `core`.
+    pub fn new(core: usize) -> Self {
+        unsafe { Self::new_from(LOCAL_BASE, core) }
+    }
+
+    /// Returns a new handle to the interrupt controller of `core`.
+    pub unsafe fn new_from(base: usize, core: usize) -> Self {
+        assert!(core < NCORES, "no such core");
+        let registers = &mut *(base as *mut Registers);
+        Self { registers, core }
+    }
+
+    /// Routes the interrupt of the non-secure physical timer to the IRQ of
+    /// the core.
+    pub fn enable_timer(&mut self) {
+        self.registers.TIMER_INT_CONTROL[self.core].or_mask(1 << 1);
+    }
+
+    /// Raises an IRQ of the core whenever its mailbox `mailbox` is not
+    /// zero.
+    pub fn enable_mailbox(&mut self, mailbox: usize) {
+        self.registers.MAILBOX_INT_CONTROL[self.core].or_mask(1 << mailbox);
+    }
+
+    /// Returns `true` if `int` is pending. Otherwise, returns `false`.
+    pub fn is_pending(&self, int: LocalInterrupt) -> bool {
+        self.registers.IRQ_SOURCE[self.core].read() & (1 << int as u32) != 0
+    }
+
+    /// Sets `bits` in the mailbox `mailbox` of the core `core`.
+    pub fn send(&mut self, core: usize, mailbox: usize, bits: u32) {
+        self.registers.MAILBOX_SET[core][mailbox].write(bits);
+    }
+
+    /// Clears the mailbox `mailbox` of the core and returns the bits that
+    /// were set.
+    pub fn take(&mut self, mailbox: usize) -> u32 {
+        let bits = self.registers.MAILBOX_CLEAR[self.core][mailbox].read();
+        self.registers.MAILBOX_CLEAR[self.core][mailbox].write(bits);
+        bits
+    }
+}
+
+/// Returns the frequency of the generic timer, in Hz.
+fn frequency() -> u64 {
+    let f: u64;
+    unsafe { asm!("mrs $0, cntfrq_el0" : "=r"(f) : : : "volatile"); }
+    f
+}
+
+/// Sets up the physical timer of the calling core to fire `us` microseconds
+/// from now. If its interrupt is enabled and IRQs are unmasked, then a timer
+/// interrupt will be issued in `us` microseconds.
+pub fn tick_in(us: u32) {
+    let ticks = min(us as u64 * frequency() / 1_000_000, i32::max_value() as u64);
+    unsafe {
+        asm!("msr cntp_tval_el0, $0
+              msr cntp_ctl_el0, $1
+              isb"
+             :: "r"(ticks), "r"(1u64) :: "volatile");
+    }
+}
+
+/// Sets up the physical timer of the calling core to fire at the time
+/// `time`, in microseconds, or right away if that time has passed.
+pub fn tick_at(time: u64) {
+    let us = min(time.saturating_sub(current_time()), u32::max_value() as u64);
+    tick_in(us as u32)
+}
+
+/// Disables the physical timer of the calling core, acknowledging its
+/// interrupt.
+pub fn clear_tick() {
+    unsafe { asm!("msr cntp_ctl_el0, xzr; isb" :::: "volatile"); }
+}
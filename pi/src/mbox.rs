@@ -1,4 +1,6 @@
-use common::{IO_BASE, spin_wait};
+use core::mem::size_of;
+
+use common::{IO_BASE, spin_wait, clean_invalidate_dcache};
 
 use sys::volatile::prelude::*;
 use sys::volatile::{Volatile, ReadVolatile, Reserved};
@@ -226,10 +228,14 @@ impl Mailbox {
         message.data[1] = REQUEST;
         message.data[2..request.len() + 2].copy_from_slice(request);
 
+        // The GPU reads and writes the message in memory, behind the cache
+        let (ptr, len) = (&message as *const Buffer as usize, size_of::<Buffer>());
+        clean_invalidate_dcache(ptr, len);
         // Write message to mailbox
         self.write(Channel::TAGS, arm2gpu(message.addr() as usize) as u32);
         // Wait for write response
         self.read(Channel::TAGS);
+        clean_invalidate_dcache(ptr, len);
         if message.data[1] == MAIL_RESPONSE {
             Some(message.data)
         } else {
@@ -0,0 +1,61 @@
+use common::IO_BASE;
+use sys::volatile::prelude::*;
+use sys::volatile::{Volatile, ReadVolatile};
+
+/// The base address for the hardware random number generator registers.
+pub const RNG_REG_BASE: usize = 0x104000;
+
+/// The number of initial numbers the generator discards while warming up.
+const WARMUP_COUNT: u32 = 0x40000;
+
+#[repr(C)]
+#[allow(non_snake_case)]
+struct Registers {
+    CTRL: Volatile<u32>,
+    STATUS: Volatile<u32>,
+    DATA: ReadVolatile<u32>,
+    FF_THRESHOLD: Volatile<u32>,
+    INT_MASK: Volatile<u32>,
+}
+
+/// The Raspberry Pi hardware random number generator.
+pub struct Rng {
+    registers: &'static mut Registers
+}
+
+impl Rng {
+    /// Returns a new instance of `Rng`, enabling the generator if it is not
+    /// running yet.
+    pub fn new() -> Self {
+        unsafe { Self::new_from(IO_BASE + RNG_REG_BASE) }
+    }
+
+    pub unsafe fn new_from(base: usize) -> Self {
+        let registers = &mut *(base as *mut Registers);
+        if registers.CTRL.read() & 1 == 0 {
+            registers.STATUS.write(WARMUP_COUNT);
+            // mask the interrupt, numbers are polled
+            registers.INT_MASK.or_mask(1);
+            registers.CTRL.or_mask(1);
+        }
+        Self { registers }
+    }
+
+    /// Returns the next random word, blocking until one is available.
+    pub fn next_u32(&mut self) -> u32 {
+        while self.registers.STATUS.read() >> 24 == 0 {
+            unsafe { asm!("nop" :::: "volatile"); }
+        }
+        self.registers.DATA.read()
+    }
+
+    /// Fills `buf` with random bytes.
+    pub fn fill(&mut self, buf: &mut [u8]) {
+        for chunk in buf.chunks_mut(4) {
+            let word = self.next_u32();
+            for (i, byte) in chunk.iter_mut().enumerate() {
+                *byte = (word >> (i * 8)) as u8;
+            }
+        }
+    }
+}
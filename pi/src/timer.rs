@@ -1,3 +1,5 @@
+use core::cmp::{min, max};
+
 use common::IO_BASE;
 use sys::volatile::prelude::*;
 use sys::volatile::{Volatile, ReadVolatile};
@@ -5,6 +7,11 @@ use sys::volatile::{Volatile, ReadVolatile};
 /// The base address for the ARM system timer registers.
 pub const TIMER_REG_BASE: usize = 0x3000;
 
+/// The shortest time, in microseconds, a match can be set up in. A match set
+/// up for a time that passed before the compare register is written is only
+/// issued once the counter wraps around.
+const MIN_TICK: u32 = 10;
+
 #[repr(C)]
 #[allow(non_snake_case)]
 struct Registers {
@@ -47,6 +54,18 @@ impl Timer {
         let us = self.registers.CLO.read().wrapping_add(us);
         self.registers.COMPARE[i].write(us);
     }
+
+    /// Sets up a match in timer 1 to occur at the time `time`, in
+    /// microseconds, or right away if that time has passed.
+    pub fn tick_at(&mut self, time: u64) {
+        let us = min(time.saturating_sub(self.read()), u32::max_value() as u64) as u32;
+        self.tick_in(max(us, MIN_TICK));
+    }
+
+    /// Acknowledges a match in timer 1 without setting up a new one.
+    pub fn clear_tick(&mut self) {
+        self.registers.CS.write(1 << 1);
+    }
 }
 
 /// Returns the current time in microseconds.
@@ -75,3 +94,14 @@ pub fn spin_sleep_ms(ms: u64) {
 pub fn tick_in(us: u32) {
     Timer::new().tick_in(us)
 }
+
+/// Sets up a match in timer 1 to occur at the time `time`, in microseconds,
+/// or right away if that time has passed.
+pub fn tick_at(time: u64) {
+    Timer::new().tick_at(time)
+}
+
+/// Acknowledges a match in timer 1 without setting up a new one.
+pub fn clear_tick() {
+    Timer::new().clear_tick()
+}
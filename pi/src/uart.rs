@@ -86,6 +86,14 @@ impl MiniUart {
         Self { registers, timeout: None }
     }
 
+    /// Enables the receive interrupt: the `Aux` interrupt is raised while
+    /// there are bytes to read.
+    pub fn enable_rx_interrupt(&mut self) {
+        // bit 0 enables the receive interrupt (the datasheet swaps bits 0 and
+        // 1), bits 2 and 3 must be set to receive interrupts at all
+        self.registers.IER.write(0b1101);
+    }
+
     /// Set the read timeout to `milliseconds` milliseconds.
     pub fn set_read_timeout(&mut self, milliseconds: u32) {
         self.timeout = Some(milliseconds);
@@ -20,7 +20,7 @@ mod util;
 
 pub use stack_vec::StackVec;
 pub use util::{SliceExt, VecExt};
-pub use mutex::Mutex;
+pub use mutex::{Mutex, MutexGuard};
 
 pub mod io;
 
@@ -109,6 +109,7 @@ pub mod collections {
 #[cfg(feature = "std_shim")]
 pub mod sync {
     pub use super::mutex::{Mutex, MutexGuard};
+    pub use alloc::arc::Arc;
     pub use core::sync::atomic;
 }
 
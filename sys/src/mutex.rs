@@ -29,32 +29,29 @@ impl<T> Mutex<T> {
 }
 
 impl<T> Mutex<T> {
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
+    // The exclusive loads and stores behind the atomic operations only work
+    // once the MMU and the data cache are enabled on every core.
     pub fn try_lock(&self) -> Option<MutexGuard<T>> {
-        if !self.lock.load(Ordering::Relaxed) {
-            self.lock.store(true, Ordering::Relaxed);
-            Some(MutexGuard { lock: &self })
-        } else {
-            None
+        match self.lock.compare_exchange(false, true, Ordering::Acquire, Ordering::Relaxed) {
+            Ok(_) => Some(MutexGuard { lock: &self }),
+            Err(_) => None,
         }
     }
 
-    // Once MMU/cache is enabled, do the right thing here. For now, we don't
-    // need any real synchronization.
+    // Spins until the lock is free. The lock is not reentrant: locking it
+    // again on the same core spins forever.
     #[inline(never)]
     pub fn lock(&self) -> Option<MutexGuard<T>> {
-        // Wait until we can "aquire" the lock, then "acquire" it.
         loop {
-            match self.try_lock() {
-                Some(guard) => return Some(guard),
-                None => continue
+            if let Some(guard) = self.try_lock() {
+                return Some(guard);
             }
+            while self.lock.load(Ordering::Relaxed) {}
         }
     }
 
     fn unlock(&self) {
-        self.lock.store(false, Ordering::Relaxed);
+        self.lock.store(false, Ordering::Release);
     }
 }
 
@@ -3,6 +3,7 @@
 #![feature(optin_builtin_traits)]
 #![feature(const_fn)]
 #![feature(asm)]
+#![feature(try_reserve)]
 #![allow(safe_packed_borrows)]
 
 #![allow(unused_must_use)]
@@ -30,6 +31,7 @@ mod mbr;
 mod util;
 
 pub mod vfat;
+pub mod tmpfs;
 pub mod traits;
 
 pub use mbr::*;
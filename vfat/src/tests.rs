@@ -1,7 +1,7 @@
 extern crate rand;
 
 use std::io::prelude::*;
-use std::io::Cursor;
+use std::io::{self, Cursor, SeekFrom};
 use std::path::Path;
 
 use vfat::{Shared, VFat, BiosParameterBlock};
@@ -408,3 +408,137 @@ fn read_vfat() {
         }
     }
 }
+
+fn tmpfs_clock() -> u64 {
+    // 2018-05-24 12:34:56
+    1527165296
+}
+
+#[test]
+fn tmpfs_create_read_write() {
+    use tmpfs::TmpFs;
+
+    let fs = TmpFs::new(1 << 20, tmpfs_clock);
+    let mut file = (&fs).create_file("/hello.txt").expect("create file");
+    file.write_all(b"hello, world").unwrap();
+    assert_eq!(file.size(), 12);
+
+    let mut file = (&fs).open_file("/hello.txt").expect("open file");
+    let mut data = String::new();
+    file.read_to_string(&mut data).unwrap();
+    assert_eq!(data, "hello, world");
+
+    file.seek(SeekFrom::Start(7)).unwrap();
+    file.write_all(b"tmpfs!").unwrap();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    data.clear();
+    file.read_to_string(&mut data).unwrap();
+    assert_eq!(data, "hello, tmpfs!");
+
+    let e = (&fs).create_file("/hello.txt").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+}
+
+#[test]
+fn tmpfs_nested_dirs() {
+    use tmpfs::TmpFs;
+
+    let fs = TmpFs::new(1 << 20, tmpfs_clock);
+    let e = (&fs).create_dir("/a/b/c", false).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+
+    (&fs).create_dir("/a/b/c", true).expect("create dirs");
+    (&fs).create_file("/a/b/c/file").expect("create nested file");
+    (&fs).create_file("/a/b/other").expect("create file");
+
+    let names: Vec<String> = (&fs).open_dir("/a/b").unwrap().entries().unwrap()
+        .map(|e| e.name().to_string())
+        .collect();
+    assert_eq!(names, vec!["c", "other"]);
+
+    assert!((&fs).open("/a/b/c").unwrap().is_dir());
+    assert!((&fs).open("/a/b/c/file").unwrap().is_file());
+    assert_eq!((&fs).open("/a/b/missing").unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!((&fs).open("/a/x/missing").unwrap_err().kind(), io::ErrorKind::InvalidInput);
+}
+
+#[test]
+fn tmpfs_rename_remove() {
+    use tmpfs::TmpFs;
+
+    let fs = TmpFs::new(1 << 20, tmpfs_clock);
+    (&fs).create_dir("/dir/sub", true).unwrap();
+    (&fs).create_file("/dir/sub/file").unwrap().write_all(b"data").unwrap();
+
+    (&fs).rename("/dir/sub", "/moved").expect("rename");
+    assert_eq!((&fs).open("/dir/sub").unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!((&fs).open_file("/moved/file").unwrap().size(), 4);
+
+    let e = (&fs).rename("/moved", "/moved/inner").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+    let e = (&fs).rename("/moved", "/dir").unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::AlreadyExists);
+
+    let e = (&fs).remove("/moved", false).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    (&fs).remove("/moved", true).expect("remove recursively");
+    (&fs).remove("/dir", false).expect("remove empty dir");
+    assert_eq!((&fs).open_dir("/").unwrap().entries().unwrap().count(), 0);
+    assert_eq!(fs.used(), 0);
+}
+
+#[test]
+fn tmpfs_size_limit() {
+    use tmpfs::TmpFs;
+
+    let fs = TmpFs::new(4096, tmpfs_clock);
+    let mut file = (&fs).create_file("/big").unwrap();
+    let e = file.write_all(&[0xAF; 8192]).unwrap_err();
+    assert_eq!(e.kind(), io::ErrorKind::Other);
+    assert_eq!(file.size(), 0);
+    assert!(fs.used() <= fs.limit());
+
+    file.write_all(&[0xAF; 1024]).unwrap();
+    let used = fs.used();
+    (&fs).remove("/big", false).unwrap();
+    assert!(fs.used() < used);
+    assert_eq!(fs.used(), 0);
+}
+
+#[test]
+fn tmpfs_write_after_remove() {
+    use tmpfs::TmpFs;
+
+    let fs = TmpFs::new(4096, tmpfs_clock);
+    (&fs).create_dir("/dir", false).unwrap();
+    let mut file = (&fs).create_file("/dir/file").unwrap();
+    file.write_all(b"data").unwrap();
+    (&fs).remove("/dir", true).unwrap();
+    assert_eq!(fs.used(), 0);
+
+    // the open file is still readable, but writes must not be charged to
+    // the file system again as nothing would ever release them
+    let mut data = String::new();
+    file.seek(SeekFrom::Start(0)).unwrap();
+    file.read_to_string(&mut data).unwrap();
+    assert_eq!(data, "data");
+    assert_eq!(file.write(&[0xAF; 1024]).unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(file.set_len(1024).unwrap_err().kind(), io::ErrorKind::NotFound);
+    assert_eq!(fs.used(), 0);
+
+    let mut file = (&fs).create_file("/file").unwrap();
+    file.write_all(&[0xAF; 2048]).unwrap();
+}
+
+#[test]
+fn tmpfs_timestamps() {
+    use tmpfs::TmpFs;
+
+    let fs = TmpFs::new(1 << 20, tmpfs_clock);
+    (&fs).create_file("/file").unwrap();
+    let entry = (&fs).open("/file").unwrap();
+    let created = entry.metadata().created();
+    assert_eq!((created.year(), created.month(), created.day()), (2018, 5, 24));
+    assert_eq!((created.hour(), created.minute(), created.second()), (12, 34, 56));
+    assert!(!entry.metadata().read_only());
+}
@@ -0,0 +1,35 @@
+use std::io;
+use std::string::String;
+use std::vec::{self, Vec};
+
+use traits;
+use vfat::Shared;
+use tmpfs::{TmpFs, Node, Content, Entry, Metadata};
+
+#[derive(Debug)]
+pub struct Dir {
+    pub name: String,
+    pub meta: Metadata,
+    pub(crate) node: Shared<Node>,
+    pub(crate) fs: TmpFs,
+}
+
+impl traits::Dir for Dir {
+    type Entry = Entry;
+    type Iter = vec::IntoIter<Entry>;
+
+    fn entries(&self) -> io::Result<Self::Iter> {
+        let children: Vec<(String, Shared<Node>)> = {
+            let mut node = self.node.borrow_mut();
+            node.meta.accessed = self.fs.now();
+            match node.content {
+                Content::Dir(ref children) => children.clone(),
+                Content::File(_) => return Err(io::Error::new(io::ErrorKind::Other, "not a directory")),
+            }
+        };
+        let entries: Vec<Entry> = children.into_iter()
+            .map(|(name, node)| Entry::new(&self.fs, name, node))
+            .collect();
+        Ok(entries.into_iter())
+    }
+}
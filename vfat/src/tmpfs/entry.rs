@@ -0,0 +1,70 @@
+use std::string::String;
+
+use traits;
+use vfat::Shared;
+use tmpfs::{TmpFs, Node, File, Dir, Metadata};
+
+#[derive(Debug)]
+pub enum Entry {
+    File(File),
+    Dir(Dir)
+}
+
+impl Entry {
+    /// Returns the entry for `node` named `name` in `fs`.
+    pub(crate) fn new(fs: &TmpFs, name: String, node: Shared<Node>) -> Self {
+        let (is_dir, meta) = {
+            let n = node.borrow();
+            (n.is_dir(), n.meta.clone())
+        };
+        let fs = fs.clone();
+        if is_dir {
+            Entry::Dir(Dir { name, meta, node, fs })
+        } else {
+            Entry::File(File { name, meta, node, fs, position: 0 })
+        }
+    }
+}
+
+impl traits::Entry for Entry {
+    type File = File;
+    type Dir = Dir;
+    type Metadata = Metadata;
+
+    fn name(&self) -> &str {
+        match self {
+            &Entry::File(ref e) => &e.name,
+            &Entry::Dir(ref e) => &e.name,
+        }
+    }
+    fn metadata(&self) -> &Self::Metadata {
+        match self {
+            &Entry::File(ref e) => &e.meta,
+            &Entry::Dir(ref e) => &e.meta,
+        }
+    }
+    fn as_file(&self) -> Option<&Self::File> {
+        match self {
+            &Entry::File(ref e) => Some(e),
+            _ => None,
+        }
+    }
+    fn as_dir(&self) -> Option<&Self::Dir> {
+        match self {
+            &Entry::Dir(ref e) => Some(e),
+            _ => None,
+        }
+    }
+    fn into_file(self) -> Option<Self::File> {
+        match self {
+            Entry::File(e) => Some(e),
+            _ => None,
+        }
+    }
+    fn into_dir(self) -> Option<Self::Dir> {
+        match self {
+            Entry::Dir(e) => Some(e),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::cmp::min;
+use std::io::{self, SeekFrom};
+use std::string::String;
+
+use traits;
+use vfat::Shared;
+use tmpfs::{TmpFs, Node, Content, Metadata};
+
+#[derive(Debug)]
+pub struct File {
+    pub name: String,
+    pub meta: Metadata,
+    pub(crate) node: Shared<Node>,
+    pub(crate) fs: TmpFs,
+
+    pub position: u64,
+}
+
+impl io::Seek for File {
+    /// Seek to offset `pos` in the file.
+    ///
+    /// Seeking beyond the end of the file is allowed; a subsequent write fills
+    /// the gap with zeroes.
+    ///
+    /// # Errors
+    ///
+    /// Seeking before the start of a file results in an `InvalidInput` error.
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(pos) => (pos, 0),
+            SeekFrom::End(pos) => (traits::File::size(self), pos),
+            SeekFrom::Current(pos) => (self.position, pos),
+        };
+        let pos = if offset >= 0 {
+            base.checked_add(offset as u64)
+        } else {
+            base.checked_sub(offset.wrapping_neg() as u64)
+        };
+        match pos {
+            Some(pos) => {
+                self.position = pos;
+                Ok(pos)
+            }
+            None => Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid seek")),
+        }
+    }
+}
+
+impl io::Read for File {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let now = self.fs.now();
+        let mut node = self.node.borrow_mut();
+        let n = match node.content {
+            Content::File(ref data) => {
+                let start = min(self.position, data.len() as u64) as usize;
+                let n = min(buf.len(), data.len() - start);
+                buf[..n].copy_from_slice(&data[start..start + n]);
+                n
+            }
+            Content::Dir(_) => return Err(io::Error::new(io::ErrorKind::Other, "is a directory")),
+        };
+        node.meta.accessed = now;
+        self.position += n as u64;
+        Ok(n)
+    }
+}
+
+fn removed() -> io::Error {
+    io::Error::new(io::ErrorKind::NotFound, "file was removed")
+}
+
+impl io::Write for File {
+    /// Writes `buf` at the current position, extending the file if needed.
+    ///
+    /// # Errors
+    ///
+    /// Writing to a file that was removed results in a `NotFound` error.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.node.borrow().unlinked {
+            return Err(removed());
+        }
+        let now = self.fs.now();
+        let start = self.position as usize;
+        let end = start.checked_add(buf.len())
+            .ok_or(io::Error::new(io::ErrorKind::InvalidInput, "file too large"))?;
+
+        let grow = end.saturating_sub(traits::File::size(self) as usize);
+        self.fs.charge(grow)?;
+
+        let mut node = self.node.borrow_mut();
+        match node.content {
+            Content::File(ref mut data) => {
+                if data.len() < end {
+                    if data.try_reserve(end - data.len()).is_err() {
+                        self.fs.release(grow);
+                        return Err(io::Error::new(io::ErrorKind::Other, "out of memory"));
+                    }
+                    data.resize(end, 0);
+                }
+                data[start..end].copy_from_slice(buf);
+            }
+            Content::Dir(_) => {
+                self.fs.release(grow);
+                return Err(io::Error::new(io::ErrorKind::Other, "is a directory"));
+            }
+        }
+        node.meta.modified = now;
+        self.position = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl File {
+    /// Truncates or extends the file to `size` bytes.
+    ///
+    /// # Errors
+    ///
+    /// Resizing a file that was removed results in a `NotFound` error.
+    pub fn set_len(&mut self, size: u64) -> io::Result<()> {
+        if self.node.borrow().unlinked {
+            return Err(removed());
+        }
+        let size = size as usize;
+        let len = traits::File::size(self) as usize;
+        if size > len {
+            self.fs.charge(size - len)?;
+        }
+
+        let mut node = self.node.borrow_mut();
+        if let Content::File(ref mut data) = node.content {
+            if size > len && data.try_reserve(size - len).is_err() {
+                self.fs.release(size - len);
+                return Err(io::Error::new(io::ErrorKind::Other, "out of memory"));
+            }
+            data.resize(size, 0);
+            data.shrink_to_fit();
+        }
+        if size < len {
+            self.fs.release(len - size);
+        }
+        node.meta.modified = self.fs.now();
+        Ok(())
+    }
+}
+
+impl traits::File for File {
+    fn sync(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.node.borrow().size()
+    }
+}
@@ -0,0 +1,96 @@
+use traits;
+
+/// A point in time as seconds since the Unix epoch (1970-01-01 00:00:00).
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Timestamp(pub u64);
+
+impl Timestamp {
+    /// Returns the (year, month, day) of the timestamp in the proleptic
+    /// Gregorian calendar.
+    fn date(&self) -> (usize, u8, u8) {
+        // shift the epoch to 0000-03-01 so leap days fall at the end of a year
+        let z = self.0 / 86400 + 719_468;
+        let era = z / 146_097;
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+        (year as usize, month as u8, day as u8)
+    }
+}
+
+impl traits::Timestamp for Timestamp {
+    fn year(&self) -> usize {
+        self.date().0
+    }
+
+    fn month(&self) -> u8 {
+        self.date().1
+    }
+
+    fn day(&self) -> u8 {
+        self.date().2
+    }
+
+    fn hour(&self) -> u8 {
+        ((self.0 % 86400) / 3600) as u8
+    }
+
+    fn minute(&self) -> u8 {
+        ((self.0 % 3600) / 60) as u8
+    }
+
+    fn second(&self) -> u8 {
+        (self.0 % 60) as u8
+    }
+}
+
+/// Metadata for a tmpfs node.
+#[derive(Default, Debug, Clone)]
+pub struct Metadata {
+    pub read_only: bool,
+    pub hidden: bool,
+    pub created: Timestamp,
+    pub accessed: Timestamp,
+    pub modified: Timestamp,
+}
+
+impl Metadata {
+    /// Returns the metadata of a node created at `now`.
+    pub fn new(now: Timestamp) -> Self {
+        Metadata {
+            read_only: false,
+            hidden: false,
+            created: now,
+            accessed: now,
+            modified: now,
+        }
+    }
+}
+
+impl traits::Metadata for Metadata {
+    type Timestamp = Timestamp;
+
+    fn read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn hidden(&self) -> bool {
+        self.hidden
+    }
+
+    fn created(&self) -> Self::Timestamp {
+        self.created
+    }
+
+    fn accessed(&self) -> Self::Timestamp {
+        self.accessed
+    }
+
+    fn modified(&self) -> Self::Timestamp {
+        self.modified
+    }
+}
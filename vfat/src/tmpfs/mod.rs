@@ -0,0 +1,310 @@
+//! An in-memory file system.
+//!
+//! Every byte stored in a `TmpFs`, including a fixed per-node overhead, is
+//! charged against a limit chosen at creation, so a tmpfs cannot exhaust the
+//! heap it lives in.
+
+pub(crate) mod node;
+pub(crate) mod metadata;
+pub(crate) mod file;
+pub(crate) mod dir;
+pub(crate) mod entry;
+
+use std::io;
+use std::path::{Path, Component};
+use std::string::String;
+use std::vec::Vec;
+
+use traits::FileSystem;
+use vfat::Shared;
+
+pub use self::metadata::{Metadata, Timestamp};
+pub use self::file::File;
+pub use self::dir::Dir;
+pub use self::entry::Entry;
+
+pub(crate) use self::node::{Node, Content};
+
+#[derive(Debug)]
+struct Inner {
+    root: Shared<Node>,
+    used: usize,
+    limit: usize,
+    clock: fn() -> u64,
+}
+
+/// A handle to an in-memory file system. Clones refer to the same file
+/// system.
+#[derive(Debug, Clone)]
+pub struct TmpFs(Shared<Inner>);
+
+impl TmpFs {
+    /// Creates an empty file system holding at most `limit` bytes.
+    ///
+    /// `clock` returns the current time in seconds since the Unix epoch and
+    /// is used for the timestamps of nodes.
+    pub fn new(limit: usize, clock: fn() -> u64) -> TmpFs {
+        let now = Timestamp(clock());
+        let root = Shared::new(Node {
+            content: Content::Dir(Vec::new()),
+            meta: Metadata::new(now),
+            unlinked: false,
+        });
+        TmpFs(Shared::new(Inner { root, used: 0, limit, clock }))
+    }
+
+    /// The number of bytes in use.
+    pub fn used(&self) -> usize {
+        self.0.borrow().used
+    }
+
+    /// The maximum number of bytes this file system may hold.
+    pub fn limit(&self) -> usize {
+        self.0.borrow().limit
+    }
+
+    pub(crate) fn now(&self) -> Timestamp {
+        let clock = self.0.borrow().clock;
+        Timestamp(clock())
+    }
+
+    /// Accounts `bytes` more bytes as used.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error of kind `Other` if the limit would be exceeded.
+    pub(crate) fn charge(&self, bytes: usize) -> io::Result<()> {
+        let mut inner = self.0.borrow_mut();
+        match inner.used.checked_add(bytes) {
+            Some(used) if used <= inner.limit => {
+                inner.used = used;
+                Ok(())
+            }
+            _ => Err(io::Error::new(io::ErrorKind::Other, "no space left on device")),
+        }
+    }
+
+    /// Accounts `bytes` fewer bytes as used.
+    pub(crate) fn release(&self, bytes: usize) {
+        let mut inner = self.0.borrow_mut();
+        inner.used -= bytes;
+    }
+
+    fn root(&self) -> Shared<Node> {
+        self.0.borrow().root.clone()
+    }
+
+    /// Returns the node reached by following `names` from the root.
+    ///
+    /// A missing last component is reported as `NotFound`, any other missing
+    /// or non-directory component as `InvalidInput`.
+    fn walk(&self, names: &[&str]) -> io::Result<Shared<Node>> {
+        let mut node = self.root();
+        for (i, name) in names.iter().enumerate() {
+            let next = {
+                let n = node.borrow();
+                if !n.is_dir() {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a directory"));
+                }
+                n.find(name)
+            };
+            node = match next {
+                Some(next) => next,
+                None if i + 1 == names.len() => {
+                    return Err(io::Error::new(io::ErrorKind::NotFound, "no such file or directory"))
+                }
+                None => {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "no such directory"))
+                }
+            };
+        }
+        Ok(node)
+    }
+
+    /// Returns the directory that holds the last component of `names`.
+    fn parent(&self, names: &[&str]) -> io::Result<Shared<Node>> {
+        let parent = match names.len() {
+            0 => return Err(io::Error::new(io::ErrorKind::InvalidInput, "root directory")),
+            n => &names[..n - 1],
+        };
+        let node = self.walk(parent).map_err(|e| match e.kind() {
+            io::ErrorKind::NotFound => io::Error::new(io::ErrorKind::InvalidInput, "no such directory"),
+            _ => e,
+        })?;
+        if !node.borrow().is_dir() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a directory"));
+        }
+        Ok(node)
+    }
+
+    /// Creates a node named `name` in the directory `parent`.
+    fn insert(&self, parent: &Shared<Node>, name: &str, content: Content) -> io::Result<Shared<Node>> {
+        if parent.borrow().find(name).is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+        self.charge(node::overhead(name))?;
+
+        let now = self.now();
+        let node = Shared::new(Node { content, meta: Metadata::new(now), unlinked: false });
+        let mut p = parent.borrow_mut();
+        if let Content::Dir(ref mut children) = p.content {
+            if children.try_reserve(1).is_err() {
+                self.release(node::overhead(name));
+                return Err(io::Error::new(io::ErrorKind::Other, "out of memory"));
+            }
+            children.push((String::from(name), node.clone()));
+        }
+        p.meta.modified = now;
+        Ok(node)
+    }
+
+    /// Unlinks and returns the node named `name` from the directory `parent`.
+    fn detach(&self, parent: &Shared<Node>, name: &str) -> Option<Shared<Node>> {
+        let now = self.now();
+        let mut p = parent.borrow_mut();
+        let node = match p.content {
+            Content::Dir(ref mut children) => {
+                let index = children.iter().position(|&(ref n, _)| n == name)?;
+                children.remove(index).1
+            }
+            Content::File(_) => return None,
+        };
+        p.meta.modified = now;
+        Some(node)
+    }
+}
+
+/// Splits `path` into its normal components.
+fn names<P: AsRef<Path>>(path: &P) -> io::Result<Vec<&str>> {
+    let mut names = Vec::new();
+    for c in path.as_ref().components() {
+        match c {
+            Component::Normal(name) => match name.to_str() {
+                Some(name) => names.push(name),
+                None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "invalid utf-8")),
+            },
+            _ => (),
+        }
+    }
+    Ok(names)
+}
+
+impl<'a> FileSystem for &'a TmpFs {
+    type File = File;
+    type Dir = Dir;
+    type Entry = Entry;
+
+    fn open<P: AsRef<Path>>(self, path: P) -> io::Result<Self::Entry> {
+        let names = names(&path)?;
+        let node = self.walk(&names)?;
+        let name = String::from(names.last().cloned().unwrap_or(""));
+        Ok(Entry::new(self, name, node))
+    }
+
+    fn create_file<P: AsRef<Path>>(self, path: P) -> io::Result<Self::File> {
+        let names = names(&path)?;
+        let parent = self.parent(&names)?;
+        let name = names[names.len() - 1];
+        let node = self.insert(&parent, name, Content::File(Vec::new()))?;
+        match Entry::new(self, String::from(name), node) {
+            Entry::File(file) => Ok(file),
+            Entry::Dir(_) => unreachable!("created a directory"),
+        }
+    }
+
+    fn create_dir<P: AsRef<Path>>(self, path: P, parents: bool) -> io::Result<Self::Dir> {
+        let names = names(&path)?;
+        if names.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "root directory"));
+        }
+
+        let parent = if parents {
+            let mut node = self.root();
+            for name in &names[..names.len() - 1] {
+                let existing = node.borrow().find(name);
+                node = match existing {
+                    Some(next) => {
+                        if !next.borrow().is_dir() {
+                            return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a directory"));
+                        }
+                        next
+                    }
+                    None => self.insert(&node, name, Content::Dir(Vec::new()))?,
+                };
+            }
+            node
+        } else {
+            self.parent(&names)?
+        };
+
+        let name = names[names.len() - 1];
+        let node = self.insert(&parent, name, Content::Dir(Vec::new()))?;
+        match Entry::new(self, String::from(name), node) {
+            Entry::Dir(dir) => Ok(dir),
+            Entry::File(_) => unreachable!("created a file"),
+        }
+    }
+
+    fn rename<P: AsRef<Path>, Q: AsRef<Path>>(self, from: P, to: Q) -> io::Result<()> {
+        let from = names(&from)?;
+        let to = names(&to)?;
+        if to.len() > from.len() && to[..from.len()] == from[..] {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "cannot move a directory into itself"));
+        }
+        if to == from {
+            return self.walk(&from).map(|_| ());
+        }
+
+        let source = self.parent(&from)?;
+        let target = self.parent(&to)?;
+        let (old, new) = (from[from.len() - 1], to[to.len() - 1]);
+        if source.borrow().find(old).is_none() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "no such file or directory"));
+        }
+        if target.borrow().find(new).is_some() {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "entry already exists"));
+        }
+
+        let delta = node::overhead(new) as isize - node::overhead(old) as isize;
+        if delta > 0 {
+            self.charge(delta as usize)?;
+        }
+
+        let node = self.detach(&source, old).expect("entry vanished");
+        let now = self.now();
+        let mut t = target.borrow_mut();
+        if let Content::Dir(ref mut children) = t.content {
+            children.push((String::from(new), node));
+        }
+        t.meta.modified = now;
+
+        if delta < 0 {
+            self.release((-delta) as usize);
+        }
+        Ok(())
+    }
+
+    fn remove<P: AsRef<Path>>(self, path: P, children: bool) -> io::Result<()> {
+        let names = names(&path)?;
+        let parent = self.parent(&names)?;
+        let name = names[names.len() - 1];
+
+        let node = parent.borrow().find(name)
+            .ok_or(io::Error::new(io::ErrorKind::NotFound, "no such file or directory"))?;
+        let non_empty = match node.borrow().content {
+            Content::Dir(ref entries) => !entries.is_empty(),
+            Content::File(_) => false,
+        };
+        if non_empty && !children {
+            return Err(io::Error::new(io::ErrorKind::Other, "directory not empty"));
+        }
+
+        // open files keep the node alive, but their writes would be charged
+        // to bytes that are released here: `unlink` makes them fail instead
+        let usage = node::usage(name, &node);
+        self.detach(&parent, name);
+        node::unlink(&node);
+        self.release(usage);
+        Ok(())
+    }
+}
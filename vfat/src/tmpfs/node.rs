@@ -0,0 +1,80 @@
+use std::mem::size_of;
+use std::string::String;
+use std::vec::Vec;
+
+use vfat::Shared;
+use tmpfs::Metadata;
+
+/// The content of a tmpfs node.
+#[derive(Debug)]
+pub enum Content {
+    File(Vec<u8>),
+    Dir(Vec<(String, Shared<Node>)>),
+}
+
+/// A file or a directory stored in memory.
+#[derive(Debug)]
+pub struct Node {
+    pub content: Content,
+    pub meta: Metadata,
+    /// Whether the node was removed from the file system. Its usage is no
+    /// longer accounted, so it can be read but not written anymore.
+    pub unlinked: bool,
+}
+
+impl Node {
+    pub fn is_dir(&self) -> bool {
+        match self.content {
+            Content::Dir(_) => true,
+            Content::File(_) => false,
+        }
+    }
+
+    /// The size of the file in bytes, `0` for directories.
+    pub fn size(&self) -> u64 {
+        match self.content {
+            Content::File(ref data) => data.len() as u64,
+            Content::Dir(_) => 0,
+        }
+    }
+
+    /// Returns the child named `name` if `self` is a directory containing it.
+    pub fn find(&self, name: &str) -> Option<Shared<Node>> {
+        match self.content {
+            Content::Dir(ref children) => children.iter()
+                .find(|&&(ref n, _)| n == name)
+                .map(|&(_, ref node)| node.clone()),
+            Content::File(_) => None,
+        }
+    }
+}
+
+/// The number of bytes accounted for a node named `name`, excluding the
+/// content of files.
+pub fn overhead(name: &str) -> usize {
+    size_of::<Node>() + size_of::<(String, Shared<Node>)>() + name.len()
+}
+
+/// Marks `node` and everything below it as unlinked.
+pub fn unlink(node: &Shared<Node>) {
+    let mut node = node.borrow_mut();
+    node.unlinked = true;
+    if let Content::Dir(ref children) = node.content {
+        for &(_, ref child) in children {
+            unlink(child);
+        }
+    }
+}
+
+/// The number of bytes accounted for `node` named `name` and everything below
+/// it.
+pub fn usage(name: &str, node: &Shared<Node>) -> usize {
+    let node = node.borrow();
+    let content = match node.content {
+        Content::File(ref data) => data.len(),
+        Content::Dir(ref children) => children.iter()
+            .map(|&(ref name, ref child)| usage(name, child))
+            .sum(),
+    };
+    overhead(name) + content
+}
@@ -11,19 +11,19 @@ pub struct Shared<T>(imp::Inner<T>);
 //#[cfg(target_os = "lainos")]
 #[cfg(not(test))]
 mod imp {
-    use std::rc::Rc;
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
     use super::Shared;
 
-    pub type Inner<T> = Rc<Mutex<T>>;
+    pub type Inner<T> = Arc<Mutex<T>>;
 
     pub fn new<T>(val: T) -> Inner<T> {
-        Rc::new(Mutex::new(val))
+        Arc::new(Mutex::new(val))
     }
 
-    // Without an enabled MMU/cache, the processor faults on atomic accesses.
-    // As such, use an `Rc` instead of an `Arc` when running on ROS until
-    // multithreading, the MMU, and caches are enabled.
+    // The cores clone and drop handles at the same time, so the count is
+    // atomic. The values themselves are only reached through the mutex; the
+    // ones that are not `Send`, such as devices boxed as trait objects or
+    // holding pointers to their registers, are not tied to a core either.
     unsafe impl<T> Sync for Shared<T> {}
     unsafe impl<T> Send for Shared<T> {}
 }
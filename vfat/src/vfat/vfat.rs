@@ -189,22 +189,22 @@ impl<'a> FileSystem for &'a Shared<VFat> {
     }
 
     fn create_file<P: AsRef<Path>>(self, _path: P) -> io::Result<Self::File> {
-        unimplemented!("read only file system")
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only file system"))
     }
 
     fn create_dir<P>(self, _path: P, _parents: bool) -> io::Result<Self::Dir>
         where P: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only file system"))
     }
 
     fn rename<P, Q>(self, _from: P, _to: Q) -> io::Result<()>
         where P: AsRef<Path>, Q: AsRef<Path>
     {
-        unimplemented!("read only file system")
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only file system"))
     }
 
     fn remove<P: AsRef<Path>>(self, _path: P, _children: bool) -> io::Result<()> {
-        unimplemented!("read only file system")
+        Err(io::Error::new(io::ErrorKind::PermissionDenied, "read only file system"))
     }
 }